@@ -0,0 +1,283 @@
+use super::{
+    common::*,
+    constraints::*,
+    poseidon::{PoseidonLeafH, PoseidonLeafHG},
+};
+use crate::groth16::PublicInputs;
+use ark_crypto_primitives::{
+    crh::{poseidon::constraints::CRHParametersVar, CRHScheme, CRHSchemeGadget, TwoToOneCRHScheme},
+    sponge::poseidon::PoseidonConfig,
+    Error,
+};
+use ark_ff::ToConstraintField;
+use ark_r1cs_std::{fields::fp::FpVar, prelude::*, uint8::UInt8, ToConstraintFieldGadget};
+use ark_relations::{
+    ns,
+    r1cs::{ConstraintSynthesizer, ConstraintSystemRef, Result},
+};
+
+/// The length of the external topic of a nullifier, such as the hash of a poll
+/// identifier.
+pub const TOPIC_LEN: usize = 32;
+
+/// A nullifier, which is the Poseidon hash of the secret leaf of a member and of an
+/// external topic. It is the same for every proof of the same member on the same topic,
+/// so that a member can act at most once per topic, and does not reveal the member.
+///
+/// Poseidon is used rather than the Pedersen leaf hash because Pedersen is linear in
+/// its input bits: the Pedersen nullifier of a leaf on a topic is the sum of a term of
+/// the leaf and a term of the topic, and anyone could subtract the latter to link every
+/// nullifier of a member.
+#[derive(Clone)]
+pub struct Nullifier {
+    pub topic: [u8; TOPIC_LEN],
+    pub value: ConstraintF,
+}
+
+impl Nullifier {
+    /// Compute the nullifier of the member with the secret leaf `leaf` on `topic`. The
+    /// leaf and the topic are each packed into field elements, 31 bytes to an element.
+    pub fn new(
+        crh_params: &PoseidonConfig<ConstraintF>,
+        leaf: &[u8],
+        topic: [u8; TOPIC_LEN],
+    ) -> core::result::Result<Self, Error> {
+        let mut input: Vec<ConstraintF> = leaf.to_field_elements().ok_or("unpackable leaf")?;
+        let topic_input: Vec<ConstraintF> =
+            topic[..].to_field_elements().ok_or("unpackable topic")?;
+        input.extend(topic_input);
+        let value = PoseidonLeafH::evaluate(crh_params, input)?;
+        Ok(Self { topic, value })
+    }
+}
+
+/// A variant of `MerkleTreeVerification` in which the leaf is a private witness, so
+/// that a proof does not reveal which member of the tree produced it. The public
+/// inputs are the root and, if `nullifier` is set, the nullifier and its topic. The
+/// nullifier is checked against the Poseidon hash configured by `nullifier_crh_params`.
+///
+/// The leaf is the secret of the member. The tree is built from the leaf digests
+/// alone, with `MerkleTree::new_with_leaf_digest`, so that members only publish the
+/// digests of their secrets.
+#[derive(Clone)]
+pub struct AnonymousMerkleTreeVerification {
+    pub leaf_crh_params: <LeafH as CRHScheme>::Parameters,
+    pub two_to_one_crh_params: <CompressH as TwoToOneCRHScheme>::Parameters,
+    pub nullifier_crh_params: PoseidonConfig<ConstraintF>,
+
+    // public input
+    pub root: Root,
+    pub nullifier: Option<Nullifier>,
+
+    // private witness
+    pub leaf: Vec<u8>,
+    pub authentication_path: Option<SimplePath>,
+}
+
+impl AnonymousMerkleTreeVerification {
+    /// Return the public inputs of the circuit, in the order in which they are
+    /// allocated: the coordinates of the root, then those of the nullifier followed by
+    /// its topic packed into field elements, if the nullifier is set.
+    pub fn public_inputs(&self) -> PublicInputs {
+        let mut inputs = PublicInputs::new();
+        inputs.push(&self.root);
+        if let Some(nullifier) = &self.nullifier {
+            inputs.push(&nullifier.value).push_bytes(&nullifier.topic);
+        }
+        inputs
+    }
+}
+
+impl ConstraintSynthesizer<ConstraintF> for AnonymousMerkleTreeVerification {
+    fn generate_constraints(self, cs: ConstraintSystemRef<ConstraintF>) -> Result<()> {
+        // allocate public input
+        let root = <RootVar as AllocVar<AllocVarOutput, ConstraintF>>::new_input(
+            ns!(cs, "root_var"),
+            || Ok(&self.root),
+        )?;
+        let nullifier = match &self.nullifier {
+            Some(nullifier) => {
+                let value = FpVar::new_input(ns!(cs, "nullifier_var"), || Ok(nullifier.value))?;
+                let topic = UInt8::new_input_vec(ns!(cs, "topic_var"), &nullifier.topic)?;
+                let crh_params = CRHParametersVar::new_constant(
+                    ns!(cs, "nullifier_crh_parameter"),
+                    &self.nullifier_crh_params,
+                )?;
+                Some((value, topic, crh_params))
+            }
+            None => None,
+        };
+
+        // allocate private witness
+        let leaf_g = UInt8::new_witness_vec(ns!(cs, "leaf_var"), &self.leaf)?;
+        let path = SimplePathVar::new_witness(ns!(cs, "path_var"), || {
+            self.authentication_path
+                .as_ref()
+                .ok_or(ark_relations::r1cs::SynthesisError::AssignmentMissing)
+        })?;
+
+        let leaf_crh_params_var =
+            LeafHashParamsVar::new_constant(ns!(cs, "leaf_crh_parameter"), &self.leaf_crh_params)?;
+        let two_to_one_crh_params_var = TwoToOneHashParamsVar::new_constant(
+            ns!(cs, "two_to_one_crh_parameter"),
+            &self.two_to_one_crh_params,
+        )?;
+
+        let is_member = path.verify_membership(
+            &leaf_crh_params_var,
+            &two_to_one_crh_params_var,
+            &root,
+            &leaf_g,
+        )?;
+        is_member.enforce_equal(&Boolean::TRUE)?;
+
+        // The nullifier is the hash of the same leaf and of the topic, packed as in
+        // `Nullifier::new`.
+        if let Some((value, topic, crh_params)) = nullifier {
+            let mut input = leaf_g.to_constraint_field()?;
+            input.extend(topic.to_constraint_field()?);
+            PoseidonLeafHG::evaluate(&crh_params, &input)?.enforce_equal(&value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AnonymousMerkleTreeVerification, Nullifier};
+    use crate::{
+        basic_merkle_tree::{common::*, poseidon::poseidon_parameters},
+        groth16::{prove, setup, verify},
+    };
+    use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+    use ark_ec::AffineRepr;
+    use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystem};
+    use ark_std::rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn anonymous_membership_with_nullifier() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let leaf_crh_params = <LeafH as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <CompressH as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+        let nullifier_crh_params = poseidon_parameters();
+
+        // The members publish the digests of their secrets, from which the allow-list
+        // is built.
+        let secrets: Vec<Vec<u8>> = (0..4u8).map(|i| vec![i; 30]).collect();
+        let digests = secrets
+            .iter()
+            .map(|secret| LeafH::evaluate(&leaf_crh_params, secret.as_slice()).unwrap())
+            .collect();
+        let tree = JubJubMerkleTree::new_with_leaf_digest(
+            &leaf_crh_params,
+            &two_to_one_crh_params,
+            digests,
+        )
+        .unwrap();
+
+        let topic = [7u8; 32];
+        let circuit = AnonymousMerkleTreeVerification {
+            leaf_crh_params: leaf_crh_params.clone(),
+            two_to_one_crh_params: two_to_one_crh_params.clone(),
+            nullifier_crh_params: nullifier_crh_params.clone(),
+            root: tree.root(),
+            nullifier: Some(Nullifier::new(&nullifier_crh_params, &secrets[2], topic).unwrap()),
+            leaf: secrets[2].clone(),
+            authentication_path: Some(tree.generate_proof(2).unwrap()),
+        };
+        // The public inputs are the root, the nullifier and the topic, but not the leaf.
+        // Bytes are packed 31 to a field element, so the topic takes two.
+        assert_eq!(circuit.public_inputs().len(), 2 + 1 + 2);
+
+        let (pk, vk) = setup(circuit.clone(), &mut rng).unwrap();
+        let proof = prove(&pk, circuit.clone(), &mut rng).unwrap();
+        assert!(verify(&vk, &circuit.public_inputs(), &proof).unwrap());
+
+        // The proof does not verify for another nullifier or another topic.
+        let other = Nullifier::new(&nullifier_crh_params, &secrets[1], topic).unwrap();
+        let inputs = AnonymousMerkleTreeVerification {
+            nullifier: Some(other),
+            ..circuit.clone()
+        }
+        .public_inputs();
+        assert!(!verify(&vk, &inputs, &proof).unwrap());
+        let other = Nullifier::new(&nullifier_crh_params, &secrets[2], [8u8; 32]).unwrap();
+        let inputs = AnonymousMerkleTreeVerification {
+            nullifier: Some(other),
+            ..circuit.clone()
+        }
+        .public_inputs();
+        assert!(!verify(&vk, &inputs, &proof).unwrap());
+
+        // A member cannot claim the nullifier of another member.
+        let cs = ConstraintSystem::new_ref();
+        AnonymousMerkleTreeVerification {
+            nullifier: Some(Nullifier::new(&nullifier_crh_params, &secrets[1], topic).unwrap()),
+            ..circuit
+        }
+        .generate_constraints(cs.clone())
+        .unwrap();
+        assert!(!cs.is_satisfied().unwrap());
+    }
+
+    #[test]
+    fn anonymous_membership_without_nullifier() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafH as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <CompressH as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+        let leaves: Vec<Vec<u8>> = (0..4u8).map(|i| vec![i; 30]).collect();
+        let tree = JubJubMerkleTree::new(
+            &leaf_crh_params,
+            &two_to_one_crh_params,
+            leaves.iter().map(|v| v.as_slice()),
+        )
+        .unwrap();
+
+        for (leaf, expected) in [(&leaves[1], true), (&leaves[2], false)] {
+            let circuit = AnonymousMerkleTreeVerification {
+                leaf_crh_params: leaf_crh_params.clone(),
+                two_to_one_crh_params: two_to_one_crh_params.clone(),
+                nullifier_crh_params: poseidon_parameters(),
+                root: tree.root(),
+                nullifier: None,
+                leaf: leaf.clone(),
+                authentication_path: Some(tree.generate_proof(1).unwrap()),
+            };
+            let cs = ConstraintSystem::new_ref();
+            circuit.generate_constraints(cs.clone()).unwrap();
+            assert_eq!(cs.is_satisfied().unwrap(), expected);
+            // Only the coordinates of the root are public.
+            assert_eq!(cs.num_instance_variables(), 1 + 2);
+        }
+    }
+
+    #[test]
+    fn nullifiers_of_a_member_are_unlinkable_across_topics() {
+        let mut rng = ark_std::test_rng();
+        let secret = vec![3u8; 30];
+        let zero = vec![0u8; 30];
+        let (topic_a, topic_b) = ([1u8; 32], [2u8; 32]);
+
+        // With the linear Pedersen hash, subtracting the hash of the topic alone, i.e.
+        // with an all-zero leaf, leaves the same term of the leaf for every topic.
+        let pedersen_params = <LeafH as CRHScheme>::setup(&mut rng).unwrap();
+        let pedersen_leaf_term = |topic: [u8; 32]| {
+            let nullifier = LeafH::evaluate(&pedersen_params, [&secret[..], &topic].concat());
+            let topic_term = LeafH::evaluate(&pedersen_params, [&zero[..], &topic].concat());
+            nullifier.unwrap().into_group() - topic_term.unwrap()
+        };
+        assert_eq!(pedersen_leaf_term(topic_a), pedersen_leaf_term(topic_b));
+
+        // The same attack does not link the Poseidon nullifiers.
+        let params = poseidon_parameters();
+        let leaf_term = |topic: [u8; 32]| {
+            let nullifier = Nullifier::new(&params, &secret, topic).unwrap();
+            nullifier.value - Nullifier::new(&params, &zero, topic).unwrap().value
+        };
+        assert_ne!(leaf_term(topic_a), leaf_term(topic_b));
+        assert_ne!(
+            Nullifier::new(&params, &secret, topic_a).unwrap().value,
+            Nullifier::new(&params, &secret, topic_b).unwrap().value
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use super::common::*;
+use crate::groth16::PublicInputs;
 use ark_crypto_primitives::{
     crh::{pedersen, CRHScheme, CRHSchemeGadget, TwoToOneCRHScheme, TwoToOneCRHSchemeGadget},
     merkle_tree::constraints::PathVar,
@@ -48,6 +49,16 @@ pub struct MerkleTreeVerification {
     pub authentication_path: Option<SimplePath>,
 }
 
+impl MerkleTreeVerification {
+    /// Return the public inputs of the circuit: the coordinates of the root, then the
+    /// bytes of the leaf.
+    pub fn public_inputs(&self) -> PublicInputs {
+        let mut inputs = PublicInputs::new();
+        inputs.push(&self.root).push_bytes(&self.leaf);
+        inputs
+    }
+}
+
 impl ConstraintSynthesizer<ConstraintF> for MerkleTreeVerification {
     fn generate_constraints(self, cs: ConstraintSystemRef<ConstraintF>) -> Result<()> {
         // allocate public input
@@ -0,0 +1,311 @@
+//! A compact and stable byte encoding of Merkle paths, such as `SimplePath` and
+//! `AccPath`, for verifiers that are not written with arkworks.
+//!
+//! # Path encoding
+//!
+//! `CompactPath::serialize_compressed` writes, with integers in little-endian:
+//!
+//! | field               | size                           |
+//! |---------------------|--------------------------------|
+//! | `leaf_index`        | 8 bytes, `u64`                 |
+//! | `depth`             | 1 byte, the number of siblings |
+//! | `leaf_sibling_hash` | one leaf digest                |
+//! | `auth_path`         | `depth - 1` inner digests      |
+//!
+//! The position of each node on the path is given by the bits of `leaf_index`, from
+//! the least significant bit at the leaves: a node whose bit is 0 is a left child.
+//! `auth_path` is ordered from the level below the root down to the level above the
+//! leaves, as in `Path`. Digests use the compressed arkworks encoding; for
+//! `JubJubMerkleTreeParams`, both digests are JubJub points, encoded as the 32
+//! little-endian bytes of `y`, whose most significant bit is set if `x` is the larger
+//! of `x` and `-x`.
+//!
+//! # Hashes
+//!
+//! For `JubJubMerkleTreeParams`, a leaf digest is the Pedersen hash of the leaf, and
+//! an inner digest is the Pedersen hash of the uncompressed encodings of its two
+//! children, which are `x` then `y` in 32 little-endian bytes each. The Pedersen hash
+//! of at most 128 bytes pads them with zeros to 128 bytes, and sums the generators of
+//! `encode_pedersen_parameters` whose index is that of a set bit, with the bits of each
+//! byte taken from the least significant one.
+//!
+//! The test vectors in `test_vectors/basic_merkle_tree.json` pin down this encoding
+//! together with the hashes of `JubJubMerkleTreeParams`.
+use ark_crypto_primitives::{
+    crh::pedersen,
+    merkle_tree::{Config, Path},
+};
+use ark_ec::CurveGroup;
+use ark_serialize::{
+    CanonicalDeserialize, CanonicalSerialize, Compress, Read, SerializationError, Valid, Validate,
+    Write,
+};
+use blake2::{Blake2s256, Digest};
+
+/// A Merkle path with the compact encoding of the module documentation. It stores the
+/// leaf index once rather than the position of every level.
+pub struct CompactPath<P: Config>(pub Path<P>);
+
+impl<P: Config> Clone for CompactPath<P> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<P: Config> From<Path<P>> for CompactPath<P> {
+    fn from(path: Path<P>) -> Self {
+        Self(path)
+    }
+}
+
+impl<P: Config> CompactPath<P> {
+    /// The largest number of siblings, for which every `u64` is a leaf index.
+    pub const MAX_DEPTH: usize = 64;
+
+    pub fn into_path(self) -> Path<P> {
+        self.0
+    }
+
+    /// Return the number of siblings, which is the number of levels above the leaves.
+    pub fn depth(&self) -> usize {
+        self.0.auth_path.len() + 1
+    }
+
+    /// Check that the depth is supported and that the leaf index fits in it.
+    fn check_shape(&self) -> Result<(), SerializationError> {
+        let depth = self.depth();
+        let index = self.0.leaf_index as u64;
+        if depth > Self::MAX_DEPTH || (depth < 64 && index >> depth != 0) {
+            return Err(SerializationError::InvalidData);
+        }
+        Ok(())
+    }
+}
+
+impl<P: Config> Valid for CompactPath<P> {
+    fn check(&self) -> Result<(), SerializationError> {
+        self.check_shape()?;
+        self.0.leaf_sibling_hash.check()?;
+        P::InnerDigest::batch_check(self.0.auth_path.iter())
+    }
+}
+
+impl<P: Config> CanonicalSerialize for CompactPath<P> {
+    fn serialize_with_mode<W: Write>(
+        &self,
+        mut writer: W,
+        compress: Compress,
+    ) -> Result<(), SerializationError> {
+        self.check_shape()?;
+        (self.0.leaf_index as u64).serialize_with_mode(&mut writer, compress)?;
+        (self.depth() as u8).serialize_with_mode(&mut writer, compress)?;
+        self.0
+            .leaf_sibling_hash
+            .serialize_with_mode(&mut writer, compress)?;
+        for node in &self.0.auth_path {
+            node.serialize_with_mode(&mut writer, compress)?;
+        }
+        Ok(())
+    }
+
+    fn serialized_size(&self, compress: Compress) -> usize {
+        8 + 1
+            + self.0.leaf_sibling_hash.serialized_size(compress)
+            + self
+                .0
+                .auth_path
+                .iter()
+                .map(|node| node.serialized_size(compress))
+                .sum::<usize>()
+    }
+}
+
+impl<P: Config> CanonicalDeserialize for CompactPath<P> {
+    fn deserialize_with_mode<R: Read>(
+        mut reader: R,
+        compress: Compress,
+        validate: Validate,
+    ) -> Result<Self, SerializationError> {
+        let leaf_index = u64::deserialize_with_mode(&mut reader, compress, validate)?;
+        let depth = u8::deserialize_with_mode(&mut reader, compress, validate)? as usize;
+        if depth == 0 || depth > Self::MAX_DEPTH {
+            return Err(SerializationError::InvalidData);
+        }
+        let leaf_sibling_hash =
+            P::LeafDigest::deserialize_with_mode(&mut reader, compress, validate)?;
+        let auth_path = (1..depth)
+            .map(|_| P::InnerDigest::deserialize_with_mode(&mut reader, compress, validate))
+            .collect::<Result<_, _>>()?;
+        let path = Self(Path {
+            leaf_sibling_hash,
+            auth_path,
+            leaf_index: usize::try_from(leaf_index).map_err(|_| SerializationError::InvalidData)?,
+        });
+        path.check_shape()?;
+        Ok(path)
+    }
+}
+
+/// Encode Pedersen parameters as their generators, window by window, each as a
+/// compressed point.
+pub fn encode_pedersen_parameters<C: CurveGroup>(params: &pedersen::Parameters<C>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for window in &params.generators {
+        for generator in C::normalize_batch(window) {
+            generator
+                .serialize_compressed(&mut bytes)
+                .expect("serialization into a vector should not fail");
+        }
+    }
+    bytes
+}
+
+/// Return the Blake2s-256 hash of `encode_pedersen_parameters(params)`, with which
+/// verifiers can check that they use the same parameters as the prover.
+pub fn pedersen_parameters_fingerprint<C: CurveGroup>(
+    params: &pedersen::Parameters<C>,
+) -> [u8; 32] {
+    Blake2s256::digest(encode_pedersen_parameters(params)).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode_pedersen_parameters, pedersen_parameters_fingerprint, CompactPath};
+    use crate::basic_merkle_tree::common::*;
+    use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+    use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+    use ark_std::rand::{rngs::StdRng, SeedableRng};
+    use serde_json::{json, Value};
+
+    /// The test vectors are regenerated by running this module's tests with this
+    /// environment variable set.
+    const UPDATE_VARIABLE: &str = "UPDATE_TEST_VECTORS";
+    const TEST_VECTORS: &str = "test_vectors/basic_merkle_tree.json";
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    fn to_bytes<T: CanonicalSerialize>(value: &T) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        value.serialize_compressed(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn compact_path_round_trip() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafH as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <CompressH as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+        let leaves: Vec<Vec<u8>> = (0..8u8).map(|i| vec![i; 30]).collect();
+        let tree = JubJubMerkleTree::new(
+            &leaf_crh_params,
+            &two_to_one_crh_params,
+            leaves.iter().map(|v| v.as_slice()),
+        )
+        .unwrap();
+
+        for (index, leaf) in leaves.iter().enumerate() {
+            let path = CompactPath::from(tree.generate_proof(index).unwrap());
+            let bytes = to_bytes(&path);
+            // The index, the depth, and three points of 32 bytes.
+            assert_eq!(bytes.len(), 8 + 1 + 3 * 32);
+            assert_eq!(bytes.len(), path.compressed_size());
+            assert_eq!(bytes[..9], [index as u8, 0, 0, 0, 0, 0, 0, 0, 3]);
+            let decoded =
+                CompactPath::<JubJubMerkleTreeParams>::deserialize_compressed(bytes.as_slice())
+                    .unwrap()
+                    .into_path();
+            assert_eq!(decoded.leaf_index, index);
+            assert!(decoded
+                .verify(
+                    &leaf_crh_params,
+                    &two_to_one_crh_params,
+                    &tree.root(),
+                    leaf.as_slice()
+                )
+                .unwrap());
+        }
+
+        // A leaf index that does not fit in the depth is rejected, as is a truncation.
+        let mut bytes = to_bytes(&CompactPath::from(tree.generate_proof(5).unwrap()));
+        bytes[0] = 8;
+        assert!(
+            CompactPath::<JubJubMerkleTreeParams>::deserialize_compressed(bytes.as_slice())
+                .is_err()
+        );
+        bytes[0] = 5;
+        assert!(
+            CompactPath::<JubJubMerkleTreeParams>::deserialize_compressed(
+                &bytes[..bytes.len() - 1]
+            )
+            .is_err()
+        );
+    }
+
+    /// Generate the test vectors: the parameters sampled from a fixed seed, and two
+    /// trees with their roots and the encoded path of every leaf.
+    fn test_vectors() -> Value {
+        let mut rng = StdRng::seed_from_u64(0);
+        let leaf_crh_params = <LeafH as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <CompressH as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+        let trees: Vec<Value> = [
+            (0..4u8).map(|i| vec![i; 30]).collect::<Vec<_>>(),
+            (0..8u8).map(|i| (0..8 * i).collect()).collect(),
+        ]
+        .iter()
+        .map(|leaves| {
+            let tree = JubJubMerkleTree::new(
+                &leaf_crh_params,
+                &two_to_one_crh_params,
+                leaves.iter().map(|v| v.as_slice()),
+            )
+            .unwrap();
+            let paths: Vec<Value> = (0..leaves.len())
+                .map(|index| {
+                    let path = CompactPath::from(tree.generate_proof(index).unwrap());
+                    json!({ "leaf_index": index, "path": hex(&to_bytes(&path)) })
+                })
+                .collect();
+            json!({
+                "leaves": leaves.iter().map(|leaf| hex(leaf)).collect::<Vec<_>>(),
+                "root": hex(&to_bytes(&tree.root())),
+                "paths": paths,
+            })
+        })
+        .collect();
+        let parameters = |params| {
+            json!({
+                "fingerprint": hex(&pedersen_parameters_fingerprint(params)),
+                "generators": hex(&encode_pedersen_parameters(params)),
+            })
+        };
+        json!({
+            "description": "Merkle trees of `basic_merkle_tree::common::JubJubMerkleTreeParams`. \
+                Paths use the encoding of `basic_merkle_tree::encoding`, and every byte \
+                string is hex encoded.",
+            "parameters": {
+                "leaf_crh": parameters(&leaf_crh_params),
+                "two_to_one_crh": parameters(&two_to_one_crh_params),
+            },
+            "trees": trees,
+        })
+    }
+
+    #[test]
+    fn test_vectors_are_up_to_date() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(TEST_VECTORS);
+        let vectors = test_vectors();
+        if std::env::var_os(UPDATE_VARIABLE).is_some() {
+            let json = serde_json::to_string_pretty(&vectors).unwrap();
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, json + "\n").unwrap();
+        }
+        let expected: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap())
+            .unwrap_or_else(|_| panic!("{TEST_VECTORS} should be valid JSON"));
+        assert!(
+            expected == vectors,
+            "{TEST_VECTORS} is out of date; set {UPDATE_VARIABLE} to regenerate it"
+        );
+    }
+}
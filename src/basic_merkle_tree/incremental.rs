@@ -0,0 +1,276 @@
+use ark_crypto_primitives::{
+    crh::{CRHScheme, TwoToOneCRHScheme},
+    merkle_tree::{Config, DigestConverter, LeafParam, Path, TwoToOneParam},
+    Error,
+};
+use std::collections::BTreeMap;
+
+/// An append-only Merkle tree that stores only its right-most frontier and its root,
+/// rather than every leaf. Leaves that are not appended yet are empty, with the
+/// default leaf digest, so the tree has the same root and paths as the `MerkleTree`
+/// of the same height built by `MerkleTree::blank` and updated with the appended
+/// leaves.
+///
+/// The paths of marked leaves are kept up to date as later leaves are appended.
+pub struct IncrementalMerkleTree<P: Config> {
+    height: usize,
+    leaf_hash_params: LeafParam<P>,
+    two_to_one_hash_params: TwoToOneParam<P>,
+    /// The number of appended leaves, which is the index of the next leaf.
+    size: usize,
+    /// The digest of the last appended leaf that is a left child.
+    leaf_frontier: P::LeafDigest,
+    /// `frontier[level - 1]` is the last node at `level` that is a left child, where
+    /// the leaves are at level 0. Once the next leaf is in the subtree of its right
+    /// sibling, it is complete.
+    frontier: Vec<P::InnerDigest>,
+    /// `empty_nodes[level - 1]` is the node at `level` above empty leaves only.
+    empty_nodes: Vec<P::InnerDigest>,
+    root: P::InnerDigest,
+    /// The paths of the marked leaves, by leaf index.
+    witnesses: BTreeMap<usize, Witness<P>>,
+}
+
+/// The siblings on the path of a marked leaf. Siblings to the right of the path change
+/// as leaves are appended.
+struct Witness<P: Config> {
+    leaf_sibling_hash: P::LeafDigest,
+    /// `siblings[level - 1]` is the sibling at `level` of the node on the path.
+    siblings: Vec<P::InnerDigest>,
+}
+
+impl<P: Config> IncrementalMerkleTree<P> {
+    /// Create an empty tree with `2^(height - 1)` leaves, as in `MerkleTree::blank`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `height` is less than 2.
+    pub fn blank(
+        leaf_hash_params: &LeafParam<P>,
+        two_to_one_hash_params: &TwoToOneParam<P>,
+        height: usize,
+    ) -> Result<Self, Error> {
+        assert!(height > 1, "a Merkle tree has at least two leaves");
+        let empty_leaf = P::LeafDigest::default();
+        let mut empty_nodes = vec![P::TwoToOneHash::evaluate(
+            two_to_one_hash_params,
+            P::LeafInnerDigestConverter::convert(empty_leaf.clone())?,
+            P::LeafInnerDigestConverter::convert(empty_leaf.clone())?,
+        )?];
+        for level in 1..height - 1 {
+            let child = &empty_nodes[level - 1];
+            let node = P::TwoToOneHash::compress(two_to_one_hash_params, child, child)?;
+            empty_nodes.push(node);
+        }
+        let root = empty_nodes.pop().expect("the root is above the leaves");
+        Ok(Self {
+            height,
+            leaf_hash_params: leaf_hash_params.clone(),
+            two_to_one_hash_params: two_to_one_hash_params.clone(),
+            size: 0,
+            leaf_frontier: empty_leaf,
+            frontier: empty_nodes.clone(),
+            empty_nodes,
+            root,
+            witnesses: BTreeMap::new(),
+        })
+    }
+
+    /// Return the height of the tree, including the leaves and the root.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Return the number of appended leaves.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn root(&self) -> P::InnerDigest {
+        self.root.clone()
+    }
+
+    /// Append `leaf` after the last appended leaf, and return its index.
+    pub fn append(&mut self, leaf: &P::Leaf) -> Result<usize, Error> {
+        let index = self.size;
+        if index >= 1 << (self.height - 1) {
+            return Err("the Merkle tree is full".into());
+        }
+        let digest = P::LeafHash::evaluate(&self.leaf_hash_params, leaf)?;
+
+        // Hash the path of the new leaf, padded with empty nodes on the right.
+        // `ancestors[level - 1]` is the new node at `level` on the path.
+        let (left, right) = if index & 1 == 0 {
+            self.leaf_frontier = digest.clone();
+            (digest.clone(), P::LeafDigest::default())
+        } else {
+            (self.leaf_frontier.clone(), digest.clone())
+        };
+        let mut node = P::TwoToOneHash::evaluate(
+            &self.two_to_one_hash_params,
+            P::LeafInnerDigestConverter::convert(left)?,
+            P::LeafInnerDigestConverter::convert(right)?,
+        )?;
+        let mut ancestors = Vec::with_capacity(self.height - 2);
+        for level in 1..self.height - 1 {
+            ancestors.push(node.clone());
+            let (left, right) = if (index >> level) & 1 == 0 {
+                self.frontier[level - 1] = node.clone();
+                (node, self.empty_nodes[level - 1].clone())
+            } else {
+                (self.frontier[level - 1].clone(), node)
+            };
+            node = P::TwoToOneHash::compress(&self.two_to_one_hash_params, left, right)?;
+        }
+        self.root = node;
+
+        // The new leaf is below the right sibling of the path of each marked leaf, at
+        // the highest level where their indices differ.
+        for (&marked, witness) in &mut self.witnesses {
+            match (marked ^ index).ilog2() as usize {
+                0 => witness.leaf_sibling_hash = digest.clone(),
+                level => witness.siblings[level - 1] = ancestors[level - 1].clone(),
+            }
+        }
+        self.size += 1;
+        Ok(index)
+    }
+
+    /// Start keeping the path of the last appended leaf up to date, and return its
+    /// index, or `None` if the tree is empty.
+    pub fn mark(&mut self) -> Option<usize> {
+        let index = self.size.checked_sub(1)?;
+        let leaf_sibling_hash = if index & 1 == 1 {
+            self.leaf_frontier.clone()
+        } else {
+            P::LeafDigest::default()
+        };
+        let siblings = (1..self.height - 1)
+            .map(|level| {
+                if (index >> level) & 1 == 1 {
+                    self.frontier[level - 1].clone()
+                } else {
+                    self.empty_nodes[level - 1].clone()
+                }
+            })
+            .collect();
+        self.witnesses.insert(
+            index,
+            Witness {
+                leaf_sibling_hash,
+                siblings,
+            },
+        );
+        Some(index)
+    }
+
+    /// Stop keeping the path of the leaf at `index` up to date. Return whether it was
+    /// marked.
+    pub fn unmark(&mut self, index: usize) -> bool {
+        self.witnesses.remove(&index).is_some()
+    }
+
+    /// Return the indices of the marked leaves, in increasing order.
+    pub fn marked_leaves(&self) -> impl Iterator<Item = usize> + '_ {
+        self.witnesses.keys().copied()
+    }
+
+    /// Return the path of the leaf at `index` to the current root, if it is marked.
+    pub fn witness(&self, index: usize) -> Option<Path<P>> {
+        let witness = self.witnesses.get(&index)?;
+        Some(Path {
+            leaf_sibling_hash: witness.leaf_sibling_hash.clone(),
+            auth_path: witness.siblings.iter().rev().cloned().collect(),
+            leaf_index: index,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IncrementalMerkleTree;
+    use crate::basic_merkle_tree::{common::*, constraints::*};
+    use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+    use ark_r1cs_std::prelude::*;
+    use ark_relations::{ns, r1cs::ConstraintSystem};
+
+    #[test]
+    fn incremental_tree_matches_dense_tree() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafH as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <CompressH as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+        let height = 4;
+        let mut tree = IncrementalMerkleTree::<JubJubMerkleTreeParams>::blank(
+            &leaf_crh_params,
+            &two_to_one_crh_params,
+            height,
+        )
+        .unwrap();
+        let mut dense =
+            JubJubMerkleTree::blank(&leaf_crh_params, &two_to_one_crh_params, height).unwrap();
+        assert_eq!(tree.root(), dense.root());
+        assert_eq!(tree.mark(), None);
+
+        let leaves: Vec<Vec<u8>> = (0..8u8).map(|i| vec![i; 30]).collect();
+        for (index, leaf) in leaves.iter().enumerate() {
+            assert_eq!(tree.append(leaf).unwrap(), index);
+            dense.update(index, leaf.as_slice()).unwrap();
+            assert_eq!(tree.root(), dense.root());
+            // Track a left child, a right child and a leaf in the right half.
+            if [2, 3, 4].contains(&index) {
+                assert_eq!(tree.mark(), Some(index));
+            }
+            for marked in tree.marked_leaves() {
+                let path = tree.witness(marked).unwrap();
+                let expected = dense.generate_proof(marked).unwrap();
+                assert_eq!(path.leaf_sibling_hash, expected.leaf_sibling_hash);
+                assert_eq!(path.auth_path, expected.auth_path);
+                assert_eq!(path.leaf_index, expected.leaf_index);
+            }
+        }
+        assert!(tree.append(&[0u8; 30]).is_err());
+        assert!(tree.unmark(3));
+        assert!(tree.witness(3).is_none());
+        assert_eq!(tree.marked_leaves().collect::<Vec<_>>(), vec![2, 4]);
+    }
+
+    #[test]
+    fn incremental_tree_witness_verifies_in_circuit() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafH as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <CompressH as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+        let mut tree = IncrementalMerkleTree::<JubJubMerkleTreeParams>::blank(
+            &leaf_crh_params,
+            &two_to_one_crh_params,
+            4,
+        )
+        .unwrap();
+        tree.append(&[1u8; 30]).unwrap();
+        tree.mark();
+        tree.append(&[2u8; 30]).unwrap();
+        tree.append(&[3u8; 30]).unwrap();
+
+        let cs = ConstraintSystem::<ConstraintF>::new_ref();
+        let root = RootVar::new_input(ns!(cs, "root"), || Ok(tree.root())).unwrap();
+        let leaf = UInt8::new_witness_vec(ns!(cs, "leaf"), &[1u8; 30]).unwrap();
+        let leaf_crh_params_var =
+            LeafHashParamsVar::new_constant(ns!(cs, "leaf_crh_params"), &leaf_crh_params).unwrap();
+        let two_to_one_crh_params_var = TwoToOneHashParamsVar::new_constant(
+            ns!(cs, "two_to_one_crh_params"),
+            &two_to_one_crh_params,
+        )
+        .unwrap();
+        let path =
+            SimplePathVar::new_witness(ns!(cs, "path"), || Ok(tree.witness(0).unwrap())).unwrap();
+        path.verify_membership(
+            &leaf_crh_params_var,
+            &two_to_one_crh_params_var,
+            &root,
+            &leaf,
+        )
+        .unwrap()
+        .enforce_equal(&Boolean::TRUE)
+        .unwrap();
+        assert!(cs.is_satisfied().unwrap());
+    }
+}
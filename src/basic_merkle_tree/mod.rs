@@ -1,5 +1,12 @@
+pub mod anonymous;
 pub mod common;
 pub mod constraints;
+pub mod encoding;
+pub mod incremental;
+pub mod multiproof;
+pub mod mutators;
+pub mod poseidon;
+pub mod storage;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,422 @@
+use ark_crypto_primitives::{
+    crh::{CRHScheme, CRHSchemeGadget, TwoToOneCRHScheme, TwoToOneCRHSchemeGadget},
+    merkle_tree::{
+        constraints::{ConfigGadget, DigestVarConverter},
+        Config, DigestConverter, LeafParam, MerkleTree, Path, TwoToOneParam,
+    },
+    Error,
+};
+use ark_ff::Field;
+use ark_r1cs_std::prelude::*;
+use ark_relations::r1cs::{Namespace, SynthesisError};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use std::{borrow::Borrow, collections::BTreeMap};
+
+/// A proof that several leaves are in a Merkle tree. Unlike one `Path` per leaf, it
+/// holds each node needed to hash the leaves up to the root once, and omits the
+/// nodes that are computed from the leaves themselves.
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct MultiPath<P: Config> {
+    /// The height of the tree, including the leaves and the root.
+    pub height: usize,
+    /// The indices of the proven leaves, in increasing order.
+    pub leaf_indices: Vec<usize>,
+    /// The siblings of the proven leaves that are not proven themselves, in
+    /// increasing order of index.
+    pub leaf_siblings: Vec<P::LeafDigest>,
+    /// The siblings of the internal nodes above the proven leaves that are not above
+    /// proven leaves themselves, level by level from the bottom, and in increasing
+    /// order of index within a level.
+    pub auth_nodes: Vec<P::InnerDigest>,
+}
+
+impl<P: Config> Clone for MultiPath<P> {
+    fn clone(&self) -> Self {
+        Self {
+            height: self.height,
+            leaf_indices: self.leaf_indices.clone(),
+            leaf_siblings: self.leaf_siblings.clone(),
+            auth_nodes: self.auth_nodes.clone(),
+        }
+    }
+}
+
+impl<P: Config> MultiPath<P> {
+    /// Return the proof that the leaves at `indices` are in `tree`. The indices may be
+    /// in any order, and repeated.
+    pub fn generate(tree: &MerkleTree<P>, indices: &[usize]) -> Result<Self, Error> {
+        let mut leaf_indices = indices.to_vec();
+        leaf_indices.sort_unstable();
+        leaf_indices.dedup();
+        if !is_well_formed(tree.height(), &leaf_indices, leaf_indices.len()) {
+            return Err("a multiproof proves one or more leaves of the tree".into());
+        }
+        let paths: BTreeMap<usize, Path<P>> = leaf_indices
+            .iter()
+            .map(|&index| Ok((index, tree.generate_proof(index)?)))
+            .collect::<Result<_, Error>>()?;
+
+        let leaf_siblings = missing_siblings(&leaf_indices)
+            .map(|sibling| paths[&(sibling ^ 1)].leaf_sibling_hash.clone())
+            .collect();
+        // Each node above the proven leaves, with one of the proven leaves below it,
+        // whose path holds the sibling of the node.
+        let mut nodes: Vec<(usize, usize)> = leaf_indices.iter().map(|&i| (i, i)).collect();
+        let mut auth_nodes = Vec::new();
+        for level in 1..tree.height() - 1 {
+            nodes = nodes
+                .into_iter()
+                .map(|(index, leaf)| (index >> 1, leaf))
+                .collect();
+            nodes.dedup_by_key(|(index, _)| *index);
+            let indices: Vec<usize> = nodes.iter().map(|(index, _)| *index).collect();
+            for sibling in missing_siblings(&indices) {
+                let (_, leaf) = nodes[indices.binary_search(&(sibling ^ 1)).unwrap()];
+                auth_nodes.push(paths[&leaf].auth_path[tree.height() - 2 - level].clone());
+            }
+        }
+        Ok(Self {
+            height: tree.height(),
+            leaf_indices,
+            leaf_siblings,
+            auth_nodes,
+        })
+    }
+
+    /// Check that `leaves` are the leaves at `self.leaf_indices`, in the same order,
+    /// in the Merkle tree with root `root`.
+    pub fn verify<L: Borrow<P::Leaf>>(
+        &self,
+        leaf_hash_params: &LeafParam<P>,
+        two_to_one_hash_params: &TwoToOneParam<P>,
+        root: &P::InnerDigest,
+        leaves: &[L],
+    ) -> Result<bool, Error> {
+        if !is_well_formed(self.height, &self.leaf_indices, leaves.len()) {
+            return Ok(false);
+        }
+        let digests = self
+            .leaf_indices
+            .iter()
+            .zip(leaves)
+            .map(|(&index, leaf)| {
+                Ok((
+                    index,
+                    P::LeafHash::evaluate(leaf_hash_params, leaf.borrow())?,
+                ))
+            })
+            .collect::<Result<_, Error>>()?;
+        let mut leaf_siblings = self.leaf_siblings.iter().cloned();
+        let level = hash_level(digests, &mut leaf_siblings, |left, right| {
+            P::TwoToOneHash::evaluate(
+                two_to_one_hash_params,
+                P::LeafInnerDigestConverter::convert(left)?,
+                P::LeafInnerDigestConverter::convert(right)?,
+            )
+        })?;
+        let Some(mut nodes) = level else {
+            return Ok(false);
+        };
+        let mut auth_nodes = self.auth_nodes.iter().cloned();
+        for _ in 1..self.height - 1 {
+            let level = hash_level(nodes, &mut auth_nodes, |left, right| {
+                P::TwoToOneHash::compress(two_to_one_hash_params, left, right)
+            })?;
+            let Some(parents) = level else {
+                return Ok(false);
+            };
+            nodes = parents;
+        }
+        Ok(leaf_siblings.next().is_none()
+            && auth_nodes.next().is_none()
+            && nodes.len() == 1
+            && &nodes[0].1 == root)
+    }
+}
+
+/// Return whether a multiproof for `leaf_indices` in a tree of height `height` can
+/// prove `num_leaves` leaves.
+fn is_well_formed(height: usize, leaf_indices: &[usize], num_leaves: usize) -> bool {
+    height > 1
+        && height <= usize::BITS as usize
+        && !leaf_indices.is_empty()
+        && leaf_indices.len() == num_leaves
+        && leaf_indices.windows(2).all(|pair| pair[0] < pair[1])
+        && leaf_indices
+            .last()
+            .is_some_and(|&last| last >> (height - 1) == 0)
+}
+
+/// Return the siblings of the nodes at `indices`, in increasing order of index, that
+/// are not at `indices` themselves. The indices must be increasing.
+fn missing_siblings(indices: &[usize]) -> impl Iterator<Item = usize> + '_ {
+    indices.iter().enumerate().filter_map(|(i, &index)| {
+        let has_left_sibling = index & 1 == 1 && i > 0 && indices[i - 1] == index ^ 1;
+        let has_right_sibling =
+            index & 1 == 0 && indices.get(i + 1).is_some_and(|&next| next == index ^ 1);
+        (!has_left_sibling && !has_right_sibling).then_some(index ^ 1)
+    })
+}
+
+/// Hash the nodes of one level, given as pairs of an index and a digest in increasing
+/// order of index, into their parents. The siblings that are not among the nodes are
+/// taken in order from `siblings`. Return `None` if there are not enough of them.
+fn hash_level<D, O, E>(
+    nodes: Vec<(usize, D)>,
+    siblings: &mut impl Iterator<Item = D>,
+    mut hash: impl FnMut(D, D) -> Result<O, E>,
+) -> Result<Option<Vec<(usize, O)>>, E> {
+    let mut parents = Vec::with_capacity(nodes.len());
+    let mut nodes = nodes.into_iter().peekable();
+    while let Some((index, digest)) = nodes.next() {
+        let (left, right) = if index & 1 == 1 {
+            match siblings.next() {
+                Some(sibling) => (sibling, digest),
+                None => return Ok(None),
+            }
+        } else if let Some((_, right)) = nodes.next_if(|(next, _)| *next == index ^ 1) {
+            (digest, right)
+        } else {
+            match siblings.next() {
+                Some(sibling) => (digest, sibling),
+                None => return Ok(None),
+            }
+        };
+        parents.push((index >> 1, hash(left, right)?));
+    }
+    Ok(Some(parents))
+}
+
+type LeafParamVar<P, F, PG> = <<PG as ConfigGadget<P, F>>::LeafHash as CRHSchemeGadget<
+    <P as Config>::LeafHash,
+    F,
+>>::ParametersVar;
+type TwoToOneParamVar<P, F, PG> =
+    <<PG as ConfigGadget<P, F>>::TwoToOneHash as TwoToOneCRHSchemeGadget<
+        <P as Config>::TwoToOneHash,
+        F,
+    >>::ParametersVar;
+
+/// The R1CS equivalent of a `MultiPath`. The leaf indices determine which nodes are
+/// hashed together, so they are constants of the circuit: a circuit verifying a
+/// multiproof for other indices is another circuit.
+pub struct MultiPathVar<P: Config, F: Field, PG: ConfigGadget<P, F>> {
+    height: usize,
+    leaf_indices: Vec<usize>,
+    leaf_siblings: Vec<PG::LeafDigest>,
+    auth_nodes: Vec<PG::InnerDigest>,
+}
+
+impl<P: Config, F: Field, PG: ConfigGadget<P, F>> AllocVar<MultiPath<P>, F>
+    for MultiPathVar<P, F, PG>
+{
+    #[tracing::instrument(target = "r1cs", skip(cs, f, mode))]
+    fn new_variable<T: Borrow<MultiPath<P>>>(
+        cs: impl Into<Namespace<F>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let ns = cs.into();
+        let cs = ns.cs();
+        let value = f()?;
+        let path = value.borrow();
+        Ok(Self {
+            height: path.height,
+            leaf_indices: path.leaf_indices.clone(),
+            leaf_siblings: Vec::new_variable(
+                ark_relations::ns!(cs, "leaf_siblings"),
+                || Ok(path.leaf_siblings.as_slice()),
+                mode,
+            )?,
+            auth_nodes: Vec::new_variable(
+                ark_relations::ns!(cs, "auth_nodes"),
+                || Ok(path.auth_nodes.as_slice()),
+                mode,
+            )?,
+        })
+    }
+}
+
+impl<P: Config, F: Field, PG: ConfigGadget<P, F>> MultiPathVar<P, F, PG> {
+    /// Return the indices of the proven leaves, in increasing order.
+    pub fn leaf_indices(&self) -> &[usize] {
+        &self.leaf_indices
+    }
+
+    /// Return whether `leaves` are the leaves at `self.leaf_indices()`, in the same
+    /// order, in the Merkle tree with root `root`. Each node is hashed once, whereas
+    /// verifying one path per leaf hashes shared nodes once per path.
+    #[tracing::instrument(
+        target = "r1cs",
+        skip(self, leaf_hash_params, two_to_one_hash_params, root, leaves)
+    )]
+    pub fn verify_membership<L: Borrow<PG::Leaf>>(
+        &self,
+        leaf_hash_params: &LeafParamVar<P, F, PG>,
+        two_to_one_hash_params: &TwoToOneParamVar<P, F, PG>,
+        root: &PG::InnerDigest,
+        leaves: &[L],
+    ) -> Result<Boolean<F>, SynthesisError> {
+        if !is_well_formed(self.height, &self.leaf_indices, leaves.len()) {
+            return Ok(Boolean::FALSE);
+        }
+        let digests = self
+            .leaf_indices
+            .iter()
+            .zip(leaves)
+            .map(|(&index, leaf)| {
+                Ok((
+                    index,
+                    PG::LeafHash::evaluate(leaf_hash_params, leaf.borrow())?,
+                ))
+            })
+            .collect::<Result<_, SynthesisError>>()?;
+        let mut leaf_siblings = self.leaf_siblings.iter().cloned();
+        let level = hash_level(digests, &mut leaf_siblings, |left, right| {
+            let left = PG::LeafInnerConverter::convert(left)?;
+            let right = PG::LeafInnerConverter::convert(right)?;
+            PG::TwoToOneHash::evaluate(two_to_one_hash_params, left.borrow(), right.borrow())
+        })?;
+        let Some(mut nodes) = level else {
+            return Ok(Boolean::FALSE);
+        };
+        let mut auth_nodes = self.auth_nodes.iter().cloned();
+        for _ in 1..self.height - 1 {
+            let level = hash_level(nodes, &mut auth_nodes, |left, right| {
+                PG::TwoToOneHash::compress(two_to_one_hash_params, &left, &right)
+            })?;
+            let Some(parents) = level else {
+                return Ok(Boolean::FALSE);
+            };
+            nodes = parents;
+        }
+        if leaf_siblings.next().is_some() || auth_nodes.next().is_some() || nodes.len() != 1 {
+            return Ok(Boolean::FALSE);
+        }
+        nodes[0].1.is_eq(root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MultiPath, MultiPathVar};
+    use crate::basic_merkle_tree::{common::*, constraints::*};
+    use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+    use ark_r1cs_std::prelude::*;
+    use ark_relations::{ns, r1cs::ConstraintSystem};
+
+    type MultiPathVarJubJub =
+        MultiPathVar<JubJubMerkleTreeParams, ConstraintF, JubJubMerkleTreeParamsVar>;
+
+    fn setup() -> (
+        AllocVarParameters,
+        AllocVarParameters,
+        Vec<Vec<u8>>,
+        JubJubMerkleTree,
+    ) {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafH as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <CompressH as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+        let leaves: Vec<Vec<u8>> = (0..16u8).map(|i| vec![i; 30]).collect();
+        let tree = JubJubMerkleTree::new(
+            &leaf_crh_params,
+            &two_to_one_crh_params,
+            leaves.iter().map(|v| v.as_slice()),
+        )
+        .unwrap();
+        (leaf_crh_params, two_to_one_crh_params, leaves, tree)
+    }
+
+    #[test]
+    fn multiproof_verifies_and_deduplicates_siblings() {
+        let (leaf_crh_params, two_to_one_crh_params, leaves, tree) = setup();
+        let root = tree.root();
+        let verify = |proof: &MultiPath<_>, leaves: &[&[u8]]| {
+            proof
+                .verify(&leaf_crh_params, &two_to_one_crh_params, &root, leaves)
+                .unwrap()
+        };
+
+        // Sibling leaves, leaves sharing upper nodes, and an isolated leaf.
+        let proof = MultiPath::generate(&tree, &[9, 3, 2, 15, 1, 3]).unwrap();
+        assert_eq!(proof.leaf_indices, vec![1, 2, 3, 9, 15]);
+        let proven: Vec<&[u8]> = proof
+            .leaf_indices
+            .iter()
+            .map(|&i| leaves[i].as_slice())
+            .collect();
+        assert!(verify(&proof, &proven));
+        // 5 separate paths would hold 5 leaf siblings and 5 * 3 internal nodes.
+        assert_eq!(proof.leaf_siblings.len(), 3);
+        assert_eq!(proof.auth_nodes.len(), 3);
+
+        let mut tampered = proven.clone();
+        tampered[3] = leaves[8].as_slice();
+        assert!(!verify(&proof, &tampered));
+        assert!(!verify(&proof, &proven[..4]));
+        let mut truncated = proof.clone();
+        truncated.auth_nodes.pop();
+        assert!(!verify(&truncated, &proven));
+        let mut extended = proof.clone();
+        extended.leaf_siblings.push(proof.leaf_siblings[0]);
+        assert!(!verify(&extended, &proven));
+        let mut reindexed = proof.clone();
+        reindexed.leaf_indices[4] = 14;
+        assert!(!verify(&reindexed, &proven));
+
+        // A single leaf is proven by its path.
+        let proof = MultiPath::generate(&tree, &[6]).unwrap();
+        let path = tree.generate_proof(6).unwrap();
+        assert_eq!(proof.leaf_siblings, vec![path.leaf_sibling_hash]);
+        assert_eq!(
+            proof.auth_nodes,
+            path.auth_path.into_iter().rev().collect::<Vec<_>>()
+        );
+        assert!(verify(&proof, &[leaves[6].as_slice()]));
+        assert!(MultiPath::generate(&tree, &[]).is_err());
+        assert!(MultiPath::generate(&tree, &[16]).is_err());
+    }
+
+    #[test]
+    fn multiproof_gadget() {
+        let (leaf_crh_params, two_to_one_crh_params, leaves, tree) = setup();
+        let indices = [4, 5, 12];
+        let proof = MultiPath::generate(&tree, &indices).unwrap();
+
+        let is_satisfied = |leaves: &[Vec<u8>]| {
+            let cs = ConstraintSystem::<ConstraintF>::new_ref();
+            let root = RootVar::new_input(ns!(cs, "root"), || Ok(tree.root())).unwrap();
+            let leaf_crh_params_var =
+                LeafHashParamsVar::new_constant(ns!(cs, "leaf_crh_params"), &leaf_crh_params)
+                    .unwrap();
+            let two_to_one_crh_params_var = TwoToOneHashParamsVar::new_constant(
+                ns!(cs, "two_to_one_crh_params"),
+                &two_to_one_crh_params,
+            )
+            .unwrap();
+            let leaves: Vec<Vec<UInt8<ConstraintF>>> = leaves
+                .iter()
+                .map(|leaf| UInt8::new_witness_vec(ns!(cs, "leaf"), leaf).unwrap())
+                .collect();
+            let proof = MultiPathVarJubJub::new_witness(ns!(cs, "proof"), || Ok(&proof)).unwrap();
+            assert_eq!(proof.leaf_indices(), &indices);
+            proof
+                .verify_membership(
+                    &leaf_crh_params_var,
+                    &two_to_one_crh_params_var,
+                    &root,
+                    &leaves,
+                )
+                .unwrap()
+                .enforce_equal(&Boolean::TRUE)
+                .unwrap();
+            cs.is_satisfied().unwrap()
+        };
+
+        let proven: Vec<Vec<u8>> = indices.iter().map(|&i| leaves[i].clone()).collect();
+        assert!(is_satisfied(&proven));
+        let mut tampered = proven.clone();
+        tampered[1] = leaves[6].clone();
+        assert!(!is_satisfied(&tampered));
+    }
+}
@@ -0,0 +1,87 @@
+use super::common::SimplePath;
+use ark_ec::{AffineRepr, CurveGroup};
+use ark_ed_on_bls12_381::EdwardsAffine;
+
+/// A way for a dishonest prover to corrupt a Merkle path in a witness. A circuit that
+/// checks membership against a fixed root should be unsatisfiable with any of them.
+#[derive(Hash, Eq, PartialEq, Copy, Clone, Debug)]
+pub enum PathMutation {
+    /// Replace the sibling of the leaf by another digest.
+    FlipLeafSibling,
+    /// Replace the sibling of the inner node at `depth` by another digest, where the
+    /// root has depth 0.
+    FlipSibling(usize),
+    /// Swap the left and right children at level `level` above the leaves, i.e. flip
+    /// bit `level` of the leaf position. Level 0 swaps the leaf with its sibling.
+    SwapPosition(usize),
+}
+
+impl PathMutation {
+    /// Every mutation of a path in a tree of height `height`.
+    pub fn all(height: usize) -> Vec<Self> {
+        let inner_levels = height - 2;
+        std::iter::once(PathMutation::FlipLeafSibling)
+            .chain((1..=inner_levels).map(PathMutation::FlipSibling))
+            .chain((0..=inner_levels).map(PathMutation::SwapPosition))
+            .collect()
+    }
+
+    /// Apply the mutation to `path`.
+    pub fn apply(&self, path: &mut SimplePath) {
+        match *self {
+            PathMutation::FlipLeafSibling => flip(&mut path.leaf_sibling_hash),
+            PathMutation::FlipSibling(depth) => flip(&mut path.auth_path[depth - 1]),
+            PathMutation::SwapPosition(level) => path.leaf_index ^= 1 << level,
+        }
+    }
+}
+
+/// Replace `digest` by another point of the curve.
+fn flip(digest: &mut EdwardsAffine) {
+    *digest = (*digest + EdwardsAffine::generator()).into_affine();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PathMutation;
+    use crate::basic_merkle_tree::{common::*, constraints::MerkleTreeVerification};
+    use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+    use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystem};
+
+    #[test]
+    fn mutated_paths_are_unsatisfiable() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafH as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <CompressH as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+        let leaves: Vec<Vec<u8>> = (0..8u8).map(|i| vec![i; 30]).collect();
+        let tree = JubJubMerkleTree::new(
+            &leaf_crh_params,
+            &two_to_one_crh_params,
+            leaves.iter().map(|v| v.as_slice()),
+        )
+        .unwrap();
+
+        let is_satisfied = |mutation: Option<PathMutation>| {
+            let mut path = tree.generate_proof(5).unwrap();
+            if let Some(mutation) = mutation {
+                mutation.apply(&mut path);
+            }
+            let circuit = MerkleTreeVerification {
+                leaf_crh_params: leaf_crh_params.clone(),
+                two_to_one_crh_params: two_to_one_crh_params.clone(),
+                root: tree.root(),
+                leaf: leaves[5].clone(),
+                authentication_path: Some(path),
+            };
+            let cs = ConstraintSystem::new_ref();
+            circuit.generate_constraints(cs.clone()).unwrap();
+            cs.is_satisfied().unwrap()
+        };
+        assert!(is_satisfied(None));
+        let mutations = PathMutation::all(tree.height());
+        assert_eq!(mutations.len(), 6);
+        for mutation in mutations {
+            assert!(!is_satisfied(Some(mutation)), "{mutation:?} is satisfiable");
+        }
+    }
+}
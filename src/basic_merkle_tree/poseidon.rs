@@ -0,0 +1,46 @@
+use super::common::ConstraintF;
+use ark_crypto_primitives::{
+    crh::poseidon,
+    merkle_tree::{constraints::ConfigGadget, Config, IdentityDigestConverter, MerkleTree, Path},
+    sponge::poseidon::PoseidonConfig,
+};
+use ark_r1cs_std::fields::fp::FpVar;
+
+pub type PoseidonLeafH = poseidon::CRH<ConstraintF>;
+pub type PoseidonLeafHG = poseidon::constraints::CRHGadget<ConstraintF>;
+pub type PoseidonCompressH = poseidon::TwoToOneCRH<ConstraintF>;
+pub type PoseidonCompressHG = poseidon::constraints::TwoToOneCRHGadget<ConstraintF>;
+
+/// The Poseidon configuration of the leaf and two-to-one hashes: rate 2, capacity 1,
+/// the S-box `x^17`, 8 full rounds and 31 partial rounds, as recommended for a
+/// 255-bit field when optimizing for constraints. The round constants and MDS matrix
+/// are derived with the Grain LFSR of the Poseidon reference implementation.
+pub fn poseidon_parameters() -> PoseidonConfig<ConstraintF> {
+    crate::poseidon::poseidon_config(2, 17, 8, 31)
+}
+
+/// A Merkle tree over field elements, hashed with Poseidon. Unlike the Pedersen
+/// `JubJubMerkleTreeParams`, its leaves are not decomposed into bytes in circuits.
+pub struct PoseidonMerkleTreeParams;
+impl Config for PoseidonMerkleTreeParams {
+    type Leaf = [ConstraintF];
+    type LeafDigest = ConstraintF;
+    type LeafInnerDigestConverter = IdentityDigestConverter<ConstraintF>;
+    type InnerDigest = ConstraintF;
+    type LeafHash = PoseidonLeafH;
+    type TwoToOneHash = PoseidonCompressH;
+}
+
+pub struct PoseidonMerkleTreeParamsVar;
+impl ConfigGadget<PoseidonMerkleTreeParams, ConstraintF> for PoseidonMerkleTreeParamsVar {
+    type Leaf = [FpVar<ConstraintF>];
+    type LeafDigest = FpVar<ConstraintF>;
+    type LeafInnerConverter = IdentityDigestConverter<FpVar<ConstraintF>>;
+    type InnerDigest = FpVar<ConstraintF>;
+    type LeafHash = PoseidonLeafHG;
+    type TwoToOneHash = PoseidonCompressHG;
+}
+
+pub type PoseidonMerkleTree = MerkleTree<PoseidonMerkleTreeParams>;
+
+pub type PoseidonPath = Path<PoseidonMerkleTreeParams>;
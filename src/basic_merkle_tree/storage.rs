@@ -0,0 +1,1213 @@
+use ark_crypto_primitives::{
+    crh::{CRHScheme, TwoToOneCRHScheme},
+    merkle_tree::{Config, DigestConverter, LeafParam, Path, TwoToOneParam},
+    Error,
+};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_std::cfg_into_iter;
+use blake2::{Blake2s256 as Blake2s, Digest};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use std::{
+    borrow::Borrow,
+    collections::{BTreeMap, HashMap},
+    fs::{File, OpenOptions},
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    marker::PhantomData,
+    path::{Path as FilePath, PathBuf},
+};
+
+/// A store of the nodes of a binary Merkle tree. Nodes are addressed by their level,
+/// where the leaves are at level 0, and by their index within the level. Nodes that
+/// were never written are empty, and their digest is computed by the tree.
+pub trait NodeStore<P: Config> {
+    /// Return the height of the tree whose nodes the store holds, or `None` if the store
+    /// holds the nodes of a tree of any height.
+    fn height(&self) -> Option<usize>;
+
+    /// Return the digest of the leaf at `index`, or `None` if it was never written.
+    fn leaf(&self, index: usize) -> Result<Option<P::LeafDigest>, Error>;
+
+    /// Return the node at `index` within `level`, or `None` if it was never written.
+    fn node(&self, level: usize, index: usize) -> Result<Option<P::InnerDigest>, Error>;
+
+    /// Return every record written to the store, by key.
+    fn records(&self) -> Result<BTreeMap<u64, Vec<u8>>, Error>;
+
+    /// Write every node and record of `batch`, replacing the nodes at the same positions
+    /// and the records with the same keys.
+    fn write(&mut self, batch: &NodeBatch<P>) -> Result<(), Error>;
+}
+
+/// A tree configuration whose leaves, digests and hash parameters can be shared across
+/// threads, so that the nodes of a level can be hashed in parallel.
+pub trait SyncConfig:
+    Config<
+    Leaf: Sync,
+    LeafDigest: Send + Sync,
+    InnerDigest: Send + Sync,
+    LeafHash: CRHScheme<Parameters: Sync>,
+    TwoToOneHash: TwoToOneCRHScheme<Parameters: Sync>,
+>
+{
+}
+
+impl<P> SyncConfig for P where
+    P: Config<
+        Leaf: Sync,
+        LeafDigest: Send + Sync,
+        InnerDigest: Send + Sync,
+        LeafHash: CRHScheme<Parameters: Sync>,
+        TwoToOneHash: TwoToOneCRHScheme<Parameters: Sync>,
+    >
+{
+}
+
+/// A set of nodes of a binary Merkle tree, by position, and of records written with
+/// them.
+pub struct NodeBatch<P: Config> {
+    /// The leaf digests, by leaf index.
+    pub leaves: HashMap<usize, P::LeafDigest>,
+    /// The inner nodes, by level and index within the level.
+    pub nodes: HashMap<(usize, usize), P::InnerDigest>,
+    /// Opaque records, by key, such as the data that the leaves commit to. A store
+    /// persists them together with the nodes of the same batch.
+    pub records: BTreeMap<u64, Vec<u8>>,
+}
+
+impl<P: Config> Default for NodeBatch<P> {
+    fn default() -> Self {
+        Self {
+            leaves: HashMap::new(),
+            nodes: HashMap::new(),
+            records: BTreeMap::new(),
+        }
+    }
+}
+
+impl<P: Config> Clone for NodeBatch<P> {
+    fn clone(&self) -> Self {
+        Self {
+            leaves: self.leaves.clone(),
+            nodes: self.nodes.clone(),
+            records: self.records.clone(),
+        }
+    }
+}
+
+impl<P: Config> NodeBatch<P> {
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty() && self.nodes.is_empty() && self.records.is_empty()
+    }
+
+    /// Add the nodes and records of `other` at positions and keys that are not in the
+    /// batch yet.
+    pub fn merge_vacant(&mut self, other: NodeBatch<P>) {
+        for (index, leaf) in other.leaves {
+            self.leaves.entry(index).or_insert(leaf);
+        }
+        for (position, node) in other.nodes {
+            self.nodes.entry(position).or_insert(node);
+        }
+        for (key, record) in other.records {
+            self.records.entry(key).or_insert(record);
+        }
+    }
+}
+
+/// A node store keeping every written node in memory.
+pub struct MemoryNodeStore<P: Config> {
+    nodes: NodeBatch<P>,
+}
+
+impl<P: Config> Default for MemoryNodeStore<P> {
+    fn default() -> Self {
+        Self {
+            nodes: NodeBatch::default(),
+        }
+    }
+}
+
+impl<P: Config> Clone for MemoryNodeStore<P> {
+    fn clone(&self) -> Self {
+        Self {
+            nodes: self.nodes.clone(),
+        }
+    }
+}
+
+impl<P: Config> NodeStore<P> for MemoryNodeStore<P> {
+    fn height(&self) -> Option<usize> {
+        None
+    }
+
+    fn leaf(&self, index: usize) -> Result<Option<P::LeafDigest>, Error> {
+        Ok(self.nodes.leaves.get(&index).cloned())
+    }
+
+    fn node(&self, level: usize, index: usize) -> Result<Option<P::InnerDigest>, Error> {
+        Ok(self.nodes.nodes.get(&(level, index)).cloned())
+    }
+
+    fn records(&self) -> Result<BTreeMap<u64, Vec<u8>>, Error> {
+        Ok(self.nodes.records.clone())
+    }
+
+    fn write(&mut self, batch: &NodeBatch<P>) -> Result<(), Error> {
+        self.nodes.leaves.extend(batch.leaves.clone());
+        self.nodes.nodes.extend(batch.nodes.clone());
+        self.nodes.records.extend(batch.records.clone());
+        Ok(())
+    }
+}
+
+/// The magic bytes at the start of the node file of a `FileNodeStore`.
+const MAGIC: &[u8; 8] = b"MRKLNODE";
+
+/// The size of the header of the node file of a `FileNodeStore`: the magic bytes, the
+/// height of the tree as a little-endian `u64`, and the digest of its hash parameters.
+const HEADER_SIZE: u64 = 8 + 8 + 32;
+
+/// The largest height of a tree kept in a `FileNodeStore`, whose node indices and slot
+/// offsets fit in 64 bits.
+pub const MAX_FILE_TREE_HEIGHT: usize = 64;
+
+/// A write to a `FileNodeStore`. It is saved to the journal file before the store files
+/// are modified, and replayed when the store is opened if the write was interrupted, so
+/// that a write is applied either entirely or not at all.
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+struct Journal {
+    /// The slots to write, by offset in the node file, in increasing order. The root has
+    /// the largest offset, so it is written last.
+    slots: Vec<(u64, Vec<u8>)>,
+    /// The length of the record file before the write.
+    records_len: u64,
+    /// The records to append to the record file.
+    records: BTreeMap<u64, Vec<u8>>,
+}
+
+/// A node store keeping the nodes of a tree of a fixed height in a file. Every node
+/// has a slot at a fixed offset, made of a byte telling whether the node was written,
+/// followed by its compressed serialization. Levels are laid out one after the other
+/// from the leaves up, after a header recording the height of the tree and the digest
+/// of its hash parameters, so unwritten nodes are holes in a sparse file, and a blank
+/// tree takes no space on disk.
+///
+/// Records are appended to a second file, next to the node file, and kept in memory.
+/// Writes go through a journal file, so that a crash leaves the store as it was before
+/// or after the interrupted write.
+pub struct FileNodeStore<P: Config> {
+    file: File,
+    records_file: File,
+    journal_path: PathBuf,
+    records: BTreeMap<u64, Vec<u8>>,
+    height: usize,
+    leaf_slot_size: u64,
+    inner_slot_size: u64,
+    _config: PhantomData<P>,
+}
+
+impl<P: Config> FileNodeStore<P> {
+    /// Open the nodes of a tree of `height` stored in the file at `path`, creating an
+    /// empty store if the file does not exist. `params_digest` identifies the hash
+    /// parameters of the tree, as computed by `params_digest`. Records are kept in the
+    /// file at `path` with the extension `records`, and the journal in the file with the
+    /// extension `journal`.
+    ///
+    /// Fails if `height` is not between 2 and `MAX_FILE_TREE_HEIGHT`, or if the file
+    /// holds a tree of another height or with other hash parameters.
+    pub fn open(
+        path: impl AsRef<FilePath>,
+        height: usize,
+        params_digest: [u8; 32],
+    ) -> Result<Self, Error> {
+        if !(2..=MAX_FILE_TREE_HEIGHT).contains(&height) {
+            return Err("the height of a tree in a file must be between 2 and 64".into());
+        }
+        let path = path.as_ref();
+        let open = |path: &FilePath| {
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)
+        };
+        let mut file = open(path)?;
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        header.extend(MAGIC);
+        header.extend((height as u64).to_le_bytes());
+        header.extend(params_digest);
+        if file.metadata()?.len() == 0 {
+            file.write_all(&header)?;
+            file.sync_data()?;
+        } else {
+            let mut stored = vec![0u8; HEADER_SIZE as usize];
+            file.read_exact(&mut stored)?;
+            if stored[..8] != MAGIC[..] {
+                return Err("the file does not hold the nodes of a Merkle tree".into());
+            }
+            if stored[8..16] != header[8..16] {
+                return Err("the file holds a tree of another height".into());
+            }
+            if stored[16..] != header[16..] {
+                return Err("the file holds a tree with other hash parameters".into());
+            }
+        }
+        let mut store = Self {
+            file,
+            records_file: open(&path.with_extension("records"))?,
+            journal_path: path.with_extension("journal"),
+            records: BTreeMap::new(),
+            height,
+            leaf_slot_size: 1 + P::LeafDigest::default().compressed_size() as u64,
+            inner_slot_size: 1 + P::InnerDigest::default().compressed_size() as u64,
+            _config: PhantomData,
+        };
+        store.recover()?;
+        store.records = store.read_records()?;
+        Ok(store)
+    }
+
+    /// Replay the journal of an interrupted write, if its journal was saved entirely,
+    /// and remove it. A journal that was not saved entirely is discarded, since the
+    /// store files are only modified once the journal is saved.
+    fn recover(&mut self) -> Result<(), Error> {
+        let bytes = match std::fs::read(&self.journal_path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        if bytes.len() >= 32 {
+            let (body, digest) = bytes.split_at(bytes.len() - 32);
+            if Blake2s::digest(body).as_slice() == digest {
+                self.apply(&Journal::deserialize_uncompressed(body)?)?;
+            }
+        }
+        std::fs::remove_file(&self.journal_path)?;
+        Ok(())
+    }
+
+    /// Read every record of the record file, where later records replace earlier ones
+    /// with the same key.
+    fn read_records(&mut self) -> Result<BTreeMap<u64, Vec<u8>>, Error> {
+        let mut bytes = Vec::new();
+        self.records_file.seek(SeekFrom::Start(0))?;
+        self.records_file.read_to_end(&mut bytes)?;
+        let mut records = BTreeMap::new();
+        let mut reader = bytes.as_slice();
+        while !reader.is_empty() {
+            let (key, record) = <(u64, Vec<u8>)>::deserialize_uncompressed(&mut reader)?;
+            records.insert(key, record);
+        }
+        Ok(records)
+    }
+
+    /// Write the slots and append the records of `journal` to the store files. Applying
+    /// the same journal again leaves the files unchanged.
+    fn apply(&mut self, journal: &Journal) -> Result<(), Error> {
+        for (offset, slot) in &journal.slots {
+            self.file.seek(SeekFrom::Start(*offset))?;
+            self.file.write_all(slot)?;
+        }
+        self.file.sync_data()?;
+        let mut records = Vec::new();
+        for (key, record) in &journal.records {
+            (*key, record.clone()).serialize_uncompressed(&mut records)?;
+        }
+        self.records_file.set_len(journal.records_len)?;
+        self.records_file.seek(SeekFrom::End(0))?;
+        self.records_file.write_all(&records)?;
+        self.records_file.sync_data()?;
+        Ok(())
+    }
+
+    /// Return the journal of the write of `batch`.
+    fn journal(&self, batch: &NodeBatch<P>) -> Result<Journal, Error> {
+        let mut slots = Vec::with_capacity(batch.leaves.len() + batch.nodes.len());
+        for (&index, leaf) in &batch.leaves {
+            slots.push(self.encode_slot(0, index, leaf)?);
+        }
+        for (&(level, index), node) in &batch.nodes {
+            slots.push(self.encode_slot(level, index, node)?);
+        }
+        slots.sort_unstable_by_key(|(offset, _)| *offset);
+        Ok(Journal {
+            slots,
+            records_len: self.records_file.metadata()?.len(),
+            records: batch.records.clone(),
+        })
+    }
+
+    /// Save `journal` to the journal file, followed by its Blake2s digest, which tells
+    /// whether it was saved entirely.
+    fn save_journal(&self, journal: &Journal) -> Result<(), Error> {
+        let mut bytes = Vec::new();
+        journal.serialize_uncompressed(&mut bytes)?;
+        let digest = Blake2s::digest(&bytes);
+        bytes.extend(digest);
+        let mut file = File::create(&self.journal_path)?;
+        file.write_all(&bytes)?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    /// Return the offset of the slot of the node at `index` within `level`, and the
+    /// size of the slot.
+    fn slot(&self, level: usize, index: usize) -> Result<(u64, u64), Error> {
+        if level >= self.height || index >> (self.height - 1 - level) != 0 {
+            return Err("the node is outside of the tree".into());
+        }
+        let num_leaves = 1u64
+            .checked_shl(self.height as u32 - 1)
+            .ok_or("the tree is too high to be stored in a file")?;
+        let too_high = || "the tree is too high to be stored in a file";
+        let (start, size) = if level == 0 {
+            (HEADER_SIZE, self.leaf_slot_size)
+        } else {
+            // The levels below `level` hold `num_leaves` leaves and
+            // `num_leaves - 2^(height - level)` inner nodes.
+            let inner_nodes_below = num_leaves - (1 << (self.height - level));
+            let start = num_leaves
+                .checked_mul(self.leaf_slot_size)
+                .and_then(|leaves| {
+                    inner_nodes_below
+                        .checked_mul(self.inner_slot_size)?
+                        .checked_add(leaves)?
+                        .checked_add(HEADER_SIZE)
+                })
+                .ok_or_else(too_high)?;
+            (start, self.inner_slot_size)
+        };
+        let offset = (index as u64)
+            .checked_mul(size)
+            .and_then(|offset| offset.checked_add(start))
+            .ok_or_else(too_high)?;
+        Ok((offset, size))
+    }
+
+    /// Read the slot of the node at `index` within `level`, and deserialize the node if
+    /// it was written.
+    fn read<T: CanonicalDeserialize>(
+        &self,
+        level: usize,
+        index: usize,
+    ) -> Result<Option<T>, Error> {
+        let (offset, size) = self.slot(level, index)?;
+        if offset + size > self.file.metadata()?.len() {
+            return Ok(None);
+        }
+        let mut slot = vec![0u8; size as usize];
+        read_exact_at(&self.file, &mut slot, offset)?;
+        match slot[0] {
+            0 => Ok(None),
+            _ => Ok(Some(T::deserialize_compressed(&slot[1..])?)),
+        }
+    }
+
+    /// Return the offset and the content of the slot of `node` at `index` within
+    /// `level`.
+    fn encode_slot<T: CanonicalSerialize>(
+        &self,
+        level: usize,
+        index: usize,
+        node: &T,
+    ) -> Result<(u64, Vec<u8>), Error> {
+        let (offset, size) = self.slot(level, index)?;
+        let mut slot = Vec::with_capacity(size as usize);
+        slot.push(1);
+        node.serialize_compressed(&mut slot)?;
+        if slot.len() as u64 != size {
+            return Err("the digests of the tree do not have a fixed size".into());
+        }
+        Ok((offset, slot))
+    }
+}
+
+impl<P: Config> NodeStore<P> for FileNodeStore<P> {
+    fn height(&self) -> Option<usize> {
+        Some(self.height)
+    }
+
+    fn leaf(&self, index: usize) -> Result<Option<P::LeafDigest>, Error> {
+        self.read(0, index)
+    }
+
+    fn node(&self, level: usize, index: usize) -> Result<Option<P::InnerDigest>, Error> {
+        match level {
+            0 => Err("the leaves are not inner nodes".into()),
+            _ => self.read(level, index),
+        }
+    }
+
+    fn records(&self) -> Result<BTreeMap<u64, Vec<u8>>, Error> {
+        Ok(self.records.clone())
+    }
+
+    /// Save the batch to the journal, then write it to the store files, then remove the
+    /// journal.
+    fn write(&mut self, batch: &NodeBatch<P>) -> Result<(), Error> {
+        let journal = self.journal(batch)?;
+        self.save_journal(&journal)?;
+        self.apply(&journal)?;
+        std::fs::remove_file(&self.journal_path)?;
+        self.records.extend(journal.records);
+        Ok(())
+    }
+}
+
+/// Read exactly `buf.len()` bytes of `file` from `offset`, without using the cursor of
+/// the file, so that threads sharing a `FileNodeStore` can read it at once.
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+/// Read exactly `buf.len()` bytes of `file` from `offset`, without using the cursor of
+/// the file, so that threads sharing a `FileNodeStore` can read it at once.
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> std::io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset) {
+            Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(read) => {
+                buf = &mut buf[read..];
+                offset += read as u64;
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Return a digest identifying the hash parameters of a tree: the Blake2s hash of the
+/// digest of the leaf `probe` and of the node above two empty leaves. A `FileNodeStore`
+/// records it, so that a tree is not reopened with other hash parameters by mistake.
+/// `probe` must not be made of zeros only, which a Pedersen hash maps to the identity
+/// whatever its parameters.
+pub fn params_digest<P: Config>(
+    leaf_hash_params: &LeafParam<P>,
+    two_to_one_hash_params: &TwoToOneParam<P>,
+    probe: &P::Leaf,
+) -> Result<[u8; 32], Error> {
+    let empty_leaf = P::LeafDigest::default();
+    let empty_node = P::TwoToOneHash::evaluate(
+        two_to_one_hash_params,
+        P::LeafInnerDigestConverter::convert(empty_leaf.clone())?,
+        P::LeafInnerDigestConverter::convert(empty_leaf)?,
+    )?;
+    let mut bytes = Vec::new();
+    P::LeafHash::evaluate(leaf_hash_params, probe)?.serialize_compressed(&mut bytes)?;
+    empty_node.serialize_compressed(&mut bytes)?;
+    Ok(Blake2s::digest(bytes).into())
+}
+
+/// A copy-on-write view of a node store. Nodes are read from the nodes written to the
+/// view, then from `layers` in order, then from `base`, which is never modified.
+pub struct OverlayNodeStore<'a, P: Config, S> {
+    written: NodeBatch<P>,
+    layers: Vec<&'a NodeBatch<P>>,
+    base: &'a S,
+}
+
+impl<P: Config, S: NodeStore<P>> NodeStore<P> for OverlayNodeStore<'_, P, S> {
+    fn height(&self) -> Option<usize> {
+        self.base.height()
+    }
+
+    fn leaf(&self, index: usize) -> Result<Option<P::LeafDigest>, Error> {
+        let layer = std::iter::once(&self.written).chain(self.layers.iter().copied());
+        for nodes in layer {
+            if let Some(leaf) = nodes.leaves.get(&index) {
+                return Ok(Some(leaf.clone()));
+            }
+        }
+        self.base.leaf(index)
+    }
+
+    fn node(&self, level: usize, index: usize) -> Result<Option<P::InnerDigest>, Error> {
+        let layer = std::iter::once(&self.written).chain(self.layers.iter().copied());
+        for nodes in layer {
+            if let Some(node) = nodes.nodes.get(&(level, index)) {
+                return Ok(Some(node.clone()));
+            }
+        }
+        self.base.node(level, index)
+    }
+
+    fn records(&self) -> Result<BTreeMap<u64, Vec<u8>>, Error> {
+        let mut records = self.base.records()?;
+        let layers = std::iter::once(&self.written).chain(self.layers.iter().copied());
+        for nodes in layers.rev() {
+            records.extend(nodes.records.clone());
+        }
+        Ok(records)
+    }
+
+    fn write(&mut self, batch: &NodeBatch<P>) -> Result<(), Error> {
+        self.written.leaves.extend(batch.leaves.clone());
+        self.written.nodes.extend(batch.nodes.clone());
+        self.written.records.extend(batch.records.clone());
+        Ok(())
+    }
+}
+
+/// A binary Merkle tree whose nodes are kept in a `NodeStore`. It has the same root
+/// and paths as the `MerkleTree` of the same height built by `MerkleTree::blank` and
+/// updated with the same leaves, but only the root is kept in memory, and an update
+/// reads and writes only the nodes on the path of the updated leaf.
+pub struct StoredMerkleTree<P: Config, S = MemoryNodeStore<P>> {
+    height: usize,
+    leaf_hash_params: LeafParam<P>,
+    two_to_one_hash_params: TwoToOneParam<P>,
+    /// `empty_nodes[level - 1]` is the node at `level` above empty leaves only.
+    empty_nodes: Vec<P::InnerDigest>,
+    root: P::InnerDigest,
+    store: S,
+}
+
+impl<P: Config, S: Clone> Clone for StoredMerkleTree<P, S> {
+    fn clone(&self) -> Self {
+        Self {
+            height: self.height,
+            leaf_hash_params: self.leaf_hash_params.clone(),
+            two_to_one_hash_params: self.two_to_one_hash_params.clone(),
+            empty_nodes: self.empty_nodes.clone(),
+            root: self.root.clone(),
+            store: self.store.clone(),
+        }
+    }
+}
+
+impl<P: Config, S: NodeStore<P> + Default> StoredMerkleTree<P, S> {
+    /// Create an empty tree with `2^(height - 1)` leaves, as in `MerkleTree::blank`,
+    /// in an empty store.
+    pub fn blank(
+        leaf_hash_params: &LeafParam<P>,
+        two_to_one_hash_params: &TwoToOneParam<P>,
+        height: usize,
+    ) -> Result<Self, Error> {
+        Self::new(
+            leaf_hash_params,
+            two_to_one_hash_params,
+            height,
+            S::default(),
+        )
+    }
+}
+
+impl<P: Config, S: NodeStore<P>> StoredMerkleTree<P, S> {
+    /// Open the tree with `2^(height - 1)` leaves whose nodes are in `store`. The tree
+    /// of an empty store is blank. Fails if `store` holds a tree of another height.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `height` is less than 2.
+    pub fn new(
+        leaf_hash_params: &LeafParam<P>,
+        two_to_one_hash_params: &TwoToOneParam<P>,
+        height: usize,
+        store: S,
+    ) -> Result<Self, Error> {
+        assert!(height > 1, "a Merkle tree has at least two leaves");
+        if store.height().is_some_and(|stored| stored != height) {
+            return Err("the store holds a tree of another height".into());
+        }
+        let empty_leaf = P::LeafDigest::default();
+        let mut empty_nodes = vec![P::TwoToOneHash::evaluate(
+            two_to_one_hash_params,
+            P::LeafInnerDigestConverter::convert(empty_leaf.clone())?,
+            P::LeafInnerDigestConverter::convert(empty_leaf)?,
+        )?];
+        for level in 1..height - 1 {
+            let child = &empty_nodes[level - 1];
+            let node = P::TwoToOneHash::compress(two_to_one_hash_params, child, child)?;
+            empty_nodes.push(node);
+        }
+        let root = match store.node(height - 1, 0)? {
+            Some(root) => root,
+            None => empty_nodes[height - 2].clone(),
+        };
+        Ok(Self {
+            height,
+            leaf_hash_params: leaf_hash_params.clone(),
+            two_to_one_hash_params: two_to_one_hash_params.clone(),
+            empty_nodes,
+            root,
+            store,
+        })
+    }
+
+    /// Return the height of the tree, including the leaves and the root.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn root(&self) -> P::InnerDigest {
+        self.root.clone()
+    }
+
+    /// Return the store of the nodes of the tree.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Return the leaf digest at `index`, which is the default digest if it was never
+    /// written.
+    fn leaf(&self, index: usize) -> Result<P::LeafDigest, Error> {
+        Ok(self.store.leaf(index)?.unwrap_or_default())
+    }
+
+    /// Return the node at `index` within `level`, which is the empty node if it was
+    /// never written.
+    fn node(&self, level: usize, index: usize) -> Result<P::InnerDigest, Error> {
+        match self.store.node(level, index)? {
+            Some(node) => Ok(node),
+            None => Ok(self.empty_nodes[level - 1].clone()),
+        }
+    }
+
+    fn check_index(&self, index: usize) -> Result<(), Error> {
+        if index >> (self.height - 1) != 0 {
+            return Err("the leaf index is out of range".into());
+        }
+        Ok(())
+    }
+
+    /// Return the path of the leaf at `index`.
+    pub fn generate_proof(&self, index: usize) -> Result<Path<P>, Error> {
+        self.check_index(index)?;
+        let auth_path = (1..self.height - 1)
+            .rev()
+            .map(|level| self.node(level, (index >> level) ^ 1))
+            .collect::<Result<_, _>>()?;
+        Ok(Path {
+            leaf_sibling_hash: self.leaf(index ^ 1)?,
+            auth_path,
+            leaf_index: index,
+        })
+    }
+
+    /// Replace the leaf at `index` by `new_leaf`, writing only the nodes on its path
+    /// to the store. Returns the nodes that were overwritten, as they were before the
+    /// update.
+    pub fn update(&mut self, index: usize, new_leaf: &P::Leaf) -> Result<NodeBatch<P>, Error> {
+        self.check_index(index)?;
+        let digest = P::LeafHash::evaluate(&self.leaf_hash_params, new_leaf)?;
+        let mut batch = NodeBatch::default();
+        let mut overwritten = NodeBatch::default();
+        overwritten.leaves.insert(index, self.leaf(index)?);
+        batch.leaves.insert(index, digest.clone());
+
+        let sibling = self.leaf(index ^ 1)?;
+        let (left, right) = if index & 1 == 0 {
+            (digest, sibling)
+        } else {
+            (sibling, digest)
+        };
+        let mut node = P::TwoToOneHash::evaluate(
+            &self.two_to_one_hash_params,
+            P::LeafInnerDigestConverter::convert(left)?,
+            P::LeafInnerDigestConverter::convert(right)?,
+        )?;
+        for level in 1..self.height {
+            let position = index >> level;
+            overwritten
+                .nodes
+                .insert((level, position), self.node(level, position)?);
+            batch.nodes.insert((level, position), node.clone());
+            if level == self.height - 1 {
+                break;
+            }
+            let sibling = self.node(level, position ^ 1)?;
+            let (left, right) = if position & 1 == 0 {
+                (node, sibling)
+            } else {
+                (sibling, node)
+            };
+            node = P::TwoToOneHash::compress(&self.two_to_one_hash_params, left, right)?;
+        }
+        self.store.write(&batch)?;
+        self.root = node;
+        Ok(overwritten)
+    }
+
+    /// Return a copy-on-write view of the tree. Updates to the view are kept in memory,
+    /// and leave the tree unchanged.
+    pub fn overlay(&self) -> StoredMerkleTree<P, OverlayNodeStore<'_, P, S>> {
+        self.rewind(std::iter::empty())
+    }
+
+    /// Return a view of the tree before successive updates, given the batches of nodes
+    /// that they overwrote, in the order of the updates.
+    pub fn rewind<'a>(
+        &'a self,
+        overwritten: impl IntoIterator<Item = &'a NodeBatch<P>>,
+    ) -> StoredMerkleTree<P, OverlayNodeStore<'a, P, S>> {
+        let layers: Vec<_> = overwritten.into_iter().collect();
+        // Every update overwrites the root.
+        let root = layers
+            .first()
+            .and_then(|nodes| nodes.nodes.get(&(self.height - 1, 0)))
+            .unwrap_or(&self.root)
+            .clone();
+        let store = OverlayNodeStore {
+            written: NodeBatch::default(),
+            layers,
+            base: &self.store,
+        };
+        StoredMerkleTree {
+            height: self.height,
+            leaf_hash_params: self.leaf_hash_params.clone(),
+            two_to_one_hash_params: self.two_to_one_hash_params.clone(),
+            empty_nodes: self.empty_nodes.clone(),
+            root,
+            store,
+        }
+    }
+}
+
+impl<P: SyncConfig, S: NodeStore<P>> StoredMerkleTree<P, S> {
+    /// Replace the leaves at the indices of `updates` by the matching leaves, where later
+    /// updates of the same leaf replace earlier ones. Every node above an updated leaf
+    /// is computed exactly once, level by level, and the nodes of a level are hashed in
+    /// parallel if the `parallel` feature is enabled. The changed nodes are written to
+    /// the store at once. Returns the nodes that were overwritten, as they were before
+    /// the update.
+    pub fn update_batch<L: Borrow<P::Leaf> + Sync>(
+        &mut self,
+        updates: &[(usize, L)],
+    ) -> Result<NodeBatch<P>, Error> {
+        self.update_batch_with_records(updates, BTreeMap::new())
+    }
+
+    /// Update the leaves as `update_batch` does, and write `records` to the store with
+    /// the changed nodes, so that the store persists both or neither.
+    pub fn update_batch_with_records<L: Borrow<P::Leaf> + Sync>(
+        &mut self,
+        updates: &[(usize, L)],
+        records: BTreeMap<u64, Vec<u8>>,
+    ) -> Result<NodeBatch<P>, Error> {
+        let leaves: BTreeMap<usize, &L> =
+            updates.iter().map(|(index, leaf)| (*index, leaf)).collect();
+        for &index in leaves.keys() {
+            self.check_index(index)?;
+        }
+        let mut batch = NodeBatch {
+            records,
+            ..NodeBatch::default()
+        };
+        let mut overwritten = NodeBatch::default();
+        if leaves.is_empty() {
+            if !batch.is_empty() {
+                self.store.write(&batch)?;
+            }
+            return Ok(overwritten);
+        }
+        // Errors are not `Send`, so they are carried out of the thread pool as strings.
+        let leaf_hash_params = &self.leaf_hash_params;
+        let digests: Vec<(usize, P::LeafDigest)> =
+            cfg_into_iter!(leaves.into_iter().collect::<Vec<_>>())
+                .map(|(index, leaf)| {
+                    let digest = P::LeafHash::evaluate(leaf_hash_params, (*leaf).borrow());
+                    digest
+                        .map(|digest| (index, digest))
+                        .map_err(|e| e.to_string())
+                })
+                .collect::<Result<_, String>>()?;
+        for (index, digest) in &digests {
+            overwritten.leaves.insert(*index, self.leaf(*index)?);
+            batch.leaves.insert(*index, digest.clone());
+        }
+
+        let two_to_one_hash_params = &self.two_to_one_hash_params;
+        let pairs = pair_siblings(digests, |index| self.leaf(index))?;
+        let mut nodes: Vec<(usize, P::InnerDigest)> = cfg_into_iter!(pairs)
+            .map(|(index, left, right)| {
+                let node = || {
+                    P::TwoToOneHash::evaluate(
+                        two_to_one_hash_params,
+                        P::LeafInnerDigestConverter::convert(left)?,
+                        P::LeafInnerDigestConverter::convert(right)?,
+                    )
+                };
+                node().map(|node| (index, node)).map_err(|e| e.to_string())
+            })
+            .collect::<Result<_, String>>()?;
+        for level in 1..self.height {
+            for (index, node) in &nodes {
+                overwritten
+                    .nodes
+                    .insert((level, *index), self.node(level, *index)?);
+                batch.nodes.insert((level, *index), node.clone());
+            }
+            if level == self.height - 1 {
+                break;
+            }
+            let pairs = pair_siblings(nodes, |index| self.node(level, index))?;
+            nodes = cfg_into_iter!(pairs)
+                .map(|(index, left, right)| {
+                    let node = P::TwoToOneHash::compress(two_to_one_hash_params, left, right);
+                    node.map(|node| (index, node)).map_err(|e| e.to_string())
+                })
+                .collect::<Result<_, String>>()?;
+        }
+        self.store.write(&batch)?;
+        self.root = nodes.pop().expect("the root is updated").1;
+        Ok(overwritten)
+    }
+}
+
+/// Pair up the updated `nodes` of a level, sorted by index, with their siblings, which
+/// are read with `sibling` unless they are updated too. Returns the index of the parent
+/// of each pair, followed by its left and right children.
+fn pair_siblings<D>(
+    nodes: Vec<(usize, D)>,
+    mut sibling: impl FnMut(usize) -> Result<D, Error>,
+) -> Result<Vec<(usize, D, D)>, Error> {
+    let mut pairs = Vec::with_capacity(nodes.len());
+    let mut nodes = nodes.into_iter().peekable();
+    while let Some((index, node)) = nodes.next() {
+        let (left, right) = if index & 1 == 1 {
+            (sibling(index ^ 1)?, node)
+        } else if let Some((_, right)) = nodes.next_if(|(next, _)| *next == index ^ 1) {
+            (node, right)
+        } else {
+            (node, sibling(index ^ 1)?)
+        };
+        pairs.push((index >> 1, left, right));
+    }
+    Ok(pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        params_digest, FileNodeStore, MemoryNodeStore, NodeBatch, NodeStore, StoredMerkleTree,
+    };
+    use crate::basic_merkle_tree::common::*;
+    use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+    use std::{collections::BTreeMap, fs::OpenOptions};
+
+    #[test]
+    fn stored_tree_matches_dense_tree() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafH as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <CompressH as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+        let height = 4;
+        let mut tree = StoredMerkleTree::<JubJubMerkleTreeParams>::blank(
+            &leaf_crh_params,
+            &two_to_one_crh_params,
+            height,
+        )
+        .unwrap();
+        let mut dense =
+            JubJubMerkleTree::blank(&leaf_crh_params, &two_to_one_crh_params, height).unwrap();
+        assert_eq!(tree.root(), dense.root());
+
+        let mut overwritten = Vec::new();
+        for (index, i) in [(3, 1u8), (4, 2), (3, 3), (0, 4)] {
+            let leaf = [i; 30];
+            overwritten.push(tree.update(index, &leaf).unwrap());
+            dense.update(index, &leaf).unwrap();
+            assert_eq!(tree.root(), dense.root());
+            for index in 0..8 {
+                let path = tree.generate_proof(index).unwrap();
+                let expected = dense.generate_proof(index).unwrap();
+                assert_eq!(path.leaf_sibling_hash, expected.leaf_sibling_hash);
+                assert_eq!(path.auth_path, expected.auth_path);
+                assert_eq!(path.leaf_index, expected.leaf_index);
+            }
+        }
+        // An update writes one node per level.
+        assert_eq!(overwritten[0].leaves.len(), 1);
+        assert_eq!(overwritten[0].nodes.len(), height - 1);
+        assert!(tree.generate_proof(8).is_err());
+        assert!(tree.update(8, &[0u8; 30]).is_err());
+
+        // Rewinding the last two updates restores the root after the first two.
+        let mut expected =
+            JubJubMerkleTree::blank(&leaf_crh_params, &two_to_one_crh_params, height).unwrap();
+        expected.update(3, &[1u8; 30]).unwrap();
+        expected.update(4, &[2u8; 30]).unwrap();
+        let rewound = tree.rewind(&overwritten[2..]);
+        assert_eq!(rewound.root(), expected.root());
+        let path = rewound.generate_proof(3).unwrap();
+        assert!(path
+            .verify(
+                &leaf_crh_params,
+                &two_to_one_crh_params,
+                &expected.root(),
+                [1u8; 30]
+            )
+            .unwrap());
+
+        // Updates to an overlay leave the tree unchanged.
+        let root = tree.root();
+        let mut overlay = tree.overlay();
+        overlay.update(7, &[5u8; 30]).unwrap();
+        dense.update(7, &[5u8; 30]).unwrap();
+        assert_eq!(overlay.root(), dense.root());
+        assert_eq!(tree.root(), root);
+    }
+
+    #[test]
+    fn batch_update_matches_sequential_updates() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafH as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <CompressH as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+        let height = 5;
+        let mut tree = StoredMerkleTree::<JubJubMerkleTreeParams>::blank(
+            &leaf_crh_params,
+            &two_to_one_crh_params,
+            height,
+        )
+        .unwrap();
+        let mut sequential = tree.clone();
+        assert!(tree.update_batch::<&[u8]>(&[]).unwrap().is_empty());
+
+        // Siblings, a leaf updated twice, and leaves in both halves of the tree.
+        let leaves: Vec<Vec<u8>> = (0..6u8).map(|i| vec![i; 30]).collect();
+        let updates = [
+            (4, leaves[0].as_slice()),
+            (5, leaves[1].as_slice()),
+            (14, leaves[2].as_slice()),
+            (4, leaves[3].as_slice()),
+            (0, leaves[4].as_slice()),
+            (15, leaves[5].as_slice()),
+        ];
+        let overwritten = tree.update_batch(&updates).unwrap();
+        for (index, leaf) in updates {
+            sequential.update(index, leaf).unwrap();
+        }
+        assert_eq!(tree.root(), sequential.root());
+        for index in 0..16 {
+            let path = tree.generate_proof(index).unwrap();
+            let expected = sequential.generate_proof(index).unwrap();
+            assert_eq!(path.leaf_sibling_hash, expected.leaf_sibling_hash);
+            assert_eq!(path.auth_path, expected.auth_path);
+        }
+        // Each changed node is written once: the parents of leaves 0, 4-5 and 14-15,
+        // then nodes 0, 1 and 3 of level 2, nodes 0 and 1 of level 3, and the root.
+        assert_eq!(overwritten.leaves.len(), 5);
+        assert_eq!(overwritten.nodes.len(), 3 + 3 + 2 + 1);
+        assert!(tree.update_batch(&[(16, leaves[0].as_slice())]).is_err());
+
+        // Rewinding the batch restores the blank tree.
+        let blank = StoredMerkleTree::<JubJubMerkleTreeParams>::blank(
+            &leaf_crh_params,
+            &two_to_one_crh_params,
+            height,
+        )
+        .unwrap();
+        assert_eq!(tree.rewind([&overwritten]).root(), blank.root());
+    }
+
+    #[test]
+    fn file_store_persists_nodes() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafH as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <CompressH as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nodes");
+        let height = 33;
+        let digest =
+            params_digest::<JubJubMerkleTreeParams>(&leaf_crh_params, &two_to_one_crh_params, &[1])
+                .unwrap();
+
+        let mut memory = StoredMerkleTree::<JubJubMerkleTreeParams, MemoryNodeStore<_>>::blank(
+            &leaf_crh_params,
+            &two_to_one_crh_params,
+            height,
+        )
+        .unwrap();
+        let store = FileNodeStore::<JubJubMerkleTreeParams>::open(&path, height, digest).unwrap();
+        let mut tree =
+            StoredMerkleTree::new(&leaf_crh_params, &two_to_one_crh_params, height, store).unwrap();
+        assert_eq!(tree.root(), memory.root());
+        for (index, i) in [(0, 1u8), (1 << 31, 2), ((1 << 32) - 1, 3)] {
+            tree.update(index, &[i; 30]).unwrap();
+            memory.update(index, &[i; 30]).unwrap();
+        }
+        let root = tree.root();
+        assert_eq!(root, memory.root());
+        drop(tree);
+
+        // Reopening the file restores the tree without rehashing any leaf.
+        let store = FileNodeStore::<JubJubMerkleTreeParams>::open(&path, height, digest).unwrap();
+        assert_eq!(
+            store.leaf(1 << 31).unwrap(),
+            memory.store().leaf(1 << 31).unwrap()
+        );
+        let tree =
+            StoredMerkleTree::new(&leaf_crh_params, &two_to_one_crh_params, height, store).unwrap();
+        assert_eq!(tree.root(), root);
+        let path = tree.generate_proof(1 << 31).unwrap();
+        assert!(path
+            .verify(&leaf_crh_params, &two_to_one_crh_params, &root, [2u8; 30])
+            .unwrap());
+    }
+
+    #[test]
+    fn file_store_reads_concurrently() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafH as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <CompressH as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+        let digest =
+            params_digest::<JubJubMerkleTreeParams>(&leaf_crh_params, &two_to_one_crh_params, &[1])
+                .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let height = 5;
+        let store =
+            FileNodeStore::<JubJubMerkleTreeParams>::open(dir.path().join("nodes"), height, digest)
+                .unwrap();
+        let mut tree =
+            StoredMerkleTree::new(&leaf_crh_params, &two_to_one_crh_params, height, store).unwrap();
+        let leaves: Vec<(usize, [u8; 30])> = (0..16).map(|i| (i, [i as u8 + 1; 30])).collect();
+        tree.update_batch(&leaves).unwrap();
+
+        // Every thread reads every node many times, and each read finds its own slot.
+        let root = tree.root();
+        let tree = &tree;
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..10 {
+                        for (index, leaf) in &leaves {
+                            let path = tree.generate_proof(*index).unwrap();
+                            assert!(path
+                                .verify(
+                                    &leaf_crh_params,
+                                    &two_to_one_crh_params,
+                                    &root,
+                                    leaf.as_slice()
+                                )
+                                .unwrap());
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn file_store_checks_its_header() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafH as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <CompressH as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+        let other_crh_params = <LeafH as CRHScheme>::setup(&mut rng).unwrap();
+        let digest =
+            params_digest::<JubJubMerkleTreeParams>(&leaf_crh_params, &two_to_one_crh_params, &[1])
+                .unwrap();
+        let other_digest = params_digest::<JubJubMerkleTreeParams>(
+            &other_crh_params,
+            &two_to_one_crh_params,
+            &[1],
+        )
+        .unwrap();
+        assert_ne!(digest, other_digest);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nodes");
+
+        // Heights whose node indices overflow 64 bits are rejected.
+        for height in [0, 1, 65, 128] {
+            assert!(FileNodeStore::<JubJubMerkleTreeParams>::open(&path, height, digest).is_err());
+        }
+        assert!(FileNodeStore::<JubJubMerkleTreeParams>::open(&path, 64, digest).is_ok());
+        // The file is reopened only with the same height and hash parameters.
+        assert!(FileNodeStore::<JubJubMerkleTreeParams>::open(&path, 63, digest).is_err());
+        assert!(FileNodeStore::<JubJubMerkleTreeParams>::open(&path, 64, other_digest).is_err());
+        let store = FileNodeStore::<JubJubMerkleTreeParams>::open(&path, 64, digest).unwrap();
+        // A tree of another height is not opened over the store.
+        assert!(
+            StoredMerkleTree::new(&leaf_crh_params, &two_to_one_crh_params, 63, store).is_err()
+        );
+    }
+
+    #[test]
+    fn file_store_recovers_interrupted_writes() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafH as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <CompressH as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+        let digest =
+            params_digest::<JubJubMerkleTreeParams>(&leaf_crh_params, &two_to_one_crh_params, &[1])
+                .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let height = 4;
+        let open = |name: &str| {
+            let store = FileNodeStore::<JubJubMerkleTreeParams>::open(
+                dir.path().join(name),
+                height,
+                digest,
+            )
+            .unwrap();
+            StoredMerkleTree::new(&leaf_crh_params, &two_to_one_crh_params, height, store).unwrap()
+        };
+
+        // The batch of an update of a tree whose leaf 3 is already written: the nodes at
+        // the positions that the update overwrites, as they are after the update.
+        let mut memory = StoredMerkleTree::<JubJubMerkleTreeParams>::blank(
+            &leaf_crh_params,
+            &two_to_one_crh_params,
+            height,
+        )
+        .unwrap();
+        memory.update(3, &[1u8; 30]).unwrap();
+        let old_root = memory.root();
+        let overwritten = memory.update(5, &[2u8; 30]).unwrap();
+        let new_root = memory.root();
+        let batch = NodeBatch {
+            leaves: overwritten
+                .leaves
+                .keys()
+                .map(|&index| (index, memory.store().leaf(index).unwrap().unwrap()))
+                .collect(),
+            nodes: overwritten
+                .nodes
+                .keys()
+                .map(|&(level, index)| {
+                    let node = memory.store().node(level, index).unwrap().unwrap();
+                    ((level, index), node)
+                })
+                .collect(),
+            records: BTreeMap::from([(5, vec![2u8])]),
+        };
+
+        // A crash after the journal is saved, before or while the nodes are written:
+        // reopening the store completes the write.
+        for written_slots in [0, 2] {
+            let name = format!("written-{}", written_slots);
+            let mut tree = open(&name);
+            tree.update(3, &[1u8; 30]).unwrap();
+            let store = tree.store();
+            let journal = store.journal(&batch).unwrap();
+            store.save_journal(&journal).unwrap();
+            let mut file = &store.file;
+            for (offset, slot) in &journal.slots[..written_slots] {
+                std::io::Seek::seek(&mut file, std::io::SeekFrom::Start(*offset)).unwrap();
+                std::io::Write::write_all(&mut file, slot).unwrap();
+            }
+            drop(tree);
+            let tree = open(&name);
+            assert_eq!(tree.root(), new_root);
+            assert_eq!(tree.store().records().unwrap(), batch.records);
+            assert!(!dir.path().join(&name).with_extension("journal").exists());
+        }
+
+        // A crash while the journal is saved: reopening the store discards the write.
+        let mut tree = open("torn");
+        tree.update(3, &[1u8; 30]).unwrap();
+        let store = tree.store();
+        store.save_journal(&store.journal(&batch).unwrap()).unwrap();
+        let journal_path = dir.path().join("torn").with_extension("journal");
+        let file = OpenOptions::new().write(true).open(&journal_path).unwrap();
+        file.set_len(file.metadata().unwrap().len() - 1).unwrap();
+        drop(tree);
+        let tree = open("torn");
+        assert_eq!(tree.root(), old_root);
+        assert!(tree.store().records().unwrap().is_empty());
+        assert!(!journal_path.exists());
+    }
+}
@@ -0,0 +1,133 @@
+//! Proves and verifies the circuits of the crate with Groth16 over BLS12-381, whose
+//! scalar field is the `ConstraintF` of the circuits.
+//!
+//! The public inputs passed to `verify` must be the values of the variables allocated
+//! with `new_input`, in allocation order. `PublicInputs` packs them the way the R1CS
+//! gadgets allocate them.
+use crate::basic_merkle_tree::common::ConstraintF;
+use ark_bls12_381::Bls12_381;
+use ark_ff::ToConstraintField;
+use ark_groth16::Groth16;
+use ark_relations::r1cs::{ConstraintSynthesizer, SynthesisError};
+use ark_snark::SNARK;
+use ark_std::rand::{CryptoRng, RngCore};
+
+pub type ProvingKey = ark_groth16::ProvingKey<Bls12_381>;
+pub type VerifyingKey = ark_groth16::VerifyingKey<Bls12_381>;
+pub type Proof = ark_groth16::Proof<Bls12_381>;
+
+/// Generate the keys of the circuit with the shape of `circuit`. Its witness does not
+/// need to satisfy the circuit, but it must have the same size as the witnesses of the
+/// proofs, such as the same path length and leaf length.
+pub fn setup<C: ConstraintSynthesizer<ConstraintF>, R: RngCore + CryptoRng>(
+    circuit: C,
+    rng: &mut R,
+) -> Result<(ProvingKey, VerifyingKey), SynthesisError> {
+    Groth16::<Bls12_381>::circuit_specific_setup(circuit, rng)
+}
+
+/// Prove that the witness of `circuit` satisfies it.
+pub fn prove<C: ConstraintSynthesizer<ConstraintF>, R: RngCore + CryptoRng>(
+    pk: &ProvingKey,
+    circuit: C,
+    rng: &mut R,
+) -> Result<Proof, SynthesisError> {
+    Groth16::<Bls12_381>::prove(pk, circuit, rng)
+}
+
+/// Check `proof` against `public_inputs`.
+pub fn verify(
+    vk: &VerifyingKey,
+    public_inputs: &PublicInputs,
+    proof: &Proof,
+) -> Result<bool, SynthesisError> {
+    Groth16::<Bls12_381>::verify(vk, public_inputs.as_slice(), proof)
+}
+
+/// The public inputs of a circuit, as field elements.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PublicInputs {
+    inputs: Vec<ConstraintF>,
+}
+
+impl PublicInputs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append `value`, as allocated by `new_input` for its R1CS equivalent. Field
+    /// elements are appended as is, and twisted Edwards points, such as Pedersen
+    /// digests and `RootVar`, as their coordinates `x` then `y`.
+    pub fn push<T: ToConstraintField<ConstraintF> + ?Sized>(&mut self, value: &T) -> &mut Self {
+        self.inputs.extend(
+            value
+                .to_field_elements()
+                .expect("the value should be representable in the constraint field"),
+        );
+        self
+    }
+
+    /// Append `bytes`, as allocated by `UInt8::new_input_vec`: packed little-endian into
+    /// field elements of 31 bytes each.
+    pub fn push_bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.push(bytes)
+    }
+
+    pub fn as_slice(&self) -> &[ConstraintF] {
+        &self.inputs
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{prove, setup, verify, PublicInputs};
+    use crate::basic_merkle_tree::{common::*, constraints::MerkleTreeVerification};
+    use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+    use ark_std::rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn merkle_tree_verification_proofs() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let leaf_crh_params = <LeafH as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <CompressH as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+        let leaves: Vec<Vec<u8>> = (0..4u8).map(|i| vec![i; 40]).collect();
+        let tree = JubJubMerkleTree::new(
+            &leaf_crh_params,
+            &two_to_one_crh_params,
+            leaves.iter().map(|v| v.as_slice()),
+        )
+        .unwrap();
+        let circuit = |index: usize, root: Root| MerkleTreeVerification {
+            leaf_crh_params: leaf_crh_params.clone(),
+            two_to_one_crh_params: two_to_one_crh_params.clone(),
+            root,
+            leaf: leaves[index].clone(),
+            authentication_path: Some(tree.generate_proof(index).unwrap()),
+        };
+
+        let (pk, vk) = setup(circuit(0, tree.root()), &mut rng).unwrap();
+        let honest = circuit(1, tree.root());
+        let inputs = honest.public_inputs();
+        // The root takes two field elements, and the 40 leaf bytes take two more.
+        assert_eq!(inputs.len(), 2 + 2);
+        let proof = prove(&pk, honest, &mut rng).unwrap();
+        assert!(verify(&vk, &inputs, &proof).unwrap());
+
+        // The proof fails against a tampered root or leaf.
+        let mut other_root = tree.root();
+        other_root.x += ConstraintF::from(1u8);
+        let tampered = circuit(1, other_root).public_inputs();
+        assert!(!verify(&vk, &tampered, &proof).unwrap());
+        let mut tampered = PublicInputs::new();
+        tampered.push(&tree.root()).push_bytes(&leaves[2]);
+        assert!(!verify(&vk, &tampered, &proof).unwrap());
+    }
+}
@@ -0,0 +1,247 @@
+use super::{KaryPath, TreeHash};
+use ark_r1cs_std::prelude::*;
+use ark_relations::r1cs::{Namespace, SynthesisError};
+use std::borrow::Borrow;
+
+/// The R1CS equivalent of a `KaryPath`.
+pub struct KaryPathVar<H: TreeHash, const ARITY: usize> {
+    /// `positions[level]` holds the little-endian bits of the position of the node
+    /// on the path at `level` among its siblings.
+    positions: Vec<Vec<Boolean<H::ConstraintF>>>,
+    /// `siblings[level]` holds the siblings of the node on the path at `level`, from
+    /// left to right.
+    siblings: Vec<Vec<H::DigestVar>>,
+}
+
+impl<H: TreeHash, const ARITY: usize> AllocVar<KaryPath<H, ARITY>, H::ConstraintF>
+    for KaryPathVar<H, ARITY>
+{
+    #[tracing::instrument(target = "r1cs", skip(cs, f, mode))]
+    fn new_variable<T: Borrow<KaryPath<H, ARITY>>>(
+        cs: impl Into<Namespace<H::ConstraintF>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let ns = cs.into();
+        let cs = ns.cs();
+        let value = f()?;
+        let path = value.borrow();
+        let bits_per_level = ARITY.trailing_zeros() as usize;
+        let mut positions = Vec::with_capacity(path.siblings.len());
+        let mut siblings = Vec::with_capacity(path.siblings.len());
+        for (level, level_siblings) in path.siblings.iter().enumerate() {
+            let position = path.leaf_index >> (level * bits_per_level);
+            let bits: Vec<bool> = (0..bits_per_level)
+                .map(|i| (position >> i) & 1 == 1)
+                .collect();
+            positions.push(Vec::new_variable(
+                ark_relations::ns!(cs, "position"),
+                || Ok(bits),
+                mode,
+            )?);
+            siblings.push(Vec::new_variable(
+                ark_relations::ns!(cs, "siblings"),
+                || Ok(level_siblings.as_slice()),
+                mode,
+            )?);
+        }
+        Ok(Self {
+            positions,
+            siblings,
+        })
+    }
+}
+
+impl<H: TreeHash, const ARITY: usize> KaryPathVar<H, ARITY> {
+    /// Return the little-endian bits of the index of the leaf.
+    pub fn get_leaf_position(&self) -> Vec<Boolean<H::ConstraintF>> {
+        self.positions.concat()
+    }
+
+    /// Calculate the root of the tree, assuming that `leaf` is the leaf on the path.
+    #[tracing::instrument(target = "r1cs", skip(self, leaf_hash_params, node_hash_params, leaf))]
+    pub fn calculate_root(
+        &self,
+        leaf_hash_params: &H::ParametersVar,
+        node_hash_params: &H::ParametersVar,
+        leaf: &H::LeafVar,
+    ) -> Result<H::DigestVar, SynthesisError> {
+        let mut node = H::hash_leaf_var(leaf_hash_params, leaf)?;
+        for (position, siblings) in self.positions.iter().zip(&self.siblings) {
+            if siblings.len() != ARITY - 1 {
+                return Err(SynthesisError::Unsatisfiable);
+            }
+            // `is_at[j]` is whether the node is the j-th child, and `is_after[j]` whether
+            // it comes after the j-th child.
+            let is_at = (0..ARITY)
+                .map(|j| {
+                    let bits: Vec<_> = position
+                        .iter()
+                        .enumerate()
+                        .map(|(i, bit)| {
+                            if (j >> i) & 1 == 1 {
+                                bit.clone()
+                            } else {
+                                bit.not()
+                            }
+                        })
+                        .collect();
+                    Boolean::kary_and(&bits)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let mut is_after = vec![Boolean::FALSE; ARITY];
+            for j in (0..ARITY - 1).rev() {
+                is_after[j] = is_after[j + 1].or(&is_at[j + 1])?;
+            }
+            // The j-th child is the node, the j-th sibling if the node comes after it,
+            // and the sibling before otherwise.
+            let children = (0..ARITY)
+                .map(|j| {
+                    let child = match j {
+                        0 => node.clone(),
+                        _ => is_at[j].select(&node, &siblings[j - 1])?,
+                    };
+                    match siblings.get(j) {
+                        Some(sibling) => is_after[j].select(sibling, &child),
+                        None => Ok(child),
+                    }
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            node = H::hash_children_var(node_hash_params, &children)?;
+        }
+        Ok(node)
+    }
+
+    /// Check that `leaf` is the leaf on the path in the tree with root `root`.
+    #[tracing::instrument(
+        target = "r1cs",
+        skip(self, leaf_hash_params, node_hash_params, root, leaf)
+    )]
+    pub fn verify_membership(
+        &self,
+        leaf_hash_params: &H::ParametersVar,
+        node_hash_params: &H::ParametersVar,
+        root: &H::DigestVar,
+        leaf: &H::LeafVar,
+    ) -> Result<Boolean<H::ConstraintF>, SynthesisError> {
+        self.calculate_root(leaf_hash_params, node_hash_params, leaf)?
+            .is_eq(root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::KaryPathVar;
+    use crate::{
+        basic_merkle_tree::common::ConstraintF,
+        kary_merkle_tree::{KaryMerkleTree, PedersenTreeHash, PoseidonTreeHash, TreeHash},
+        profile::{assert_budget, profile_with, ConstraintCounts},
+    };
+    use ark_r1cs_std::{fields::fp::FpVar, prelude::*, uint64::UInt64};
+    use ark_relations::{ns, r1cs::ConstraintSystem};
+
+    /// Verify paths of a Poseidon tree of 64 leaves in a circuit, and return the size
+    /// of the circuit, which is the same for every path.
+    fn check_poseidon_paths<const ARITY: usize>() -> ConstraintCounts {
+        let params = PoseidonTreeHash::setup(&mut ark_std::test_rng()).unwrap();
+        let leaves: Vec<Vec<ConstraintF>> =
+            (0..64u64).map(|i| vec![ConstraintF::from(i)]).collect();
+        let tree = KaryMerkleTree::<PoseidonTreeHash, ARITY>::new(
+            &params,
+            &params,
+            leaves.iter().map(Vec::as_slice),
+        )
+        .unwrap();
+        let mut counts = None;
+        for index in [0, 37, 63] {
+            let (leaf, path) = (&leaves[index], tree.generate_proof(index).unwrap());
+            let (is_satisfied, report) = profile_with(|cs| {
+                let params_var = <PoseidonTreeHash as TreeHash>::ParametersVar::new_constant(
+                    ns!(cs, "params"),
+                    &params,
+                )?;
+                let root = FpVar::new_input(ns!(cs, "root"), || Ok(tree.root()))?;
+                let leaf = Vec::<FpVar<_>>::new_witness(ns!(cs, "leaf"), || Ok(leaf.clone()))?;
+                let path =
+                    KaryPathVar::<PoseidonTreeHash, ARITY>::new_witness(ns!(cs, "path"), || {
+                        Ok(&path)
+                    })?;
+                let index_bits = UInt64::constant(index as u64).to_bits_le();
+                for (bit, index_bit) in path.get_leaf_position().iter().zip(&index_bits) {
+                    bit.enforce_equal(index_bit)?;
+                }
+                path.verify_membership(&params_var, &params_var, &root, &leaf)?
+                    .enforce_equal(&Boolean::TRUE)?;
+                cs.is_satisfied()
+            })
+            .unwrap();
+            assert!(is_satisfied, "the path of leaf {index} is rejected");
+            assert_eq!(*counts.get_or_insert(report.total), report.total);
+        }
+        counts.unwrap()
+    }
+
+    #[test]
+    fn poseidon_depth_versus_width() {
+        // Budgets of the circuit verifying a path in trees of the same size but of
+        // different arities. Lower the numbers when a change makes a circuit smaller.
+        for (arity, counts, constraints, witnesses) in [
+            (2, check_poseidon_paths::<2>(), 1913, 1912),
+            (4, check_poseidon_paths::<4>(), 1952, 1954),
+            (8, check_poseidon_paths::<8>(), 2543, 2550),
+        ] {
+            println!("arity {arity}: {counts:?}");
+            assert_budget(
+                counts,
+                ConstraintCounts {
+                    constraints,
+                    witnesses,
+                    public_inputs: 1,
+                },
+            );
+        }
+    }
+
+    #[test]
+    fn pedersen_path_gadget_rejects_another_leaf() {
+        let mut rng = ark_std::test_rng();
+        let leaf_params = PedersenTreeHash::setup(&mut rng).unwrap();
+        let node_params = PedersenTreeHash::setup(&mut rng).unwrap();
+        let leaves: Vec<Vec<u8>> = (0..16u8).map(|i| vec![i; 30]).collect();
+        let tree = KaryMerkleTree::<PedersenTreeHash, 4>::new(
+            &leaf_params,
+            &node_params,
+            leaves.iter().map(|v| v.as_slice()),
+        )
+        .unwrap();
+        let path = tree.generate_proof(6).unwrap();
+
+        for (leaf, expected) in [(&leaves[6], true), (&leaves[7], false)] {
+            let cs = ConstraintSystem::<ConstraintF>::new_ref();
+            let leaf_params_var = <PedersenTreeHash as TreeHash>::ParametersVar::new_constant(
+                ns!(cs, "leaf_params"),
+                &leaf_params,
+            )
+            .unwrap();
+            let node_params_var = <PedersenTreeHash as TreeHash>::ParametersVar::new_constant(
+                ns!(cs, "node_params"),
+                &node_params,
+            )
+            .unwrap();
+            let root =
+                <PedersenTreeHash as TreeHash>::DigestVar::new_input(ns!(cs, "root"), || {
+                    Ok(tree.root())
+                })
+                .unwrap();
+            let leaf = UInt8::new_witness_vec(ns!(cs, "leaf"), leaf).unwrap();
+            let path =
+                KaryPathVar::<PedersenTreeHash, 4>::new_witness(ns!(cs, "path"), || Ok(&path))
+                    .unwrap();
+            path.verify_membership(&leaf_params_var, &node_params_var, &root, &leaf)
+                .unwrap()
+                .enforce_equal(&Boolean::TRUE)
+                .unwrap();
+            assert_eq!(cs.is_satisfied().unwrap(), expected);
+        }
+    }
+}
@@ -0,0 +1,405 @@
+use crate::basic_merkle_tree::{common::ConstraintF, poseidon::poseidon_parameters};
+use ark_crypto_primitives::{
+    crh::{pedersen, poseidon, CRHScheme, CRHSchemeGadget},
+    sponge::poseidon::PoseidonConfig,
+    to_uncompressed_bytes, Error,
+};
+use ark_ed_on_bls12_381::{constraints::EdwardsVar, EdwardsAffine, EdwardsProjective as JubJub};
+use ark_ff::PrimeField;
+use ark_r1cs_std::{fields::fp::FpVar, prelude::*};
+use ark_relations::r1cs::SynthesisError;
+use ark_serialize::CanonicalSerialize;
+use ark_std::rand::Rng;
+use std::{borrow::Borrow, fmt::Debug};
+
+pub mod constraints;
+
+/// A hash function for the leaves and the internal nodes of a `KaryMerkleTree`,
+/// together with its R1CS equivalent.
+pub trait TreeHash {
+    type ConstraintF: PrimeField;
+    type Parameters: Clone;
+    type Leaf: ?Sized;
+    type Digest: Clone + Eq + Debug;
+    /// The R1CS equivalent of `Self::Parameters`.
+    type ParametersVar: AllocVar<Self::Parameters, Self::ConstraintF>;
+    /// The R1CS equivalent of `Self::Leaf`.
+    type LeafVar: ?Sized;
+    /// The R1CS equivalent of `Self::Digest`.
+    type DigestVar: AllocVar<Self::Digest, Self::ConstraintF>
+        + EqGadget<Self::ConstraintF>
+        + CondSelectGadget<Self::ConstraintF>
+        + R1CSVar<Self::ConstraintF>
+        + Clone;
+
+    fn setup<R: Rng>(rng: &mut R) -> Result<Self::Parameters, Error>;
+
+    fn hash_leaf(params: &Self::Parameters, leaf: &Self::Leaf) -> Result<Self::Digest, Error>;
+
+    /// Hash the children of an internal node, from left to right.
+    fn hash_children(
+        params: &Self::Parameters,
+        children: &[Self::Digest],
+    ) -> Result<Self::Digest, Error>;
+
+    fn hash_leaf_var(
+        params: &Self::ParametersVar,
+        leaf: &Self::LeafVar,
+    ) -> Result<Self::DigestVar, SynthesisError>;
+
+    fn hash_children_var(
+        params: &Self::ParametersVar,
+        children: &[Self::DigestVar],
+    ) -> Result<Self::DigestVar, SynthesisError>;
+}
+
+/// Windows large enough for Pedersen to hash the uncompressed encodings of 8
+/// children.
+#[derive(Clone)]
+pub struct Window4x1024;
+impl pedersen::Window for Window4x1024 {
+    const WINDOW_SIZE: usize = 4;
+    const NUM_WINDOWS: usize = 1024;
+}
+
+/// Pedersen over JubJub, as in `basic_merkle_tree::common`, with larger windows.
+/// Leaves are bytes, and internal nodes hash the uncompressed encodings of their
+/// children.
+#[derive(Copy, Clone, Debug)]
+pub struct PedersenTreeHash;
+
+type PedersenH = pedersen::CRH<JubJub, Window4x1024>;
+type PedersenHG = pedersen::constraints::CRHGadget<JubJub, EdwardsVar, Window4x1024>;
+
+impl TreeHash for PedersenTreeHash {
+    type ConstraintF = ConstraintF;
+    type Parameters = pedersen::Parameters<JubJub>;
+    type Leaf = [u8];
+    type Digest = EdwardsAffine;
+    type ParametersVar = pedersen::constraints::CRHParametersVar<JubJub, EdwardsVar>;
+    type LeafVar = [UInt8<ConstraintF>];
+    type DigestVar = EdwardsVar;
+
+    fn setup<R: Rng>(rng: &mut R) -> Result<Self::Parameters, Error> {
+        PedersenH::setup(rng)
+    }
+
+    fn hash_leaf(params: &Self::Parameters, leaf: &[u8]) -> Result<EdwardsAffine, Error> {
+        PedersenH::evaluate(params, leaf)
+    }
+
+    fn hash_children(
+        params: &Self::Parameters,
+        children: &[EdwardsAffine],
+    ) -> Result<EdwardsAffine, Error> {
+        let mut bytes = Vec::new();
+        for child in children {
+            bytes.extend(to_uncompressed_bytes!(child)?);
+        }
+        PedersenH::evaluate(params, bytes)
+    }
+
+    fn hash_leaf_var(
+        params: &Self::ParametersVar,
+        leaf: &[UInt8<ConstraintF>],
+    ) -> Result<EdwardsVar, SynthesisError> {
+        PedersenHG::evaluate(params, leaf)
+    }
+
+    fn hash_children_var(
+        params: &Self::ParametersVar,
+        children: &[EdwardsVar],
+    ) -> Result<EdwardsVar, SynthesisError> {
+        let mut bytes = Vec::new();
+        for child in children {
+            bytes.extend(child.to_bytes()?);
+        }
+        PedersenHG::evaluate(params, &bytes)
+    }
+}
+
+/// Poseidon with the parameters of `basic_merkle_tree::poseidon`. Leaves are field
+/// elements, and internal nodes hash their children in a single sponge.
+#[derive(Copy, Clone, Debug)]
+pub struct PoseidonTreeHash;
+
+type PoseidonH = poseidon::CRH<ConstraintF>;
+type PoseidonHG = poseidon::constraints::CRHGadget<ConstraintF>;
+
+impl TreeHash for PoseidonTreeHash {
+    type ConstraintF = ConstraintF;
+    type Parameters = PoseidonConfig<ConstraintF>;
+    type Leaf = [ConstraintF];
+    type Digest = ConstraintF;
+    type ParametersVar = poseidon::constraints::CRHParametersVar<ConstraintF>;
+    type LeafVar = [FpVar<ConstraintF>];
+    type DigestVar = FpVar<ConstraintF>;
+
+    /// The parameters are fixed, so `rng` is unused.
+    fn setup<R: Rng>(_rng: &mut R) -> Result<Self::Parameters, Error> {
+        Ok(poseidon_parameters())
+    }
+
+    fn hash_leaf(params: &Self::Parameters, leaf: &[ConstraintF]) -> Result<ConstraintF, Error> {
+        PoseidonH::evaluate(params, leaf)
+    }
+
+    fn hash_children(
+        params: &Self::Parameters,
+        children: &[ConstraintF],
+    ) -> Result<ConstraintF, Error> {
+        PoseidonH::evaluate(params, children)
+    }
+
+    fn hash_leaf_var(
+        params: &Self::ParametersVar,
+        leaf: &[FpVar<ConstraintF>],
+    ) -> Result<FpVar<ConstraintF>, SynthesisError> {
+        PoseidonHG::evaluate(params, leaf)
+    }
+
+    fn hash_children_var(
+        params: &Self::ParametersVar,
+        children: &[FpVar<ConstraintF>],
+    ) -> Result<FpVar<ConstraintF>, SynthesisError> {
+        PoseidonHG::evaluate(params, children)
+    }
+}
+
+/// A Merkle tree whose internal nodes have `ARITY` children, hashed with `H`. The
+/// arity must be a power of two, so that the bits of a leaf index are the
+/// positions of the nodes on its path, `log2(ARITY)` bits per level.
+///
+/// A wider tree is shallower, so its paths hash fewer nodes, but each node hashes
+/// more children and each path holds `ARITY - 1` siblings per level.
+pub struct KaryMerkleTree<H: TreeHash, const ARITY: usize> {
+    leaf_hash_params: H::Parameters,
+    node_hash_params: H::Parameters,
+    /// `levels[0]` holds the digests of the leaves, and each following level the
+    /// internal nodes above it, up to the root.
+    levels: Vec<Vec<H::Digest>>,
+}
+
+impl<H: TreeHash, const ARITY: usize> KaryMerkleTree<H, ARITY> {
+    /// Build the tree of `leaves`, whose number must be a power of `ARITY` greater
+    /// than one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ARITY` is not a power of two greater than one.
+    pub fn new<L: Borrow<H::Leaf>>(
+        leaf_hash_params: &H::Parameters,
+        node_hash_params: &H::Parameters,
+        leaves: impl IntoIterator<Item = L>,
+    ) -> Result<Self, Error> {
+        assert!(
+            ARITY > 1 && ARITY.is_power_of_two(),
+            "the arity of a Merkle tree must be a power of two greater than one"
+        );
+        let leaf_digests = leaves
+            .into_iter()
+            .map(|leaf| H::hash_leaf(leaf_hash_params, leaf.borrow()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut num_nodes = leaf_digests.len();
+        while num_nodes > 1 && num_nodes % ARITY == 0 {
+            num_nodes /= ARITY;
+        }
+        if leaf_digests.len() < ARITY || num_nodes != 1 {
+            return Err(format!(
+                "the number of leaves must be a power of {ARITY}, not {}",
+                leaf_digests.len()
+            )
+            .into());
+        }
+
+        let mut levels = vec![leaf_digests];
+        while levels.last().unwrap().len() > 1 {
+            let parents = levels
+                .last()
+                .unwrap()
+                .chunks(ARITY)
+                .map(|children| H::hash_children(node_hash_params, children))
+                .collect::<Result<_, _>>()?;
+            levels.push(parents);
+        }
+        Ok(Self {
+            leaf_hash_params: leaf_hash_params.clone(),
+            node_hash_params: node_hash_params.clone(),
+            levels,
+        })
+    }
+
+    /// Return the height of the tree, including the leaves and the root.
+    pub fn height(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn root(&self) -> H::Digest {
+        self.levels.last().unwrap()[0].clone()
+    }
+
+    pub fn generate_proof(&self, index: usize) -> Result<KaryPath<H, ARITY>, Error> {
+        if index >= self.levels[0].len() {
+            return Err(format!("the tree has no leaf at index {index}").into());
+        }
+        let siblings = self.levels[..self.height() - 1]
+            .iter()
+            .enumerate()
+            .map(|(level, nodes)| {
+                let node = index / ARITY.pow(level as u32);
+                let first_child = node - node % ARITY;
+                (first_child..first_child + ARITY)
+                    .filter(|&child| child != node)
+                    .map(|child| nodes[child].clone())
+                    .collect()
+            })
+            .collect();
+        Ok(KaryPath {
+            leaf_index: index,
+            siblings,
+        })
+    }
+
+    /// Replace the leaf at `index` by `new_leaf`, and rehash the path above it.
+    pub fn update(&mut self, index: usize, new_leaf: &H::Leaf) -> Result<(), Error> {
+        if index >= self.levels[0].len() {
+            return Err(format!("the tree has no leaf at index {index}").into());
+        }
+        self.levels[0][index] = H::hash_leaf(&self.leaf_hash_params, new_leaf)?;
+        let mut node = index;
+        for level in 1..self.height() {
+            let first_child = node - node % ARITY;
+            let children = &self.levels[level - 1][first_child..first_child + ARITY];
+            let parent = H::hash_children(&self.node_hash_params, children)?;
+            node /= ARITY;
+            self.levels[level][node] = parent;
+        }
+        Ok(())
+    }
+}
+
+/// The path of a leaf of a `KaryMerkleTree`.
+pub struct KaryPath<H: TreeHash, const ARITY: usize> {
+    pub leaf_index: usize,
+    /// `siblings[level]` holds the `ARITY - 1` siblings of the node on the path at
+    /// `level`, from left to right, where the leaves are at level 0.
+    pub siblings: Vec<Vec<H::Digest>>,
+}
+
+impl<H: TreeHash, const ARITY: usize> Clone for KaryPath<H, ARITY> {
+    fn clone(&self) -> Self {
+        Self {
+            leaf_index: self.leaf_index,
+            siblings: self.siblings.clone(),
+        }
+    }
+}
+
+impl<H: TreeHash, const ARITY: usize> KaryPath<H, ARITY> {
+    /// Check that `leaf` is the leaf at `self.leaf_index` in the tree with root
+    /// `root`.
+    pub fn verify(
+        &self,
+        leaf_hash_params: &H::Parameters,
+        node_hash_params: &H::Parameters,
+        root: &H::Digest,
+        leaf: &H::Leaf,
+    ) -> Result<bool, Error> {
+        let num_leaves = (ARITY as u128).checked_pow(self.siblings.len() as u32);
+        if self
+            .siblings
+            .iter()
+            .any(|siblings| siblings.len() != ARITY - 1)
+            || num_leaves.is_none_or(|num_leaves| self.leaf_index as u128 >= num_leaves)
+        {
+            return Ok(false);
+        }
+        let mut node = H::hash_leaf(leaf_hash_params, leaf)?;
+        let mut index = self.leaf_index;
+        for siblings in &self.siblings {
+            let position = index % ARITY;
+            let mut children = siblings.clone();
+            children.insert(position, node);
+            node = H::hash_children(node_hash_params, &children)?;
+            index /= ARITY;
+        }
+        Ok(&node == root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KaryMerkleTree, PedersenTreeHash, PoseidonTreeHash, TreeHash};
+    use crate::basic_merkle_tree::common::ConstraintF;
+
+    fn check_tree<const ARITY: usize>(num_leaves: usize) {
+        let mut rng = ark_std::test_rng();
+        let leaf_params = PedersenTreeHash::setup(&mut rng).unwrap();
+        let node_params = PedersenTreeHash::setup(&mut rng).unwrap();
+        let leaves: Vec<Vec<u8>> = (0..num_leaves as u8).map(|i| vec![i; 30]).collect();
+        let mut tree = KaryMerkleTree::<PedersenTreeHash, ARITY>::new(
+            &leaf_params,
+            &node_params,
+            leaves.iter().map(|v| v.as_slice()),
+        )
+        .unwrap();
+        let root = tree.root();
+        for (index, leaf) in leaves.iter().enumerate() {
+            let path = tree.generate_proof(index).unwrap();
+            assert_eq!(path.siblings.len(), tree.height() - 1);
+            assert!(path
+                .verify(&leaf_params, &node_params, &root, leaf)
+                .unwrap());
+            assert!(!path
+                .verify(&leaf_params, &node_params, &root, &[100u8; 30])
+                .unwrap());
+        }
+        assert!(tree.generate_proof(num_leaves).is_err());
+
+        // Updating a leaf matches rebuilding the tree.
+        let mut updated = leaves.clone();
+        updated[num_leaves - 2] = vec![100u8; 30];
+        tree.update(num_leaves - 2, &updated[num_leaves - 2])
+            .unwrap();
+        let rebuilt = KaryMerkleTree::<PedersenTreeHash, ARITY>::new(
+            &leaf_params,
+            &node_params,
+            updated.iter().map(|v| v.as_slice()),
+        )
+        .unwrap();
+        assert_eq!(tree.root(), rebuilt.root());
+        assert_ne!(tree.root(), root);
+        let path = tree.generate_proof(num_leaves - 1).unwrap();
+        assert!(path
+            .verify(
+                &leaf_params,
+                &node_params,
+                &tree.root(),
+                &leaves[num_leaves - 1]
+            )
+            .unwrap());
+    }
+
+    #[test]
+    fn kary_merkle_trees() {
+        check_tree::<2>(8);
+        check_tree::<4>(16);
+        check_tree::<8>(64);
+    }
+
+    #[test]
+    fn leaf_count_must_be_a_power_of_the_arity() {
+        let params = PoseidonTreeHash::setup(&mut ark_std::test_rng()).unwrap();
+        let leaves = |n: u64| {
+            (0..n)
+                .map(|i| vec![ConstraintF::from(i)])
+                .collect::<Vec<_>>()
+        };
+        for n in [1, 8, 32] {
+            assert!(
+                KaryMerkleTree::<PoseidonTreeHash, 4>::new(&params, &params, leaves(n)).is_err()
+            );
+        }
+        assert!(KaryMerkleTree::<PoseidonTreeHash, 4>::new(&params, &params, leaves(64)).is_ok());
+    }
+}
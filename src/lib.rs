@@ -1,8 +1,14 @@
 #![feature(test)]
 
-pub mod multiplier;
 pub mod algebra;
-pub mod merkle_tree;
 pub mod basic_merkle_tree;
+pub mod groth16;
+pub mod kary_merkle_tree;
+pub mod merkle_tree;
+pub mod multiplier;
 pub mod payment;
-pub mod rollup;
\ No newline at end of file
+pub mod poseidon;
+pub mod profile;
+pub mod rollup;
+pub mod sparse_merkle_tree;
+pub mod sum_merkle_tree;
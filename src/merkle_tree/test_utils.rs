@@ -4,6 +4,9 @@ use ark_std::{One, Zero};
 
 type F = ark_ed_on_bls12_381::Fr;
 
+/// The Poseidon configuration of the field Merkle tree tests: rate 2, the S-box `x^17`,
+/// 8 full rounds and 29 partial rounds. Its round constants are not the output of the
+/// Grain LFSR over `ed_on_bls12_381::Fr`, as `constants_are_not_grain_output` shows.
 pub(crate) fn poseidon_parameters() -> PoseidonConfig<ark_ed_on_bls12_381::Fr> {
     let full_rounds = 8;
     let partial_rounds = 29;
@@ -654,5 +657,31 @@ pub(crate) fn poseidon_parameters() -> PoseidonConfig<ark_ed_on_bls12_381::Fr> {
     PoseidonConfig::<F>::new(full_rounds, partial_rounds, alpha, mds, ark, 2, 1)
 }
 
+#[cfg(test)]
+mod tests {
+    use super::{poseidon_parameters, F};
+    use crate::poseidon::poseidon_config;
+    use ark_ff::PrimeField;
+    use ark_std::str::FromStr;
+    use num_bigint::BigUint;
+
+    #[test]
+    fn constants_are_not_grain_output() {
+        // The Grain LFSR samples round constants below the modulus, but the third round
+        // constant of the first round exceeds the modulus of `ed_on_bls12_381::Fr`, and
+        // is reduced by `from_str`.
+        let constant =
+            "12324248147325396388933912754817224521085038231095815415485781874375379288849";
+        assert!(BigUint::from_str(constant).unwrap() >= F::MODULUS.into());
+        let fixture = poseidon_parameters();
+        assert_eq!(fixture.ark[0][2], F::from_str(constant).unwrap());
+
+        let generated = poseidon_config::<F>(2, 17, 8, 29);
+        assert_eq!(generated.ark.len(), fixture.ark.len());
+        assert_ne!(generated.ark, fixture.ark);
+        assert_ne!(generated.mds, fixture.mds);
+    }
+}
+
 #[cfg(feature = "r1cs")]
 mod constraints {}
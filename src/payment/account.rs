@@ -1,6 +1,6 @@
 use ark_crypto_primitives::signature::schnorr::{PublicKey, SecretKey};
-use ark_ed_on_bls12_381::EdwardsProjective as JubJub;
-use ark_serialize::CanonicalSerialize;
+use ark_ed_on_bls12_381::{EdwardsProjective as JubJub, Fq};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 
 use super::ledger::*;
 
@@ -10,7 +10,18 @@ pub type AccountPublicKey = PublicKey<JubJub>;
 /// Account secret key used to create transaction signatures.
 pub type AccountSecretKey = SecretKey<JubJub>;
 
-#[derive(Hash, Eq, PartialEq, Copy, Clone, Ord, PartialOrd, Debug)]
+#[derive(
+    Hash,
+    Eq,
+    PartialEq,
+    Copy,
+    Clone,
+    Ord,
+    PartialOrd,
+    Debug,
+    CanonicalSerialize,
+    CanonicalDeserialize,
+)]
 pub struct AccountId(pub u8);
 
 impl AccountId {
@@ -24,17 +35,46 @@ impl AccountId {
     }
 }
 
-#[derive(Debug, Hash, Eq, PartialEq, Copy, Clone, CanonicalSerialize)]
+#[derive(Debug, Hash, Eq, PartialEq, Copy, Clone, CanonicalSerialize, CanonicalDeserialize)]
 pub struct AccountInformation {
     pub public_key: AccountPublicKey,
-    pub balance: Amount,
+    /// The balance of the account in each asset, indexed by `AssetId`.
+    pub balances: [Amount; NUM_ASSETS],
+    /// The amount of each asset that the account has locked in pending escrows,
+    /// indexed by `AssetId`. Locked funds are not part of `balances`.
+    pub locked: [Amount; NUM_ASSETS],
 }
 
-// impl AccountInformation {
-//     /// Convert the account information to bytes.
-//     pub fn to_bytes_le(&self, uncompressed_bytes:&Vec<u8>) -> Vec<u8> {
-//         let mut uncompressed_bytes = Vec::new();
-//         &self.serialize_uncompressed(&mut uncompressed_bytes);
-//         uncompressed_bytes.as_slice()
-//     }
-// }
+impl AccountInformation {
+    /// Return the balance of the account in asset `asset_id`, or `None` if the
+    /// asset identifier is invalid.
+    pub fn balance(&self, asset_id: AssetId) -> Option<Amount> {
+        self.balances.get(asset_id.0 as usize).copied()
+    }
+
+    /// Return the amount of asset `asset_id` locked in pending escrows, or `None` if
+    /// the asset identifier is invalid.
+    pub fn locked(&self, asset_id: AssetId) -> Option<Amount> {
+        self.locked.get(asset_id.0 as usize).copied()
+    }
+
+    /// Convert the account information to bytes. These bytes form the account's leaf
+    /// in an account Merkle tree hashed with Pedersen.
+    pub fn to_bytes_le(&self) -> Vec<u8> {
+        let mut uncompressed_bytes = Vec::new();
+        self.serialize_uncompressed(&mut uncompressed_bytes)
+            .expect("serialization into a vector should not fail");
+        uncompressed_bytes
+    }
+
+    /// Convert the account information to field elements: the coordinates of the
+    /// public key, then the balances and the locked amounts. These elements form the
+    /// account's leaf in an account Merkle tree hashed with Poseidon.
+    pub fn to_field_elements(&self) -> Vec<Fq> {
+        [self.public_key.x, self.public_key.y]
+            .into_iter()
+            .chain(self.balances.iter().map(|balance| Fq::from(balance.0)))
+            .chain(self.locked.iter().map(|locked| Fq::from(locked.0)))
+            .collect()
+    }
+}
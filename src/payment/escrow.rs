@@ -0,0 +1,367 @@
+use super::{
+    account::{AccountId, AccountSecretKey},
+    ledger::{self, Amount, AssetId},
+    message::{MessageEncoder, TransactionKind},
+    tree::AccountTreeConfig,
+};
+use crate::basic_merkle_tree::storage::NodeStore;
+use ark_crypto_primitives::signature::{
+    schnorr::{Schnorr, Signature},
+    SignatureScheme,
+};
+use ark_ed_on_bls12_381::EdwardsProjective as JubJub;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_std::rand::Rng;
+use blake2::{Blake2s256 as Blake2s, Digest};
+
+/// Block height of the ledger, used to express escrow expiries.
+pub type Height = u64;
+
+/// A hash lock, i.e. the Blake2s hash of a secret preimage.
+#[derive(Hash, Eq, PartialEq, Copy, Clone, Debug, CanonicalSerialize, CanonicalDeserialize)]
+pub struct HashLock(pub [u8; 32]);
+
+impl HashLock {
+    /// Lock under the hash of `preimage`.
+    pub fn from_preimage(preimage: &[u8]) -> Self {
+        Self(Blake2s::digest(preimage).into())
+    }
+
+    /// Does `preimage` open this lock?
+    pub fn is_opened_by(&self, preimage: &[u8]) -> bool {
+        *self == Self::from_preimage(preimage)
+    }
+}
+
+/// Identifies an escrow. It is the Blake2s hash of the message signed to create the
+/// escrow, so it can be computed by both parties before the escrow is applied.
+#[derive(Hash, Eq, PartialEq, Copy, Clone, Debug, CanonicalSerialize, CanonicalDeserialize)]
+pub struct EscrowId(pub [u8; 32]);
+
+/// The terms of a hash-locked and time-locked transfer (HTLC).
+#[derive(Hash, Eq, PartialEq, Copy, Clone, Debug, CanonicalSerialize, CanonicalDeserialize)]
+pub struct Escrow {
+    /// The account that locks the funds, and can refund them after expiry.
+    pub sender: AccountId,
+    /// The account that can claim the funds before expiry by revealing the preimage.
+    pub recipient: AccountId,
+    /// The asset being locked.
+    pub asset_id: AssetId,
+    /// The amount being locked.
+    pub amount: Amount,
+    /// The hash lock that the recipient must open to claim the funds.
+    pub hash_lock: HashLock,
+    /// The first height at which the funds can no longer be claimed, and can be refunded.
+    pub expiry: Height,
+}
+
+/// The action performed by an escrow transaction.
+#[derive(Clone, Debug)]
+pub enum EscrowAction {
+    /// Lock `amount` of `asset_id` from the sender's balance under the given terms.
+    Lock(Escrow),
+    /// Claim the funds of an escrow for its recipient, by revealing the preimage of
+    /// its hash lock.
+    Claim {
+        escrow_id: EscrowId,
+        preimage: Vec<u8>,
+    },
+    /// Return the funds of an expired escrow to its sender.
+    Refund { escrow_id: EscrowId },
+}
+
+/// Transaction creating or settling an escrow.
+#[derive(Clone, Debug)]
+pub struct EscrowTransaction {
+    /// What the transaction does.
+    pub action: EscrowAction,
+    /// A signature over the action by its authorized account: the sender for locks
+    /// and refunds, and the recipient for claims.
+    pub signature: Signature<JubJub>,
+}
+
+impl EscrowAction {
+    /// The message authorized by the signature of an escrow transaction.
+    pub fn message<H: AccountTreeConfig>(&self, parameters: &ledger::Parameters<H>) -> Vec<u8> {
+        match self {
+            EscrowAction::Lock(escrow) => {
+                MessageEncoder::new(&parameters.ledger_id, TransactionKind::EscrowLock)
+                    .field(&escrow.sender.to_bytes_le())
+                    .field(&escrow.recipient.to_bytes_le())
+                    .field(&escrow.asset_id.to_bytes_le())
+                    .field(&escrow.amount.to_bytes_le())
+                    .field(&escrow.hash_lock.0)
+                    .field(&escrow.expiry.to_le_bytes())
+                    .finish()
+            }
+            EscrowAction::Claim {
+                escrow_id,
+                preimage,
+            } => MessageEncoder::new(&parameters.ledger_id, TransactionKind::EscrowClaim)
+                .field(&escrow_id.0)
+                .field(preimage)
+                .finish(),
+            EscrowAction::Refund { escrow_id } => {
+                MessageEncoder::new(&parameters.ledger_id, TransactionKind::EscrowRefund)
+                    .field(&escrow_id.0)
+                    .finish()
+            }
+        }
+    }
+
+    /// Return the account that must sign this action, if it can be determined from
+    /// `state`.
+    pub fn signer<H: AccountTreeConfig, S: NodeStore<H::Config>>(
+        &self,
+        state: &ledger::State<H, S>,
+    ) -> Option<AccountId> {
+        match self {
+            EscrowAction::Lock(escrow) => Some(escrow.sender),
+            EscrowAction::Claim { escrow_id, .. } => {
+                state.escrows.get(escrow_id).map(|escrow| escrow.recipient)
+            }
+            EscrowAction::Refund { escrow_id } => {
+                state.escrows.get(escrow_id).map(|escrow| escrow.sender)
+            }
+        }
+    }
+}
+
+impl EscrowTransaction {
+    /// Return the identifier of the escrow created or settled by this transaction.
+    pub fn escrow_id<H: AccountTreeConfig>(&self, parameters: &ledger::Parameters<H>) -> EscrowId {
+        match &self.action {
+            EscrowAction::Lock(_) => {
+                EscrowId(Blake2s::digest(self.action.message(parameters)).into())
+            }
+            EscrowAction::Claim { escrow_id, .. } | EscrowAction::Refund { escrow_id } => {
+                *escrow_id
+            }
+        }
+    }
+
+    /// Check that the transaction is valid for the given ledger state. This checks
+    /// the following conditions:
+    /// 1. Verify that the signature is valid with respect to the public key of the
+    ///    signer of the action.
+    /// 2. For a lock, verify that the escrow was never created before, that the recipient
+    ///    exists, that the expiry is in the future, and that the sender has
+    ///    sufficient balance in the asset.
+    /// 3. For a claim, verify that the escrow exists, has not expired, and that the
+    ///    preimage opens its hash lock.
+    /// 4. For a refund, verify that the escrow exists and has expired.
+    pub fn validate<H: AccountTreeConfig, S: NodeStore<H::Config>>(
+        &self,
+        parameters: &ledger::Parameters<H>,
+        state: &ledger::State<H, S>,
+    ) -> bool {
+        let signer_info = match self
+            .action
+            .signer(state)
+            .and_then(|signer| state.id_to_account_info.get(&signer))
+        {
+            Some(signer_info) => signer_info,
+            None => return false,
+        };
+        let mut result = <Schnorr<JubJub, Blake2s> as SignatureScheme>::verify(
+            &parameters.sig_params,
+            &signer_info.public_key,
+            &self.action.message(parameters),
+            &self.signature,
+        )
+        .unwrap();
+        let escrow_id = self.escrow_id(parameters);
+        result &= match &self.action {
+            EscrowAction::Lock(escrow) => {
+                !state.escrows.contains_key(&escrow_id)
+                    && !state.settled_escrows.contains(&escrow_id)
+                    && state.id_to_account_info.contains_key(&escrow.recipient)
+                    && escrow.expiry > state.height
+                    && signer_info
+                        .balance(escrow.asset_id)
+                        .is_some_and(|balance| escrow.amount <= balance)
+            }
+            EscrowAction::Claim { preimage, .. } => {
+                let escrow = &state.escrows[&escrow_id];
+                state.height < escrow.expiry && escrow.hash_lock.is_opened_by(preimage)
+            }
+            EscrowAction::Refund { .. } => state.height >= state.escrows[&escrow_id].expiry,
+        };
+        result
+    }
+
+    /// Create a (possibly invalid) escrow transaction, signed with `signer_sk`.
+    pub fn create<R: Rng, H: AccountTreeConfig>(
+        parameters: &ledger::Parameters<H>,
+        action: EscrowAction,
+        signer_sk: &AccountSecretKey,
+        rng: &mut R,
+    ) -> Self {
+        let signature = <Schnorr<JubJub, Blake2s> as SignatureScheme>::sign(
+            &parameters.sig_params,
+            signer_sk,
+            &action.message(parameters),
+            rng,
+        )
+        .unwrap();
+        Self { action, signature }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Escrow, EscrowAction, EscrowTransaction, HashLock};
+    use crate::payment::ledger::{Amount, AssetId, Parameters, State};
+
+    const USD: AssetId = AssetId(0);
+
+    #[test]
+    fn claim_and_refund() {
+        let mut rng = ark_std::test_rng();
+        let pp = Parameters::sample(&mut rng);
+        let mut state = State::new(32, &pp);
+        let (alice_id, _alice_pk, alice_sk) =
+            state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        let (bob_id, _bob_pk, bob_sk) = state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        state.update_balance(alice_id, USD, Amount(10)).unwrap();
+
+        let preimage = b"swap secret".to_vec();
+        let terms = Escrow {
+            sender: alice_id,
+            recipient: bob_id,
+            asset_id: USD,
+            amount: Amount(6),
+            hash_lock: HashLock::from_preimage(&preimage),
+            expiry: 10,
+        };
+
+        // Alice locks 6 units for Bob. The locked amount is committed in her leaf.
+        let root_before_lock = state.root();
+        let lock = EscrowTransaction::create(&pp, EscrowAction::Lock(terms), &alice_sk, &mut rng);
+        let escrow_id = state.apply_escrow_transaction(&pp, &lock).unwrap();
+        assert_eq!(state.escrows[&escrow_id], terms);
+        let alice = state.id_to_account_info[&alice_id];
+        assert_eq!(alice.balance(USD), Some(Amount(4)));
+        assert_eq!(alice.locked(USD), Some(Amount(6)));
+        assert_ne!(state.root(), root_before_lock);
+        assert!(state
+            .prove_account(alice_id)
+            .unwrap()
+            .verify(&pp, &state.root()));
+        // The same lock cannot be applied twice.
+        assert!(state.apply_escrow_transaction(&pp, &lock).is_none());
+
+        // Claims need the right preimage, and a signature by the recipient.
+        let bad_claim = EscrowTransaction::create(
+            &pp,
+            EscrowAction::Claim {
+                escrow_id,
+                preimage: b"wrong secret".to_vec(),
+            },
+            &bob_sk,
+            &mut rng,
+        );
+        assert!(state.apply_escrow_transaction(&pp, &bad_claim).is_none());
+        let claim = EscrowAction::Claim {
+            escrow_id,
+            preimage: preimage.clone(),
+        };
+        let bad_claim = EscrowTransaction::create(&pp, claim.clone(), &alice_sk, &mut rng);
+        assert!(state.apply_escrow_transaction(&pp, &bad_claim).is_none());
+        // Refunds are only possible after expiry.
+        let refund =
+            EscrowTransaction::create(&pp, EscrowAction::Refund { escrow_id }, &alice_sk, &mut rng);
+        assert!(state.apply_escrow_transaction(&pp, &refund).is_none());
+
+        // Bob claims before expiry.
+        state.set_height(9);
+        let claim = EscrowTransaction::create(&pp, claim, &bob_sk, &mut rng);
+        assert_eq!(state.apply_escrow_transaction(&pp, &claim), Some(escrow_id));
+        assert!(state.escrows.is_empty());
+        let alice = state.id_to_account_info[&alice_id];
+        let bob = state.id_to_account_info[&bob_id];
+        assert_eq!(alice.balance(USD), Some(Amount(4)));
+        assert_eq!(alice.locked(USD), Some(Amount(0)));
+        assert_eq!(bob.balance(USD), Some(Amount(6)));
+        assert!(state.settled_escrows.contains(&escrow_id));
+
+        // Replaying the settled lock and claim cannot move Alice's funds again.
+        assert!(!lock.validate(&pp, &state));
+        assert!(state.apply_escrow_transaction(&pp, &lock).is_none());
+        assert!(state.apply_escrow_transaction(&pp, &claim).is_none());
+        let alice = state.id_to_account_info[&alice_id];
+        let bob = state.id_to_account_info[&bob_id];
+        assert_eq!(alice.balance(USD), Some(Amount(4)));
+        assert_eq!(alice.locked(USD), Some(Amount(0)));
+        assert_eq!(bob.balance(USD), Some(Amount(6)));
+
+        // Alice locks her remaining 4 units; Bob cannot claim them after expiry, and
+        // Alice gets them back.
+        let terms = Escrow {
+            amount: Amount(4),
+            expiry: 12,
+            ..terms
+        };
+        let lock = EscrowTransaction::create(&pp, EscrowAction::Lock(terms), &alice_sk, &mut rng);
+        let escrow_id = state.apply_escrow_transaction(&pp, &lock).unwrap();
+        state.set_height(12);
+        let claim = EscrowTransaction::create(
+            &pp,
+            EscrowAction::Claim {
+                escrow_id,
+                preimage,
+            },
+            &bob_sk,
+            &mut rng,
+        );
+        assert!(state.apply_escrow_transaction(&pp, &claim).is_none());
+        let refund =
+            EscrowTransaction::create(&pp, EscrowAction::Refund { escrow_id }, &alice_sk, &mut rng);
+        assert_eq!(
+            state.apply_escrow_transaction(&pp, &refund),
+            Some(escrow_id)
+        );
+        let alice = state.id_to_account_info[&alice_id];
+        assert_eq!(alice.balance(USD), Some(Amount(4)));
+        assert_eq!(alice.locked(USD), Some(Amount(0)));
+    }
+
+    #[test]
+    fn lock_requires_funds_and_future_expiry() {
+        let mut rng = ark_std::test_rng();
+        let pp = Parameters::sample(&mut rng);
+        let mut state = State::new(32, &pp);
+        let (alice_id, _alice_pk, alice_sk) =
+            state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        let (bob_id, _bob_pk, _bob_sk) = state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        state.update_balance(alice_id, USD, Amount(10)).unwrap();
+        state.set_height(5);
+
+        let terms = Escrow {
+            sender: alice_id,
+            recipient: bob_id,
+            asset_id: USD,
+            amount: Amount(11),
+            hash_lock: HashLock::from_preimage(b"secret"),
+            expiry: 6,
+        };
+        for bad_terms in [
+            terms,
+            Escrow {
+                amount: Amount(1),
+                expiry: 5,
+                ..terms
+            },
+            Escrow {
+                amount: Amount(1),
+                asset_id: AssetId(9),
+                ..terms
+            },
+        ] {
+            let lock =
+                EscrowTransaction::create(&pp, EscrowAction::Lock(bad_terms), &alice_sk, &mut rng);
+            assert!(!lock.validate(&pp, &state));
+            assert!(state.apply_escrow_transaction(&pp, &lock).is_none());
+        }
+    }
+}
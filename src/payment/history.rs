@@ -0,0 +1,174 @@
+use super::{
+    account::{AccountId, AccountInformation},
+    ledger::{AccMerkleTree, AccRoot},
+    proof::AccountProof,
+    tree::{AccountTreeConfig, Pedersen},
+};
+use crate::basic_merkle_tree::storage::{NodeBatch, NodeStore};
+use std::collections::{HashMap, VecDeque};
+
+/// Sequence number of a ledger state. It is incremented once per state transition
+/// (account registration, balance update, applied transaction, or batch of
+/// registrations or balance updates).
+pub type Version = u64;
+
+/// Number of past ledger states retained by default.
+pub const DEFAULT_ROOT_HISTORY: usize = 64;
+
+/// A snapshot of the ledger at a given version. Neither the account Merkle tree nor the
+/// account information is copied: the snapshot keeps the nodes and the accounts that
+/// later versions overwrote instead, so that the ledger as of `version` can be
+/// recovered from the current one.
+pub struct HistoricalState<H: AccountTreeConfig = Pedersen> {
+    /// The version at which the snapshot was taken.
+    pub version: Version,
+    /// The root of the account Merkle tree as of `version`.
+    pub root: AccRoot<H>,
+    /// The information of the accounts overwritten since `version`, as it was at
+    /// `version`, or `None` for accounts that did not exist yet.
+    pub overwritten_accounts: HashMap<AccountId, Option<AccountInformation>>,
+    /// The nodes of the account Merkle tree overwritten since `version`, as they were
+    /// at `version`.
+    pub overwritten: NodeBatch<H::Config>,
+}
+
+impl<H: AccountTreeConfig> Clone for HistoricalState<H> {
+    fn clone(&self) -> Self {
+        Self {
+            version: self.version,
+            root: self.root.clone(),
+            overwritten_accounts: self.overwritten_accounts.clone(),
+            overwritten: self.overwritten.clone(),
+        }
+    }
+}
+
+impl<H: AccountTreeConfig> HistoricalState<H> {
+    /// Return the root of the account Merkle tree as of this version.
+    pub fn root(&self) -> AccRoot<H> {
+        self.root.clone()
+    }
+}
+
+/// A bounded history of ledger states, ordered from oldest to newest.
+/// Once `capacity` is reached, recording a new state evicts the oldest one.
+#[derive(Clone)]
+pub struct RootHistory<H: AccountTreeConfig = Pedersen> {
+    capacity: usize,
+    entries: VecDeque<HistoricalState<H>>,
+}
+
+impl<H: AccountTreeConfig> RootHistory<H> {
+    /// Create an empty history retaining at most `capacity` states.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "root history must retain at least one state");
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Return the maximum number of retained states.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Record `state`, evicting the oldest state if the history is full.
+    pub fn record(&mut self, state: HistoricalState<H>) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(state);
+    }
+
+    /// Return the state recorded at `version`, if it is still retained.
+    pub fn get(&self, version: Version) -> Option<&HistoricalState<H>> {
+        // Versions are recorded in increasing order, so the position of `version`
+        // is its offset from the oldest retained version.
+        let oldest = self.entries.front()?.version;
+        let offset = version.checked_sub(oldest)?;
+        self.entries
+            .get(offset as usize)
+            .filter(|state| state.version == version)
+    }
+
+    /// Record that `nodes` of the account Merkle tree were overwritten since the
+    /// newest recorded state. Nodes overwritten earlier since then are kept as they
+    /// were at that state.
+    pub fn record_overwritten(&mut self, nodes: NodeBatch<H::Config>) {
+        if let Some(state) = self.entries.back_mut() {
+            state.overwritten.merge_vacant(nodes);
+        }
+    }
+
+    /// Record that `accounts` were overwritten since the newest recorded state, with
+    /// their information as of that state. Accounts overwritten earlier since then are
+    /// kept as they were at that state.
+    pub fn record_overwritten_accounts(
+        &mut self,
+        accounts: impl IntoIterator<Item = (AccountId, Option<AccountInformation>)>,
+    ) {
+        if let Some(state) = self.entries.back_mut() {
+            for (id, info) in accounts {
+                state.overwritten_accounts.entry(id).or_insert(info);
+            }
+        }
+    }
+
+    /// Return the information of account `id` at `version`, given the current account
+    /// information `accounts`. The first version since `version` that overwrote the
+    /// account holds its information at `version`; if none did, it is unchanged.
+    pub fn account_info(
+        &self,
+        accounts: &HashMap<AccountId, AccountInformation>,
+        id: AccountId,
+        version: Version,
+    ) -> Option<AccountInformation> {
+        self.get(version)?;
+        let overwritten = self
+            .entries
+            .iter()
+            .skip_while(|state| state.version < version)
+            .find_map(|state| state.overwritten_accounts.get(&id));
+        match overwritten {
+            Some(info) => *info,
+            None => accounts.get(&id).copied(),
+        }
+    }
+
+    /// Return a proof of the account information of `id` at `version`, against the
+    /// root at `version`, given the current account Merkle tree and account information.
+    pub fn prove_account<S: NodeStore<H::Config>>(
+        &self,
+        tree: &AccMerkleTree<H, S>,
+        accounts: &HashMap<AccountId, AccountInformation>,
+        id: AccountId,
+        version: Version,
+    ) -> Option<AccountProof<H>> {
+        let state = self.get(version)?;
+        let info = self.account_info(accounts, id, version)?;
+        let later = self
+            .entries
+            .iter()
+            .skip_while(|state| state.version < version)
+            .map(|state| &state.overwritten);
+        let path = tree.rewind(later).generate_proof(id.0 as usize).ok()?;
+        Some(AccountProof {
+            info,
+            path,
+            root: state.root(),
+        })
+    }
+
+    /// Return the oldest retained version.
+    pub fn oldest_version(&self) -> Option<Version> {
+        self.entries.front().map(|state| state.version)
+    }
+
+    /// Iterate over the retained `(version, root)` pairs, from oldest to newest.
+    pub fn roots(&self) -> impl Iterator<Item = (Version, AccRoot<H>)> + '_ {
+        self.entries
+            .iter()
+            .map(|state| (state.version, state.root()))
+    }
+}
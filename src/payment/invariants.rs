@@ -0,0 +1,197 @@
+//! Randomized checks that the ledger invariants hold after any sequence of
+//! registrations, balance updates and transfers, valid or not.
+use super::{
+    account::{AccountId, AccountPublicKey, AccountSecretKey},
+    ledger::{Amount, AssetId, Parameters, State, NUM_ASSETS},
+    transaction::Transaction,
+    tree::{AccountTreeConfig, Pedersen},
+};
+use ark_crypto_primitives::{
+    merkle_tree::MerkleTree,
+    signature::{schnorr::Schnorr, SignatureScheme},
+};
+use ark_ed_on_bls12_381::EdwardsProjective as JubJub;
+use ark_std::rand::{rngs::StdRng, SeedableRng};
+use blake2::Blake2s256 as Blake2s;
+use proptest::prelude::*;
+use std::sync::OnceLock;
+
+/// The number of accounts the ledger under test supports. This is small, so that
+/// sequences also exercise a full ledger.
+const NUM_ACCOUNTS: usize = 16;
+/// The number of distinct keys that operations register and sign with.
+const NUM_KEYS: usize = 10;
+/// The number of keys registered and funded before the operations run, so that many
+/// generated transfers are valid.
+const NUM_GENESIS_ACCOUNTS: usize = 3;
+/// The balance of the genesis accounts in every asset.
+const GENESIS_BALANCE: u64 = 10;
+
+#[derive(Clone, Debug)]
+enum Operation {
+    /// Register the `key`-th key.
+    Register { key: usize },
+    /// Set the balance of an account.
+    UpdateBalance { id: u8, asset: u8, amount: u64 },
+    /// Transfer between two accounts, signed with the sender's key if `signer` is
+    /// `None`, and with the `signer`-th key otherwise.
+    Transfer {
+        sender: u8,
+        recipient: u8,
+        asset: u8,
+        amount: u64,
+        signer: Option<usize>,
+    },
+}
+
+/// The parameters and keys shared by every test case, as sampling them is slow.
+fn setup() -> &'static (Parameters, Vec<(AccountPublicKey, AccountSecretKey)>) {
+    static SETUP: OnceLock<(Parameters, Vec<(AccountPublicKey, AccountSecretKey)>)> =
+        OnceLock::new();
+    SETUP.get_or_init(|| {
+        let mut rng = ark_std::test_rng();
+        let pp = Parameters::sample(&mut rng);
+        let keys = (0..NUM_KEYS)
+            .map(|_| {
+                <Schnorr<JubJub, Blake2s> as SignatureScheme>::keygen(&pp.sig_params, &mut rng)
+                    .unwrap()
+            })
+            .collect();
+        (pp, keys)
+    })
+}
+
+fn amount() -> impl Strategy<Value = u64> {
+    prop_oneof![0..20u64, Just(u64::MAX), any::<u64>()]
+}
+
+fn operation() -> impl Strategy<Value = Operation> {
+    // Identifiers and assets range slightly past the valid ones.
+    let id = 0..(NUM_ACCOUNTS as u8 / 2 + 2);
+    let asset = 0..(NUM_ASSETS as u8 + 1);
+    prop_oneof![
+        1 => (0..NUM_KEYS).prop_map(|key| Operation::Register { key }),
+        1 => (id.clone(), asset.clone(), amount())
+            .prop_map(|(id, asset, amount)| Operation::UpdateBalance { id, asset, amount }),
+        3 => (
+            id.clone(),
+            // Transfers to the sender itself are generated on purpose.
+            prop::option::weighted(0.8, id),
+            asset,
+            amount(),
+            prop::option::weighted(0.2, 0..NUM_KEYS)
+        )
+            .prop_map(|(sender, recipient, asset, amount, signer)| {
+                let recipient = recipient.unwrap_or(sender);
+                Operation::Transfer {
+                    sender,
+                    recipient,
+                    asset,
+                    amount,
+                    signer,
+                }
+            }),
+    ]
+}
+
+/// Return the total balance of every asset.
+fn totals(state: &State) -> [u128; NUM_ASSETS] {
+    let mut totals = [0u128; NUM_ASSETS];
+    for info in state.id_to_account_info.values() {
+        for (total, balance) in totals.iter_mut().zip(info.balances) {
+            *total += balance.0 as u128;
+        }
+    }
+    totals
+}
+
+/// Check the invariants relating the parts of `state` to each other.
+fn check_consistency(pp: &Parameters, state: &State) -> Result<(), TestCaseError> {
+    // The root equals the root of an in-memory tree rebuilt from the account
+    // information.
+    let mut rebuilt = MerkleTree::<<Pedersen as AccountTreeConfig>::Config>::blank(
+        &pp.leaf_crh_params,
+        &pp.two_to_one_crh_params,
+        state.account_merkle_tree.height(),
+    )
+    .unwrap();
+    for (id, info) in &state.id_to_account_info {
+        rebuilt
+            .update(id.0 as usize, info.to_bytes_le().as_slice())
+            .unwrap();
+    }
+    prop_assert_eq!(rebuilt.root(), state.root());
+    // Public keys and identifiers map to each other one-to-one.
+    prop_assert_eq!(state.pub_key_to_id.len(), state.id_to_account_info.len());
+    for (public_key, id) in &state.pub_key_to_id {
+        let info = state.id_to_account_info.get(id);
+        prop_assert_eq!(info.map(|info| info.public_key), Some(*public_key));
+    }
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(48))]
+
+    #[test]
+    fn ledger_invariants_hold(operations in prop::collection::vec(operation(), 1..24)) {
+        let (pp, keys) = setup();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut state = State::new(NUM_ACCOUNTS, pp);
+        for (public_key, _) in &keys[..NUM_GENESIS_ACCOUNTS] {
+            let id = state.register(*public_key).unwrap();
+            for asset in 0..NUM_ASSETS {
+                state
+                    .update_balance(id, AssetId(asset as u8), Amount(GENESIS_BALANCE))
+                    .unwrap();
+            }
+        }
+        for operation in operations {
+            let before = totals(&state);
+            let mut expected = before;
+            match operation {
+                Operation::Register { key } => {
+                    state.register(keys[key].0);
+                }
+                Operation::UpdateBalance { id, asset, amount } => {
+                    let old = state
+                        .id_to_account_info
+                        .get(&AccountId(id))
+                        .and_then(|info| info.balance(AssetId(asset)));
+                    let result = state.update_balance(AccountId(id), AssetId(asset), Amount(amount));
+                    prop_assert_eq!(result.is_some(), old.is_some());
+                    if let Some(old) = old {
+                        let total = &mut expected[asset as usize];
+                        *total = *total - old.0 as u128 + amount as u128;
+                    }
+                }
+                Operation::Transfer { sender, recipient, asset, amount, signer } => {
+                    // Sign with the sender's key by default, or with the first key
+                    // if the sender does not exist.
+                    let sender_key = state
+                        .id_to_account_info
+                        .get(&AccountId(sender))
+                        .and_then(|info| keys.iter().position(|(pk, _)| *pk == info.public_key));
+                    let signer = signer.or(sender_key).unwrap_or(0);
+                    let tx = Transaction::create(
+                        pp,
+                        AccountId(sender),
+                        AccountId(recipient),
+                        AssetId(asset),
+                        Amount(amount),
+                        &keys[signer].1,
+                        &mut rng,
+                    );
+                    let version = state.version;
+                    let valid = tx.validate(pp, &state);
+                    prop_assert_eq!(state.apply_transaction(pp, &tx).is_some(), valid);
+                    prop_assert_eq!(state.version, version + valid as u64);
+                }
+            }
+            // Transfers conserve the total balance of every asset, and balance updates
+            // change it by the difference between the new and old balances.
+            prop_assert_eq!(totals(&state), expected);
+            check_consistency(pp, &state)?;
+        }
+    }
+}
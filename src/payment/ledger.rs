@@ -1,24 +1,45 @@
 use super::{
     account::{AccountId, AccountInformation, AccountPublicKey, AccountSecretKey},
+    escrow::{Escrow, EscrowAction, EscrowId, EscrowTransaction, Height},
+    history::{HistoricalState, RootHistory, Version, DEFAULT_ROOT_HISTORY},
+    proof::AccountProof,
+    receipt::{Event, EventLog, Receipt},
+    simulation::{BalanceChange, SimulationReport},
     transaction::Transaction,
+    tree::{AccountTreeConfig, LeafHashParams, Pedersen, TwoToOneHashParams},
+};
+use crate::basic_merkle_tree::{
+    common::Window4x25,
+    storage::{params_digest, MemoryNodeStore, NodeBatch, NodeStore, StoredMerkleTree},
 };
-use crate::basic_merkle_tree::common::{CompressH, JubJubMerkleTree, LeafH, SimplePath};
 use ark_crypto_primitives::{
-    crh::{CRHScheme, TwoToOneCRHScheme},
-    merkle_tree::MerkleTree,
+    crh::pedersen::Window,
+    merkle_tree::{Config, Path},
     signature::{
         schnorr::{self, PublicKey, Schnorr},
         SignatureScheme,
     },
+    Error,
 };
 use ark_ed_on_bls12_381::EdwardsProjective as JubJub;
-use ark_serialize::CanonicalSerialize;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 use ark_std::{log2, rand::Rng};
 use blake2::Blake2s256 as Blake2s;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 /// Represents transaction amounts and account balances.
-#[derive(Hash, Eq, PartialEq, Copy, Clone, PartialOrd, Ord, Debug, CanonicalSerialize)]
+#[derive(
+    Hash,
+    Eq,
+    PartialEq,
+    Copy,
+    Clone,
+    PartialOrd,
+    Ord,
+    Debug,
+    CanonicalSerialize,
+    CanonicalDeserialize,
+)]
 pub struct Amount(pub u64);
 
 impl Amount {
@@ -35,105 +56,361 @@ impl Amount {
     }
 }
 
+/// Number of assets an account can hold. Every account leaf commits to one balance
+/// and one locked amount per asset.
+///
+/// A leaf of an account tree hashed with Pedersen holds at most
+/// `WINDOW_SIZE * NUM_WINDOWS / 8 = 128` bytes of `Window4x25`, which 4 assets fill
+/// entirely: more assets need a larger window.
+pub const NUM_ASSETS: usize = 4;
+
+/// The size of the account information encoded by `AccountInformation::to_bytes_le`:
+/// the uncompressed public key, then a balance and a locked amount of 8 bytes per asset.
+pub const ACCOUNT_INFO_SIZE: usize = 64 + 2 * 8 * NUM_ASSETS;
+
+// The Pedersen leaf hash cannot hash more bits than its windows hold.
+const _: () = assert!(
+    ACCOUNT_INFO_SIZE * 8 <= Window4x25::WINDOW_SIZE * Window4x25::NUM_WINDOWS,
+    "the account information does not fit in a Pedersen leaf"
+);
+
+/// Identifies an asset settled on the ledger. Valid identifiers are `0..NUM_ASSETS`.
+#[derive(
+    Hash,
+    Eq,
+    PartialEq,
+    Copy,
+    Clone,
+    PartialOrd,
+    Ord,
+    Debug,
+    Default,
+    CanonicalSerialize,
+    CanonicalDeserialize,
+)]
+pub struct AssetId(pub u8);
+
+impl AssetId {
+    pub fn to_bytes_le(&self) -> Vec<u8> {
+        vec![self.0]
+    }
+
+    /// Is this a valid asset identifier?
+    pub fn is_valid(&self) -> bool {
+        (self.0 as usize) < NUM_ASSETS
+    }
+}
+
 pub type SignatureParameters = schnorr::Parameters<JubJub, Blake2s>;
 
-/// The parameters that are used in transaction creation and validation.
+/// The key of the record of the block height in the store of the account Merkle tree.
+/// The information of each account is recorded under its identifier, below this key.
+const HEIGHT_RECORD: u64 = 1 << 32;
+
+/// The key of the first record of the escrow log in the store of the account Merkle
+/// tree. Every lock appends its escrow, and every claim or refund appends the identifier
+/// of the escrow it settles, under the next key.
+const ESCROW_RECORDS: u64 = 1 << 33;
+
+/// Identifies a ledger deployment, such as a testnet or a mainnet. It is bound into
+/// every signed message, so that a signature for one ledger is not valid on another
+/// ledger, even if both use the same signature parameters.
+#[derive(Hash, Eq, PartialEq, Clone, Debug, Default)]
+pub struct LedgerId(pub Vec<u8>);
+
+/// The parameters that are used in transaction creation and validation. The account
+/// Merkle tree is hashed as configured by `H`.
 #[derive(Clone)]
-pub struct Parameters {
+pub struct Parameters<H: AccountTreeConfig = Pedersen> {
     pub sig_params: SignatureParameters,
-    pub leaf_crh_params: <LeafH as CRHScheme>::Parameters,
-    pub two_to_one_crh_params: <CompressH as TwoToOneCRHScheme>::Parameters,
+    pub leaf_crh_params: LeafHashParams<H>,
+    pub two_to_one_crh_params: TwoToOneHashParams<H>,
+    /// The ledger that transactions created with these parameters are valid on.
+    pub ledger_id: LedgerId,
 }
 
 impl Parameters {
+    /// Sample parameters for a ledger with a random identifier, whose account tree is
+    /// hashed with Pedersen.
     pub fn sample<R: Rng>(rng: &mut R) -> Self {
+        Self::sample_with_tree(rng)
+    }
+}
+
+impl<H: AccountTreeConfig> Parameters<H> {
+    /// Sample parameters for a ledger with a random identifier, whose account tree is
+    /// hashed as configured by `H`.
+    pub fn sample_with_tree<R: Rng>(rng: &mut R) -> Self {
         let sig_params = <Schnorr<JubJub, Blake2s> as SignatureScheme>::setup(rng).unwrap();
-        let leaf_crh_params = <LeafH as CRHScheme>::setup(rng).unwrap();
-        let two_to_one_crh_params = <CompressH as TwoToOneCRHScheme>::setup(rng).unwrap();
+        let (leaf_crh_params, two_to_one_crh_params) = H::setup(rng);
+        let mut ledger_id = vec![0u8; 32];
+        rng.fill_bytes(&mut ledger_id);
         Self {
             sig_params,
             leaf_crh_params,
             two_to_one_crh_params,
+            ledger_id: LedgerId(ledger_id),
         }
     }
+
+    /// Return the same parameters, for the ledger `ledger_id`.
+    pub fn with_ledger_id(self, ledger_id: LedgerId) -> Self {
+        Self { ledger_id, ..self }
+    }
+
+    /// Return the digest of the hash parameters of the account Merkle tree, with which
+    /// a `FileNodeStore` of the tree is opened.
+    pub fn account_tree_digest(&self) -> [u8; 32] {
+        // The leaf of an account with non-zero balances is not made of zeros only.
+        let probe = AccountInformation {
+            public_key: AccountPublicKey::default(),
+            balances: [Amount(1); NUM_ASSETS],
+            locked: [Amount(0); NUM_ASSETS],
+        };
+        params_digest::<H::Config>(
+            &self.leaf_crh_params,
+            &self.two_to_one_crh_params,
+            &H::leaf(&probe),
+        )
+        .expect("the hash parameters should hash any leaf")
+    }
 }
 
-/// A Merkle tree containing account information.
-pub type AccMerkleTree = JubJubMerkleTree;
-pub type AccRoot = <CompressH as TwoToOneCRHScheme>::Output;
-pub type AccPath = SimplePath;
+/// A Merkle tree containing account information, whose nodes are kept in a store of
+/// type `S`.
+pub type AccMerkleTree<H = Pedersen, S = AccNodeStore<H>> =
+    StoredMerkleTree<<H as AccountTreeConfig>::Config, S>;
+/// The default store of the nodes of the account Merkle tree, which keeps them in memory.
+pub type AccNodeStore<H = Pedersen> = MemoryNodeStore<<H as AccountTreeConfig>::Config>;
+pub type AccRoot<H = Pedersen> = <<H as AccountTreeConfig>::Config as Config>::InnerDigest;
+pub type AccPath<H = Pedersen> = Path<<H as AccountTreeConfig>::Config>;
 
 #[derive(Clone)]
-pub struct State {
+pub struct State<H: AccountTreeConfig = Pedersen, S = AccNodeStore<H>> {
     /// What is the next available account identifier?
     pub next_available_account: Option<AccountId>,
     /// A merkle tree mapping where the i-th leaf corresponds to the i-th account's
-    /// information (= balance and public key).
-    pub account_merkle_tree: AccMerkleTree,
-    /// A mapping from an account's identifier to its information (= balance and public key).
+    /// information (= per-asset balances and public key). Its nodes are kept in a
+    /// store of type `S`.
+    pub account_merkle_tree: AccMerkleTree<H, S>,
+    /// A mapping from an account's identifier to its information (= per-asset balances
+    /// and public key).
     pub id_to_account_info: HashMap<AccountId, AccountInformation>,
     /// A mapping from a public key to an account's identifier.
     pub pub_key_to_id: HashMap<PublicKey<JubJub>, AccountId>,
+    /// The sequence number of the current state.
+    pub version: Version,
+    /// A bounded history of past states, used to prove accounts against past roots.
+    pub root_history: RootHistory<H>,
+    /// The current block height, against which escrow expiries are checked.
+    pub height: Height,
+    /// The terms of pending escrows. The funds they lock are committed in the
+    /// `locked` amounts of their senders' account information.
+    pub escrows: HashMap<EscrowId, Escrow>,
+    /// The identifiers of every escrow that was claimed or refunded. A lock whose
+    /// identifier is in this set is a replay, and is rejected.
+    pub settled_escrows: HashSet<EscrowId>,
+    /// The events emitted by every change to the state, indexed by account.
+    pub events: EventLog,
 }
 
-impl State {
+impl<H: AccountTreeConfig> State<H> {
     /// Create an empty ledger that supports `num_accounts` accounts.
-    pub fn new(num_accounts: usize, parameters: &Parameters) -> Self {
+    pub fn new(num_accounts: usize, parameters: &Parameters<H>) -> Self {
+        Self::with_root_history(num_accounts, parameters, DEFAULT_ROOT_HISTORY)
+    }
+
+    /// Create an empty ledger that supports `num_accounts` accounts, and retains the
+    /// last `history_capacity` states for historical proofs.
+    pub fn with_root_history(
+        num_accounts: usize,
+        parameters: &Parameters<H>,
+        history_capacity: usize,
+    ) -> Self {
+        Self::with_store(
+            num_accounts,
+            parameters,
+            history_capacity,
+            AccNodeStore::<H>::default(),
+        )
+        .expect("an empty store in memory should not fail")
+    }
+}
+
+impl<H: AccountTreeConfig, S: NodeStore<H::Config>> State<H, S> {
+    /// Create a ledger that supports `num_accounts` accounts, and keeps the nodes of its
+    /// account Merkle tree in `store`, such as a `FileNodeStore` for trees that do not
+    /// fit in memory. The information of every account is recorded in `store` together
+    /// with the nodes of its leaf, so a ledger created from the store of a previous
+    /// ledger holds the same accounts, without rehashing the tree. The block height and
+    /// the escrows are recorded too, so that pending escrows can still be settled and
+    /// settled escrows cannot be locked again. The root history and the event log are
+    /// kept in memory only, so the reopened ledger starts at version 0 without them.
+    ///
+    /// Fails if `store` holds a tree for another number of accounts, if it cannot be
+    /// read, or if its records are not those of a ledger.
+    pub fn with_store(
+        num_accounts: usize,
+        parameters: &Parameters<H>,
+        history_capacity: usize,
+        store: S,
+    ) -> Result<Self, Error> {
         let height = log2(num_accounts);
-        let account_merkle_tree: AccMerkleTree = MerkleTree::blank(
+        let account_merkle_tree = StoredMerkleTree::new(
             &parameters.leaf_crh_params,
             &parameters.two_to_one_crh_params,
             height as usize,
-        )
-        .unwrap();
-        let pub_key_to_id = HashMap::with_capacity(num_accounts);
-        let id_to_account_info = HashMap::with_capacity(num_accounts);
-        Self {
-            next_available_account: Some(AccountId(1)),
+            store,
+        )?;
+        let mut pub_key_to_id = HashMap::with_capacity(num_accounts);
+        let mut id_to_account_info = HashMap::with_capacity(num_accounts);
+        let mut height = 0;
+        let mut escrows = HashMap::new();
+        let mut settled_escrows = HashSet::new();
+        // Records are read by increasing key, so the escrow log is replayed in order.
+        for (key, record) in account_merkle_tree.store().records()? {
+            let record = record.as_slice();
+            if key < HEIGHT_RECORD {
+                let id = AccountId(key.try_into()?);
+                let account_info = AccountInformation::deserialize_compressed(record)?;
+                pub_key_to_id.insert(account_info.public_key, id);
+                id_to_account_info.insert(id, account_info);
+            } else if key == HEIGHT_RECORD {
+                height = Height::deserialize_compressed(record)?;
+            } else if key >= ESCROW_RECORDS {
+                match <(EscrowId, Option<Escrow>)>::deserialize_compressed(record)? {
+                    (escrow_id, Some(escrow)) => {
+                        escrows.insert(escrow_id, escrow);
+                    }
+                    (escrow_id, None) => {
+                        escrows.remove(&escrow_id);
+                        settled_escrows.insert(escrow_id);
+                    }
+                }
+            } else {
+                return Err("the store holds a record that is not a ledger's".into());
+            }
+        }
+        // Identifiers are assigned in increasing order from 1.
+        let next_available_account = match id_to_account_info.keys().max() {
+            Some(&AccountId(last)) => last.checked_add(1).map(AccountId),
+            None => Some(AccountId(1)),
+        };
+        let mut state = Self {
+            next_available_account,
             account_merkle_tree,
             pub_key_to_id,
             id_to_account_info,
-        }
+            version: 0,
+            root_history: RootHistory::new(history_capacity),
+            height,
+            escrows,
+            settled_escrows,
+            events: EventLog::default(),
+        };
+        state.root_history.record(state.snapshot());
+        Ok(state)
     }
 
     /// Return the root of the account Merkle tree.
-    pub fn root(&self) -> AccRoot {
+    pub fn root(&self) -> AccRoot<H> {
         self.account_merkle_tree.root()
     }
 
+    /// Return the root of the account Merkle tree at `version`, if that version is
+    /// still retained in the root history.
+    pub fn root_at(&self, version: Version) -> Option<AccRoot<H>> {
+        self.root_history.get(version).map(|state| state.root())
+    }
+
+    /// Return a proof of the current information of account `id` against `self.root()`.
+    /// Returns `None` if the account does not exist.
+    pub fn prove_account(&self, id: AccountId) -> Option<AccountProof<H>> {
+        let info = *self.id_to_account_info.get(&id)?;
+        let path = self
+            .account_merkle_tree
+            .generate_proof(id.0 as usize)
+            .ok()?;
+        Some(AccountProof {
+            info,
+            path,
+            root: self.root(),
+        })
+    }
+
+    /// Return a proof of the information of account `id` at `version`, against
+    /// `self.root_at(version)`.
+    /// Returns `None` if the version has been evicted from the root history, or if
+    /// the account did not exist at that version.
+    pub fn prove_account_at(&self, id: AccountId, version: Version) -> Option<AccountProof<H>> {
+        self.root_history.prove_account(
+            &self.account_merkle_tree,
+            &self.id_to_account_info,
+            id,
+            version,
+        )
+    }
+
     /// Create a new account with public key `pub_key`. Returns a fresh account identifier
-    /// if there is space for a new account, and returns `None` otherwise.
-    /// The initial balance of the new account is 0.
+    /// if there is space for a new account and `pub_key` is not registered yet, and
+    /// returns `None` otherwise.
+    /// The initial balance of the new account is 0 in every asset.
     pub fn register(&mut self, public_key: AccountPublicKey) -> Option<AccountId> {
-        self.next_available_account.and_then(|id| {
+        self.register_batch(&[public_key]).pop().flatten()
+    }
+
+    /// Register an account for each of `public_keys` in order, as `register` would, in
+    /// a single version. The account Merkle tree is updated once for the whole batch.
+    /// Returns the identifier of each new account, or `None` for the keys that
+    /// `register` would reject.
+    pub fn register_batch(&mut self, public_keys: &[AccountPublicKey]) -> Vec<Option<AccountId>> {
+        let num_leaves = 1 << (self.account_merkle_tree.height() - 1);
+        let mut accounts = Vec::new();
+        let mut ids = Vec::with_capacity(public_keys.len());
+        for &public_key in public_keys {
+            let id = match self.next_available_account {
+                Some(id)
+                    if (id.0 as usize) < num_leaves
+                        && !self.pub_key_to_id.contains_key(&public_key) =>
+                {
+                    id
+                }
+                _ => {
+                    ids.push(None);
+                    continue;
+                }
+            };
             // Construct account information for the new account.
             let account_info = AccountInformation {
                 public_key,
-                balance: Amount(0),
+                balances: [Amount(0); NUM_ASSETS],
+                locked: [Amount(0); NUM_ASSETS],
             };
-            // Insert information into the relevant accounts.
             self.pub_key_to_id.insert(public_key, id);
-            let mut uncompressed_bytes = Vec::new();
-            // todo: revisit unwrap()
-            account_info
-                .serialize_uncompressed(&mut uncompressed_bytes)
-                .unwrap();
-            self.account_merkle_tree
-                .update(id.0 as usize, uncompressed_bytes.as_slice())
-                .expect("should exist");
-            self.id_to_account_info.insert(id, account_info);
-            // Increment the next account identifier.
-            self.next_available_account
-                .as_mut()
-                .and_then(|cur| cur.checked_increment());
-            Some(id)
-        })
+            accounts.push((id, account_info));
+            // Increment the next account identifier, or mark the ledger as full.
+            self.next_available_account = self
+                .next_available_account
+                .and_then(|mut cur| cur.checked_increment().map(|_| cur));
+            ids.push(Some(id));
+        }
+        if !accounts.is_empty() {
+            self.write_accounts(&accounts);
+            self.record_version();
+            for (id, account_info) in accounts {
+                self.emit(Event::AccountCreated {
+                    id,
+                    public_key: account_info.public_key,
+                });
+            }
+        }
+        ids
     }
 
     /// Samples keys and registers these in the ledger.
     pub fn sample_keys_and_register<R: Rng>(
         &mut self,
-        ledger_params: &Parameters,
+        ledger_params: &Parameters<H>,
         rng: &mut R,
     ) -> Option<(AccountId, AccountPublicKey, AccountSecretKey)> {
         let (pub_key, secret_key) =
@@ -142,45 +419,321 @@ impl State {
         self.register(pub_key).map(|id| (id, pub_key, secret_key))
     }
 
-    /// Update the balance of `id` to `new_amount`.
-    /// Returns `Some(())` if an account with identifier `id` exists already, and `None`
-    /// otherwise.
-    pub fn update_balance(&mut self, id: AccountId, new_amount: Amount) -> Option<()> {
-        let tree = &mut self.account_merkle_tree;
-        self.id_to_account_info
-            .get_mut(&id)
-            .map(|account_info: &mut AccountInformation| {
-                account_info.balance = new_amount;
-                let mut uncompressed_bytes = Vec::new();
-                // todo: revisit unwrap()
-                account_info
-                    .serialize_uncompressed(&mut uncompressed_bytes)
-                    .unwrap();
-                tree.update(id.0 as usize, uncompressed_bytes.as_slice())
-                    .expect("should exist")
-            })
+    /// Update the balance of `id` in asset `asset_id` to `new_amount`.
+    /// Returns `Some(())` if an account with identifier `id` exists already and
+    /// `asset_id` is valid, and `None` otherwise.
+    pub fn update_balance(
+        &mut self,
+        id: AccountId,
+        asset_id: AssetId,
+        new_amount: Amount,
+    ) -> Option<()> {
+        self.update_balances(&[(id, asset_id, new_amount)])
+    }
+
+    /// Apply each balance update `(id, asset_id, new_amount)` of `updates` in order, as
+    /// `update_balance` would, in a single version. The account Merkle tree is updated
+    /// once for the whole batch.
+    /// Returns `None` without changing the state if an account does not exist or an
+    /// asset is invalid.
+    pub fn update_balances(&mut self, updates: &[(AccountId, AssetId, Amount)]) -> Option<()> {
+        if updates.is_empty() {
+            return Some(());
+        }
+        let mut accounts: Vec<(AccountId, AccountInformation)> = Vec::new();
+        for &(id, asset_id, new_amount) in updates {
+            let mut account_info = match accounts.iter().rposition(|(other, _)| *other == id) {
+                Some(position) => accounts[position].1,
+                None => *self.id_to_account_info.get(&id)?,
+            };
+            *account_info.balances.get_mut(asset_id.0 as usize)? = new_amount;
+            accounts.push((id, account_info));
+        }
+        self.write_accounts(&accounts);
+        self.record_version();
+        for &(id, asset_id, amount) in updates {
+            self.emit(Event::BalanceUpdated {
+                id,
+                asset_id,
+                amount,
+            });
+        }
+        Some(())
     }
 
     /// Update the state by applying the transaction `tx`, if `tx` is valid.
-    pub fn apply_transaction(&mut self, pp: &Parameters, tx: &Transaction) -> Option<()> {
-        if tx.validate(pp, self) {
-            let old_sender_bal = self.id_to_account_info.get(&tx.sender)?.balance;
-            let old_receiver_bal = self.id_to_account_info.get(&tx.recipient)?.balance;
-            let new_sender_bal = old_sender_bal.checked_sub(tx.amount)?;
-            let new_receiver_bal = old_receiver_bal.checked_add(tx.amount)?;
-            self.update_balance(tx.sender, new_sender_bal);
-            self.update_balance(tx.recipient, new_receiver_bal);
-            Some(())
+    /// Returns a receipt describing the changes made by the transaction.
+    pub fn apply_transaction(
+        &mut self,
+        pp: &Parameters<H>,
+        tx: &Transaction,
+    ) -> Option<Receipt<H>> {
+        tx.check(pp, self).ok()?;
+        let old_sender = self.id_to_account_info[&tx.sender];
+        let old_recipient = self.id_to_account_info[&tx.recipient];
+        let (new_sender_bal, new_receiver_bal) =
+            tx.new_balances(&old_sender, Some(&old_recipient)).ok()?;
+        let old_root = self.root();
+        let asset = tx.asset_id.0 as usize;
+        let mut sender = old_sender;
+        *sender.balances.get_mut(asset)? = new_sender_bal;
+        let mut recipient = if tx.sender == tx.recipient {
+            sender
         } else {
-            None
+            old_recipient
+        };
+        *recipient.balances.get_mut(asset)? = new_receiver_bal;
+        self.write_accounts(&[(tx.sender, sender), (tx.recipient, recipient)]);
+        self.record_version();
+
+        let mut balance_changes = BTreeMap::new();
+        for (id, before) in [(tx.sender, old_sender), (tx.recipient, old_recipient)] {
+            let changes = BalanceChange::between(&before, &self.id_to_account_info[&id]);
+            if !changes.is_empty() {
+                balance_changes.insert(id, changes);
+            }
+        }
+        let event = Event::Transferred {
+            sender: tx.sender,
+            recipient: tx.recipient,
+            asset_id: tx.asset_id,
+            amount: tx.amount,
+        };
+        self.emit(event);
+        Some(Receipt {
+            tx_hash: tx.hash(pp),
+            version: self.version,
+            old_root,
+            new_root: self.root(),
+            balance_changes,
+            events: vec![event],
+        })
+    }
+
+    /// Dry-run `transactions` in order, as `apply_transaction` would, without mutating
+    /// the state. Every transaction is checked against the state left by the previous
+    /// successful ones, and failed transactions are skipped.
+    ///
+    /// Updated accounts are kept in an overlay over `self.id_to_account_info`, and the
+    /// resulting root is computed in a copy-on-write overlay over the account tree, so
+    /// no part of the state is cloned. The overlay is trusted to be consistent with the
+    /// resulting root, so Merkle proofs of the senders are not checked.
+    pub fn simulate(
+        &self,
+        pp: &Parameters<H>,
+        transactions: &[Transaction],
+    ) -> SimulationReport<H> {
+        let mut overlay: HashMap<AccountId, AccountInformation> = HashMap::new();
+        let lookup = |overlay: &HashMap<AccountId, AccountInformation>, id: AccountId| {
+            overlay
+                .get(&id)
+                .or_else(|| self.id_to_account_info.get(&id))
+                .copied()
+        };
+        let mut outcomes = Vec::with_capacity(transactions.len());
+        for tx in transactions {
+            let sender = lookup(&overlay, tx.sender);
+            let recipient = lookup(&overlay, tx.recipient);
+            let outcome = tx.check_transfer(pp, sender.as_ref(), recipient.as_ref());
+            if let Ok((new_sender_bal, new_receiver_bal)) = outcome {
+                // Write the balances in the same order as `apply_transaction`.
+                for (id, balance) in [
+                    (tx.sender, new_sender_bal),
+                    (tx.recipient, new_receiver_bal),
+                ] {
+                    let mut account_info = lookup(&overlay, id).expect("should exist");
+                    account_info.balances[tx.asset_id.0 as usize] = balance;
+                    overlay.insert(id, account_info);
+                }
+            }
+            outcomes.push(outcome.map(|_| ()));
+        }
+
+        let mut tree = self.account_merkle_tree.overlay();
+        let leaves: Vec<(usize, Vec<H::LeafElement>)> = overlay
+            .iter()
+            .map(|(id, after)| (id.0 as usize, H::leaf(after)))
+            .collect();
+        tree.update_batch(&leaves).expect("should exist");
+        let mut balance_changes = BTreeMap::new();
+        for (id, after) in &overlay {
+            let changes = BalanceChange::between(&self.id_to_account_info[id], after);
+            if !changes.is_empty() {
+                balance_changes.insert(*id, changes);
+            }
+        }
+        SimulationReport {
+            outcomes,
+            balance_changes,
+            root: tree.root(),
+        }
+    }
+
+    /// Advance the block height to `height`, and record it in the store of the account
+    /// Merkle tree. Heights never decrease.
+    pub fn set_height(&mut self, height: Height) {
+        assert!(height >= self.height, "block height cannot decrease");
+        self.height = height;
+        self.write_accounts_with_records(&[], BTreeMap::from([(HEIGHT_RECORD, record(&height))]));
+    }
+
+    /// Update the state by applying the escrow transaction `tx`, if `tx` is valid.
+    /// Returns the identifier of the escrow that was created or settled.
+    pub fn apply_escrow_transaction(
+        &mut self,
+        pp: &Parameters<H>,
+        tx: &EscrowTransaction,
+    ) -> Option<EscrowId> {
+        if !tx.validate(pp, self) {
+            return None;
+        }
+        let escrow_id = tx.escrow_id(pp);
+        let escrow_record = |escrow: Option<Escrow>| {
+            BTreeMap::from([(self.next_escrow_record(), record(&(escrow_id, escrow)))])
+        };
+        let event = match &tx.action {
+            EscrowAction::Lock(escrow) => {
+                // Move the funds from the sender's balance to its locked amount.
+                let mut sender = self.id_to_account_info[&escrow.sender];
+                let asset = escrow.asset_id.0 as usize;
+                sender.balances[asset] = sender.balances[asset].checked_sub(escrow.amount)?;
+                sender.locked[asset] = sender.locked[asset].checked_add(escrow.amount)?;
+                let records = escrow_record(Some(*escrow));
+                self.write_accounts_with_records(&[(escrow.sender, sender)], records);
+                self.escrows.insert(escrow_id, *escrow);
+                Event::EscrowLocked {
+                    escrow_id,
+                    escrow: *escrow,
+                }
+            }
+            EscrowAction::Claim { .. } | EscrowAction::Refund { .. } => {
+                // Release the locked funds to the recipient for claims, or back to
+                // the sender for refunds.
+                let escrow = self.escrows[&escrow_id];
+                let payee = match tx.action {
+                    EscrowAction::Claim { .. } => escrow.recipient,
+                    _ => escrow.sender,
+                };
+                let asset = escrow.asset_id.0 as usize;
+                let mut sender = self.id_to_account_info[&escrow.sender];
+                sender.locked[asset] = sender.locked[asset].checked_sub(escrow.amount)?;
+                let records = escrow_record(None);
+                if payee == escrow.sender {
+                    sender.balances[asset] = sender.balances[asset].checked_add(escrow.amount)?;
+                    self.write_accounts_with_records(&[(escrow.sender, sender)], records);
+                } else {
+                    let mut recipient = self.id_to_account_info[&payee];
+                    recipient.balances[asset] =
+                        recipient.balances[asset].checked_add(escrow.amount)?;
+                    let accounts = [(payee, recipient), (escrow.sender, sender)];
+                    self.write_accounts_with_records(&accounts, records);
+                }
+                self.escrows.remove(&escrow_id);
+                self.settled_escrows.insert(escrow_id);
+                match tx.action {
+                    EscrowAction::Claim { .. } => Event::EscrowClaimed { escrow_id, escrow },
+                    _ => Event::EscrowRefunded { escrow_id, escrow },
+                }
+            }
+        };
+        self.record_version();
+        self.emit(event);
+        Some(escrow_id)
+    }
+
+    /// Write the information of each account of `accounts` into both the account map
+    /// and the account Merkle tree, where later entries for the same account replace
+    /// earlier ones. The tree is updated with a single batch, which records the account
+    /// information in its store, and its overwritten nodes and accounts are kept in the
+    /// root history.
+    fn write_accounts(&mut self, accounts: &[(AccountId, AccountInformation)]) {
+        self.write_accounts_with_records(accounts, BTreeMap::new());
+    }
+
+    /// Write `accounts` as `write_accounts` does, and write `records` to the store of
+    /// the account Merkle tree in the same batch.
+    fn write_accounts_with_records(
+        &mut self,
+        accounts: &[(AccountId, AccountInformation)],
+        mut records: BTreeMap<u64, Vec<u8>>,
+    ) {
+        let leaves: Vec<(usize, Vec<H::LeafElement>)> = accounts
+            .iter()
+            .map(|(id, account_info)| (id.0 as usize, H::leaf(account_info)))
+            .collect();
+        records.extend(
+            accounts
+                .iter()
+                .map(|(id, account_info)| (id.0 as u64, record(account_info))),
+        );
+        let overwritten = self
+            .account_merkle_tree
+            .update_batch_with_records(&leaves, records)
+            .expect("should exist");
+        self.root_history.record_overwritten(overwritten);
+        let previous: Vec<_> = accounts
+            .iter()
+            .map(|(id, _)| (*id, self.id_to_account_info.get(id).copied()))
+            .collect();
+        self.root_history.record_overwritten_accounts(previous);
+        self.id_to_account_info.extend(accounts.iter().copied());
+    }
+
+    /// Return the key of the next record of the escrow log. Every pending escrow was
+    /// logged once, when it was locked, and every settled escrow twice.
+    fn next_escrow_record(&self) -> u64 {
+        ESCROW_RECORDS + (self.escrows.len() + 2 * self.settled_escrows.len()) as u64
+    }
+
+    /// Advance the version and record the resulting state in the root history.
+    fn record_version(&mut self) {
+        self.version += 1;
+        self.root_history.record(self.snapshot());
+    }
+
+    /// Record `event` as producing the current version.
+    fn emit(&mut self, event: Event) {
+        self.events.record(self.version, event);
+    }
+
+    /// Snapshot the current state.
+    fn snapshot(&self) -> HistoricalState<H> {
+        HistoricalState {
+            version: self.version,
+            root: self.root(),
+            overwritten: NodeBatch::default(),
+            overwritten_accounts: HashMap::new(),
         }
     }
 }
 
+/// Serialize `value` into a record of the store of the account Merkle tree.
+fn record(value: &impl CanonicalSerialize) -> Vec<u8> {
+    let mut record = Vec::new();
+    value
+        .serialize_compressed(&mut record)
+        .expect("serialization into a vector should not fail");
+    record
+}
+
 #[cfg(test)]
 mod test {
-    use super::super::transaction::Transaction;
-    use super::{AccountId, Amount, Parameters, State};
+    use super::super::{
+        escrow::{Escrow, EscrowAction, EscrowTransaction, HashLock},
+        proof::AccountProof,
+        receipt::Event,
+        simulation::BalanceChange,
+        transaction::{Transaction, TransactionError},
+        tree::{AccountTreeConfig, Pedersen, Poseidon},
+    };
+    use super::{
+        AccountId, Amount, AssetId, LedgerId, Parameters, State, ACCOUNT_INFO_SIZE, NUM_ASSETS,
+    };
+    use crate::basic_merkle_tree::storage::FileNodeStore;
+    use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+
+    const USD: AssetId = AssetId(0);
+    const EUR: AssetId = AssetId(1);
 
     #[test]
     fn end_to_end() {
@@ -192,29 +745,603 @@ mod test {
             state.sample_keys_and_register(&pp, &mut rng).unwrap();
         // Let's give her some initial balance to start with.
         state
-            .update_balance(alice_id, Amount(10))
+            .update_balance(alice_id, USD, Amount(10))
             .expect("Alice's account should exist");
         // Let's make an account for Bob.
         let (bob_id, _bob_pk, bob_sk) = state.sample_keys_and_register(&pp, &mut rng).unwrap();
 
         // Alice wants to transfer 5 units to Bob.
-        let tx1 = Transaction::create(&pp, alice_id, bob_id, Amount(5), &alice_sk, &mut rng);
+        let tx1 = Transaction::create(&pp, alice_id, bob_id, USD, Amount(5), &alice_sk, &mut rng);
         assert!(tx1.validate(&pp, &state));
         state.apply_transaction(&pp, &tx1).expect("should work");
         // Let's try creating invalid transactions:
         // First, let's try a transaction where the amount is larger than Alice's balance.
-        let bad_tx = Transaction::create(&pp, alice_id, bob_id, Amount(6), &alice_sk, &mut rng);
+        let bad_tx =
+            Transaction::create(&pp, alice_id, bob_id, USD, Amount(6), &alice_sk, &mut rng);
         assert!(!bad_tx.validate(&pp, &state));
-        assert!(matches!(state.apply_transaction(&pp, &bad_tx), None));
+        assert!(state.apply_transaction(&pp, &bad_tx).is_none());
         // Next, let's try a transaction where the signature is incorrect:
-        let bad_tx = Transaction::create(&pp, alice_id, bob_id, Amount(5), &bob_sk, &mut rng);
+        let bad_tx = Transaction::create(&pp, alice_id, bob_id, USD, Amount(5), &bob_sk, &mut rng);
         assert!(!bad_tx.validate(&pp, &state));
-        assert!(matches!(state.apply_transaction(&pp, &bad_tx), None));
+        assert!(state.apply_transaction(&pp, &bad_tx).is_none());
 
         // Finally, let's try a transaction to an non-existant account:
+        let bad_tx = Transaction::create(
+            &pp,
+            alice_id,
+            AccountId(10),
+            USD,
+            Amount(5),
+            &alice_sk,
+            &mut rng,
+        );
+        assert!(!bad_tx.validate(&pp, &state));
+        assert!(state.apply_transaction(&pp, &bad_tx).is_none());
+    }
+
+    #[test]
+    fn historical_account_proofs() {
+        let mut rng = ark_std::test_rng();
+        let pp = Parameters::sample(&mut rng);
+        let mut state = State::with_root_history(32, &pp, 4);
+        let (alice_id, _alice_pk, alice_sk) =
+            state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        let (bob_id, _bob_pk, _bob_sk) = state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        state.update_balance(alice_id, USD, Amount(10)).unwrap();
+        let funded_version = state.version;
+        let funded_root = state.root();
+        assert_eq!(state.root_at(funded_version), Some(funded_root));
+
+        // A failed transaction does not produce a new version.
         let bad_tx =
-            Transaction::create(&pp, alice_id, AccountId(10), Amount(5), &alice_sk, &mut rng);
+            Transaction::create(&pp, alice_id, bob_id, USD, Amount(11), &alice_sk, &mut rng);
+        assert!(state.apply_transaction(&pp, &bad_tx).is_none());
+        assert_eq!(state.version, funded_version);
+
+        let tx = Transaction::create(&pp, alice_id, bob_id, USD, Amount(4), &alice_sk, &mut rng);
+        state.apply_transaction(&pp, &tx).expect("should work");
+        assert_eq!(state.version, funded_version + 1);
+
+        // Alice's old account information verifies against the old root only.
+        let proof = state.prove_account_at(alice_id, funded_version).unwrap();
+        assert_eq!(proof.info.balance(USD), Some(Amount(10)));
+        assert!(proof.verify(&pp, &funded_root));
+        assert!(!proof.verify(&pp, &state.root()));
+
+        // Bob did not exist at version 1 (after Alice's registration).
+        assert!(state.prove_account_at(bob_id, 1).is_none());
+
+        // Snapshots only keep the accounts that later versions overwrote.
+        let funded = state.root_history.get(funded_version).unwrap();
+        assert_eq!(funded.overwritten_accounts.len(), 2);
+        let newest = state.root_history.get(state.version).unwrap();
+        assert!(newest.overwritten_accounts.is_empty());
+
+        // Only the last 4 versions are retained.
+        for _ in 0..3 {
+            state.update_balance(bob_id, USD, Amount(1)).unwrap();
+        }
+        assert!(state.root_at(funded_version).is_none());
+        assert!(state.prove_account_at(alice_id, funded_version).is_none());
+        assert_eq!(state.root_history.oldest_version(), Some(state.version - 3));
+    }
+
+    #[test]
+    fn file_backed_state() {
+        let mut rng = ark_std::test_rng();
+        let pp = Parameters::sample(&mut rng);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("accounts");
+        let store = FileNodeStore::open(&path, 5, pp.account_tree_digest()).unwrap();
+        let mut state = State::with_store(32, &pp, 4, store).unwrap();
+        let mut expected = State::with_root_history(32, &pp, 4);
+        assert_eq!(state.root(), expected.root());
+
+        let (alice_id, alice_pk, alice_sk) = state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        let (bob_id, bob_pk, bob_sk) = state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        expected.register(alice_pk).unwrap();
+        expected.register(bob_pk).unwrap();
+        state.update_balance(alice_id, USD, Amount(10)).unwrap();
+        expected.update_balance(alice_id, USD, Amount(10)).unwrap();
+        let funded_version = state.version;
+        let tx = Transaction::create(&pp, alice_id, bob_id, USD, Amount(4), &alice_sk, &mut rng);
+        assert!(state.apply_transaction(&pp, &tx).is_some());
+        assert!(expected.apply_transaction(&pp, &tx).is_some());
+        assert_eq!(state.root(), expected.root());
+
+        // Past versions are proven from the file and the overwritten nodes.
+        let proof = state.prove_account_at(alice_id, funded_version).unwrap();
+        assert_eq!(proof.info.balance(USD), Some(Amount(10)));
+        assert!(proof.verify(&pp, &expected.root_at(funded_version).unwrap()));
+        assert!(state
+            .prove_account(bob_id)
+            .unwrap()
+            .verify(&pp, &expected.root()));
+
+        // Alice locks 1 unit for Bob, who claims it, then locks 2 more units until
+        // height 10.
+        let preimage = b"swap secret".to_vec();
+        let terms = Escrow {
+            sender: alice_id,
+            recipient: bob_id,
+            asset_id: USD,
+            amount: Amount(1),
+            hash_lock: HashLock::from_preimage(&preimage),
+            expiry: 10,
+        };
+        let settled_lock =
+            EscrowTransaction::create(&pp, EscrowAction::Lock(terms), &alice_sk, &mut rng);
+        let settled_id = settled_lock.escrow_id(&pp);
+        let claim = EscrowAction::Claim {
+            escrow_id: settled_id,
+            preimage,
+        };
+        let claim = EscrowTransaction::create(&pp, claim, &bob_sk, &mut rng);
+        let terms = Escrow {
+            amount: Amount(2),
+            ..terms
+        };
+        let pending_lock =
+            EscrowTransaction::create(&pp, EscrowAction::Lock(terms), &alice_sk, &mut rng);
+        let pending_id = pending_lock.escrow_id(&pp);
+        state.set_height(3);
+        expected.set_height(3);
+        for tx in [&settled_lock, &claim, &pending_lock] {
+            assert!(state.apply_escrow_transaction(&pp, tx).is_some());
+            assert!(expected.apply_escrow_transaction(&pp, tx).is_some());
+        }
+        assert_eq!(state.root(), expected.root());
+        drop(state);
+
+        // The reopened ledger holds the same accounts, and keeps transacting.
+        let store = FileNodeStore::open(&path, 5, pp.account_tree_digest()).unwrap();
+        let mut state = State::with_store(32, &pp, 4, store).unwrap();
+        assert_eq!(state.root(), expected.root());
+        assert_eq!(state.id_to_account_info, expected.id_to_account_info);
+        assert_eq!(state.pub_key_to_id, expected.pub_key_to_id);
+        assert_eq!(
+            state.next_available_account,
+            expected.next_available_account
+        );
+        // It holds the same escrows and height, so neither lock can be replayed.
+        assert_eq!(state.height, 3);
+        assert_eq!(state.escrows, expected.escrows);
+        assert_eq!(state.settled_escrows, expected.settled_escrows);
+        for lock in [&settled_lock, &pending_lock] {
+            assert!(!lock.validate(&pp, &state));
+            assert!(state.apply_escrow_transaction(&pp, lock).is_none());
+        }
+        assert_eq!(
+            state.id_to_account_info[&alice_id].locked(USD),
+            Some(Amount(2))
+        );
+        let tx = Transaction::create(&pp, alice_id, bob_id, USD, Amount(3), &alice_sk, &mut rng);
+        assert!(state.apply_transaction(&pp, &tx).is_some());
+        assert!(expected.apply_transaction(&pp, &tx).is_some());
+        assert_eq!(state.root(), expected.root());
+        let (carol_id, carol_pk, _carol_sk) =
+            state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        assert_eq!(expected.register(carol_pk), Some(carol_id));
+        assert_eq!(state.root(), expected.root());
+
+        // The pending escrow is refunded after expiry.
+        let refund = EscrowAction::Refund {
+            escrow_id: pending_id,
+        };
+        let refund = EscrowTransaction::create(&pp, refund, &alice_sk, &mut rng);
+        state.set_height(10);
+        expected.set_height(10);
+        assert_eq!(
+            state.apply_escrow_transaction(&pp, &refund),
+            Some(pending_id)
+        );
+        assert_eq!(
+            expected.apply_escrow_transaction(&pp, &refund),
+            Some(pending_id)
+        );
+        assert_eq!(state.root(), expected.root());
+        assert_eq!(
+            state.id_to_account_info[&alice_id].balance(USD),
+            Some(Amount(2))
+        );
+
+        // A store of a ledger with other parameters cannot be opened.
+        let other = Parameters::sample(&mut rng);
+        assert!(
+            FileNodeStore::<<Pedersen as AccountTreeConfig>::Config>::open(
+                &path,
+                5,
+                other.account_tree_digest()
+            )
+            .is_err()
+        );
+        // Nor can a ledger for another number of accounts open it.
+        let store = FileNodeStore::open(&path, 5, pp.account_tree_digest()).unwrap();
+        assert!(State::with_store(64, &pp, 4, store).is_err());
+    }
+
+    #[test]
+    fn batched_updates() {
+        let mut rng = ark_std::test_rng();
+        let pp = Parameters::sample(&mut rng);
+        let mut state = State::with_root_history(32, &pp, 4);
+        let mut expected = State::with_root_history(32, &pp, 4);
+        let mut keys = Vec::new();
+        for _ in 0..3 {
+            keys.push(expected.sample_keys_and_register(&pp, &mut rng).unwrap().1);
+        }
+        expected
+            .update_balance(AccountId(1), USD, Amount(5))
+            .unwrap();
+        expected
+            .update_balance(AccountId(1), EUR, Amount(7))
+            .unwrap();
+        expected
+            .update_balance(AccountId(2), USD, Amount(3))
+            .unwrap();
+
+        // A duplicate key is rejected, and the batch is a single version.
+        let ids = state.register_batch(&[keys[0], keys[1], keys[0], keys[2]]);
+        assert_eq!(
+            ids,
+            vec![
+                Some(AccountId(1)),
+                Some(AccountId(2)),
+                None,
+                Some(AccountId(3))
+            ]
+        );
+        assert_eq!(state.version, 1);
+        // Invalid batches leave the state unchanged.
+        let root = state.root();
+        assert!(state
+            .update_balances(&[
+                (AccountId(1), USD, Amount(5)),
+                (AccountId(9), USD, Amount(1))
+            ])
+            .is_none());
+        assert_eq!((state.version, state.root()), (1, root));
+        state
+            .update_balances(&[
+                (AccountId(1), USD, Amount(1)),
+                (AccountId(2), USD, Amount(3)),
+                (AccountId(1), EUR, Amount(7)),
+                (AccountId(1), USD, Amount(5)),
+            ])
+            .unwrap();
+        assert_eq!(state.version, 2);
+        assert_eq!(state.root(), expected.root());
+        assert_eq!(state.events.records().len(), 3 + 4);
+
+        // Past versions are still proven.
+        let proof = state.prove_account_at(AccountId(1), 1).unwrap();
+        assert_eq!(proof.info.balance(USD), Some(Amount(0)));
+        assert!(proof.verify(&pp, &root));
+    }
+
+    #[test]
+    fn account_proofs() {
+        check_account_proofs::<Pedersen>();
+        check_account_proofs::<Poseidon>();
+    }
+
+    fn check_account_proofs<H: AccountTreeConfig>() {
+        let mut rng = ark_std::test_rng();
+        let pp = Parameters::<H>::sample_with_tree(&mut rng);
+        let mut state = State::new(32, &pp);
+        let (alice_id, alice_pk, _alice_sk) =
+            state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        state.update_balance(alice_id, USD, Amount(10)).unwrap();
+        let root = state.root();
+
+        // A proof survives a round trip through its serialized form, and can be
+        // checked with the parameters and the root alone.
+        let proof = state.prove_account(alice_id).unwrap();
+        let mut bytes = Vec::new();
+        proof.serialize_compressed(&mut bytes).unwrap();
+        let proof = AccountProof::<H>::deserialize_compressed(bytes.as_slice()).unwrap();
+        assert_eq!(proof.id(), Some(alice_id));
+        assert_eq!(proof.info.public_key, alice_pk);
+        assert_eq!(proof.info.balance(USD), Some(Amount(10)));
+        assert!(proof.verify(&pp, &root));
+        // The account information has the size checked against the Pedersen windows.
+        assert_eq!(proof.info.to_bytes_le().len(), ACCOUNT_INFO_SIZE);
+
+        // Tampering with the account information invalidates the proof.
+        let mut forged = proof.clone();
+        forged.info.balances[USD.0 as usize] = Amount(1000);
+        assert!(!forged.verify(&pp, &root));
+
+        // A leaf index beyond the range of account identifiers has no identifier.
+        let mut out_of_range = proof.clone();
+        out_of_range.path.leaf_index = 256;
+        assert_eq!(out_of_range.id(), None);
+
+        // The proof is stale once the account changes.
+        state.update_balance(alice_id, USD, Amount(5)).unwrap();
+        assert!(!proof.verify(&pp, &state.root()));
+        assert!(state.prove_account(AccountId(10)).is_none());
+    }
+
+    #[test]
+    fn batch_validation() {
+        let mut rng = ark_std::test_rng();
+        let pp = Parameters::sample(&mut rng);
+        let mut state = State::new(32, &pp);
+        let (alice_id, _alice_pk, alice_sk) =
+            state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        let (bob_id, _bob_pk, bob_sk) = state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        state.update_balance(alice_id, USD, Amount(10)).unwrap();
+        state.update_balance(bob_id, USD, Amount(3)).unwrap();
+
+        let txs = vec![
+            Transaction::create(&pp, alice_id, bob_id, USD, Amount(5), &alice_sk, &mut rng),
+            // Insufficient balance.
+            Transaction::create(&pp, bob_id, alice_id, USD, Amount(4), &bob_sk, &mut rng),
+            // Signed by the wrong key.
+            Transaction::create(&pp, alice_id, bob_id, USD, Amount(1), &bob_sk, &mut rng),
+            Transaction::create(&pp, bob_id, alice_id, USD, Amount(3), &bob_sk, &mut rng),
+            // Unknown recipient.
+            Transaction::create(
+                &pp,
+                alice_id,
+                AccountId(10),
+                USD,
+                Amount(1),
+                &alice_sk,
+                &mut rng,
+            ),
+        ];
+        let expected: Vec<bool> = txs.iter().map(|tx| tx.validate(&pp, &state)).collect();
+        assert_eq!(expected, vec![true, false, false, true, false]);
+        assert_eq!(Transaction::validate_batch(&txs, &pp, &state), expected);
+    }
+
+    #[test]
+    fn signatures_are_bound_to_the_ledger() {
+        let mut rng = ark_std::test_rng();
+        let testnet = Parameters::sample(&mut rng).with_ledger_id(LedgerId(b"testnet".to_vec()));
+        let mainnet = testnet
+            .clone()
+            .with_ledger_id(LedgerId(b"mainnet".to_vec()));
+        // The same key is registered under the same identifier on both ledgers.
+        let mut testnet_state = State::new(32, &testnet);
+        let mut mainnet_state = State::new(32, &mainnet);
+        let (alice_id, alice_pk, alice_sk) = testnet_state
+            .sample_keys_and_register(&testnet, &mut rng)
+            .unwrap();
+        let (bob_id, bob_pk, _bob_sk) = testnet_state
+            .sample_keys_and_register(&testnet, &mut rng)
+            .unwrap();
+        assert_eq!(mainnet_state.register(alice_pk), Some(alice_id));
+        assert_eq!(mainnet_state.register(bob_pk), Some(bob_id));
+        testnet_state
+            .update_balance(alice_id, USD, Amount(10))
+            .unwrap();
+        mainnet_state
+            .update_balance(alice_id, USD, Amount(10))
+            .unwrap();
+
+        // A testnet transaction cannot be replayed on mainnet.
+        let tx = Transaction::create(
+            &testnet,
+            alice_id,
+            bob_id,
+            USD,
+            Amount(5),
+            &alice_sk,
+            &mut rng,
+        );
+        assert_ne!(tx.message(&testnet), tx.message(&mainnet));
+        assert!(tx.validate(&testnet, &testnet_state));
+        assert!(!tx.validate(&mainnet, &mainnet_state));
+    }
+
+    #[test]
+    fn multi_asset_transfers() {
+        let mut rng = ark_std::test_rng();
+        let pp = Parameters::sample(&mut rng);
+        let mut state = State::new(32, &pp);
+        let (alice_id, _alice_pk, alice_sk) =
+            state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        let (bob_id, _bob_pk, _bob_sk) = state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        state.update_balance(alice_id, USD, Amount(10)).unwrap();
+        state.update_balance(alice_id, EUR, Amount(3)).unwrap();
+        assert!(state
+            .update_balance(alice_id, AssetId(NUM_ASSETS as u8), Amount(1))
+            .is_none());
+
+        // Balances are tracked per asset.
+        let tx = Transaction::create(&pp, alice_id, bob_id, EUR, Amount(3), &alice_sk, &mut rng);
+        state.apply_transaction(&pp, &tx).expect("should work");
+        let alice = state.id_to_account_info[&alice_id];
+        let bob = state.id_to_account_info[&bob_id];
+        assert_eq!(alice.balance(USD), Some(Amount(10)));
+        assert_eq!(alice.balance(EUR), Some(Amount(0)));
+        assert_eq!(bob.balance(USD), Some(Amount(0)));
+        assert_eq!(bob.balance(EUR), Some(Amount(3)));
+
+        // Alice's USD balance does not finance an EUR transfer.
+        let bad_tx =
+            Transaction::create(&pp, alice_id, bob_id, EUR, Amount(1), &alice_sk, &mut rng);
+        assert!(!bad_tx.validate(&pp, &state));
+        // Transfers of unknown assets are rejected.
+        let bad_tx = Transaction::create(
+            &pp,
+            alice_id,
+            bob_id,
+            AssetId(NUM_ASSETS as u8),
+            Amount(1),
+            &alice_sk,
+            &mut rng,
+        );
         assert!(!bad_tx.validate(&pp, &state));
-        assert!(matches!(state.apply_transaction(&pp, &bad_tx), None));
+        assert!(state.apply_transaction(&pp, &bad_tx).is_none());
+    }
+
+    #[test]
+    fn simulation_matches_application() {
+        let mut rng = ark_std::test_rng();
+        let pp = Parameters::sample(&mut rng);
+        let mut state = State::new(32, &pp);
+        let (alice_id, _alice_pk, alice_sk) =
+            state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        let (bob_id, _bob_pk, bob_sk) = state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        let (carol_id, _carol_pk, _carol_sk) =
+            state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        state.update_balance(alice_id, USD, Amount(10)).unwrap();
+        state.update_balance(alice_id, EUR, Amount(2)).unwrap();
+        state
+            .update_balance(carol_id, EUR, Amount(u64::MAX))
+            .unwrap();
+
+        let txs = vec![
+            Transaction::create(&pp, alice_id, bob_id, USD, Amount(6), &alice_sk, &mut rng),
+            // Only valid after the first transaction.
+            Transaction::create(&pp, bob_id, carol_id, USD, Amount(4), &bob_sk, &mut rng),
+            Transaction::create(&pp, alice_id, bob_id, USD, Amount(5), &alice_sk, &mut rng),
+            Transaction::create(&pp, alice_id, bob_id, USD, Amount(1), &bob_sk, &mut rng),
+            Transaction::create(
+                &pp,
+                alice_id,
+                AccountId(10),
+                USD,
+                Amount(1),
+                &alice_sk,
+                &mut rng,
+            ),
+            Transaction::create(
+                &pp,
+                AccountId(10),
+                bob_id,
+                USD,
+                Amount(1),
+                &alice_sk,
+                &mut rng,
+            ),
+            Transaction::create(
+                &pp,
+                alice_id,
+                bob_id,
+                AssetId(NUM_ASSETS as u8),
+                Amount(1),
+                &alice_sk,
+                &mut rng,
+            ),
+            Transaction::create(&pp, alice_id, carol_id, EUR, Amount(1), &alice_sk, &mut rng),
+            Transaction::create(&pp, alice_id, bob_id, EUR, Amount(2), &alice_sk, &mut rng),
+        ];
+        let (version, root) = (state.version, state.root());
+        let report = state.simulate(&pp, &txs);
+        assert_eq!(
+            report.outcomes,
+            vec![
+                Ok(()),
+                Ok(()),
+                Err(TransactionError::InsufficientBalance),
+                Err(TransactionError::InvalidSignature),
+                Err(TransactionError::UnknownRecipient),
+                Err(TransactionError::UnknownSender),
+                Err(TransactionError::InvalidAsset),
+                Err(TransactionError::BalanceOverflow),
+                Ok(()),
+            ]
+        );
+        assert!(!report.all_succeeded());
+        // The state is untouched.
+        assert_eq!((state.version, state.root()), (version, root));
+
+        let change = |before, after| {
+            Some(BalanceChange {
+                before: Amount(before),
+                after: Amount(after),
+            })
+        };
+        assert_eq!(report.balance_change(alice_id, USD), change(10, 4));
+        assert_eq!(report.balance_change(alice_id, EUR), change(2, 0));
+        assert_eq!(report.balance_change(bob_id, USD), change(0, 2));
+        assert_eq!(report.balance_change(bob_id, EUR), change(0, 2));
+        assert_eq!(report.balance_change(carol_id, USD), change(0, 4));
+        assert_eq!(report.balance_change(carol_id, EUR), None);
+
+        // Applying the transactions yields the same outcomes and the same root.
+        for (tx, outcome) in txs.iter().zip(&report.outcomes) {
+            assert_eq!(state.apply_transaction(&pp, tx).is_some(), outcome.is_ok());
+        }
+        assert_eq!(state.root(), report.root);
+        assert_eq!(state.simulate(&pp, &[]).root, state.root());
+    }
+
+    #[test]
+    fn receipts_and_events() {
+        let mut rng = ark_std::test_rng();
+        let pp = Parameters::sample(&mut rng);
+        let mut state = State::new(32, &pp);
+        let (alice_id, alice_pk, alice_sk) = state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        let (bob_id, _bob_pk, _bob_sk) = state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        let (carol_id, _carol_pk, _carol_sk) =
+            state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        state.update_balance(alice_id, USD, Amount(10)).unwrap();
+        let funded_version = state.version;
+
+        let old_root = state.root();
+        let tx = Transaction::create(&pp, alice_id, bob_id, USD, Amount(4), &alice_sk, &mut rng);
+        let receipt = state.apply_transaction(&pp, &tx).unwrap();
+        let transferred = Event::Transferred {
+            sender: alice_id,
+            recipient: bob_id,
+            asset_id: USD,
+            amount: Amount(4),
+        };
+        assert_eq!(receipt.tx_hash, tx.hash(&pp));
+        assert_eq!(receipt.version, state.version);
+        assert_eq!(
+            (receipt.old_root, receipt.new_root),
+            (old_root, state.root())
+        );
+        assert_eq!(receipt.events, vec![transferred]);
+        let alice_changes = &receipt.balance_changes[&alice_id];
+        let bob_changes = &receipt.balance_changes[&bob_id];
+        assert_eq!(alice_changes.len(), 1);
+        assert_eq!(alice_changes[&USD].before, Amount(10));
+        assert_eq!(alice_changes[&USD].after, Amount(6));
+        assert_eq!(bob_changes[&USD].before, Amount(0));
+        assert_eq!(bob_changes[&USD].after, Amount(4));
+        // Failed transactions produce neither receipts nor events.
+        let events = state.events.records().len();
+        let bad_tx =
+            Transaction::create(&pp, alice_id, bob_id, USD, Amount(7), &alice_sk, &mut rng);
+        assert!(state.apply_transaction(&pp, &bad_tx).is_none());
+        assert_eq!(state.events.records().len(), events);
+
+        // Alice's activity feed.
+        let feed: Vec<_> = state.events.for_account(alice_id).collect();
+        assert_eq!(feed.len(), 3);
+        assert_eq!(
+            feed[0].event,
+            Event::AccountCreated {
+                id: alice_id,
+                public_key: alice_pk
+            }
+        );
+        assert_eq!(feed[0].version, 1);
+        assert_eq!(
+            feed[1].event,
+            Event::BalanceUpdated {
+                id: alice_id,
+                asset_id: USD,
+                amount: Amount(10)
+            }
+        );
+        assert_eq!(feed[2].event, transferred);
+        assert_eq!(feed[2].version, receipt.version);
+        let recent: Vec<_> = state
+            .events
+            .for_account_since(alice_id, funded_version)
+            .collect();
+        assert_eq!(recent, vec![feed[2]]);
+        assert_eq!(state.events.for_account(bob_id).count(), 2);
+        assert_eq!(state.events.for_account(carol_id).count(), 1);
+        assert_eq!(state.events.for_account(AccountId(10)).count(), 0);
     }
 }
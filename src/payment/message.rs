@@ -0,0 +1,82 @@
+use super::{
+    account::AccountId,
+    ledger::{Amount, AssetId, LedgerId},
+};
+
+/// Domain separation tag shared by every message signed on a payment ledger.
+pub const MESSAGE_DOMAIN: &[u8] = b"arkworks-demo/payment";
+
+/// The kind of transaction a signed message authorizes.
+#[derive(Hash, Eq, PartialEq, Copy, Clone, Debug)]
+pub enum TransactionKind {
+    /// A transfer from one account to another.
+    Transfer,
+    /// Locking funds in a hash-locked and time-locked escrow.
+    EscrowLock,
+    /// Claiming the funds of an escrow by revealing the preimage of its hash lock.
+    EscrowClaim,
+    /// Refunding the funds of an expired escrow to its sender.
+    EscrowRefund,
+}
+
+impl TransactionKind {
+    /// The one-byte tag identifying this kind of transaction in signed messages.
+    pub fn tag(&self) -> u8 {
+        match self {
+            TransactionKind::Transfer => 1,
+            TransactionKind::EscrowLock => 2,
+            TransactionKind::EscrowClaim => 3,
+            TransactionKind::EscrowRefund => 4,
+        }
+    }
+}
+
+/// Encodes a signed message as a sequence of length-prefixed fields:
+/// (MESSAGE_DOMAIN || LedgerId || TransactionKind tag || field_1 || ... || field_n),
+/// where every field is preceded by its length as a little-endian `u32`.
+/// Binding the ledger identifier and the transaction kind into the message prevents a
+/// signature from being replayed on another ledger, or as another kind of transaction.
+pub struct MessageEncoder {
+    bytes: Vec<u8>,
+}
+
+impl MessageEncoder {
+    /// Start a message of kind `kind` for the ledger `ledger_id`.
+    pub fn new(ledger_id: &LedgerId, kind: TransactionKind) -> Self {
+        let mut encoder = Self { bytes: Vec::new() };
+        encoder.field(MESSAGE_DOMAIN);
+        encoder.field(&ledger_id.0);
+        encoder.field(&[kind.tag()]);
+        encoder
+    }
+
+    /// Append a length-prefixed field.
+    pub fn field(&mut self, field: &[u8]) -> &mut Self {
+        self.bytes.extend((field.len() as u32).to_le_bytes());
+        self.bytes.extend(field);
+        self
+    }
+
+    /// Return the encoded message.
+    pub fn finish(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.bytes)
+    }
+}
+
+/// The message authorizing a transfer of `amount` units of asset `asset_id` from
+/// `sender` to `recipient`: (SenderAccId || RecipientAccId || AssetId || Amount),
+/// encoded with `MessageEncoder`.
+pub fn transfer_message(
+    ledger_id: &LedgerId,
+    sender: AccountId,
+    recipient: AccountId,
+    asset_id: AssetId,
+    amount: Amount,
+) -> Vec<u8> {
+    MessageEncoder::new(ledger_id, TransactionKind::Transfer)
+        .field(&sender.to_bytes_le())
+        .field(&recipient.to_bytes_le())
+        .field(&asset_id.to_bytes_le())
+        .field(&amount.to_bytes_le())
+        .finish()
+}
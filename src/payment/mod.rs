@@ -1,3 +1,14 @@
 pub mod account;
+pub mod escrow;
+pub mod history;
 pub mod ledger;
-pub mod transaction;
\ No newline at end of file
+pub mod message;
+pub mod proof;
+pub mod receipt;
+pub mod signature;
+pub mod simulation;
+pub mod transaction;
+pub mod tree;
+
+#[cfg(test)]
+mod invariants;
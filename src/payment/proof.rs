@@ -0,0 +1,44 @@
+use super::{
+    account::{AccountId, AccountInformation},
+    ledger::{AccPath, AccRoot, Parameters},
+    tree::{AccountTreeConfig, Pedersen},
+};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+
+/// A proof that an account holds `info` in the account Merkle tree with root `root`.
+/// It can be checked by light clients that only know the ledger parameters and a
+/// trusted root, without access to the ledger `State`.
+#[derive(Clone, Debug, CanonicalSerialize, CanonicalDeserialize)]
+pub struct AccountProof<H: AccountTreeConfig = Pedersen> {
+    /// The account information being proven.
+    pub info: AccountInformation,
+    /// The authentication path of the account's leaf. Its leaf index is the account
+    /// identifier.
+    pub path: AccPath<H>,
+    /// The root against which `path` was generated.
+    pub root: AccRoot<H>,
+}
+
+impl<H: AccountTreeConfig> AccountProof<H> {
+    /// Return the identifier of the proven account, or `None` if the leaf index of the
+    /// path is not a valid account identifier.
+    pub fn id(&self) -> Option<AccountId> {
+        u8::try_from(self.path.leaf_index).ok().map(AccountId)
+    }
+
+    /// Check that the proof was generated against `root`, and that `self.info` is
+    /// a leaf of the account Merkle tree with that root.
+    pub fn verify(&self, parameters: &Parameters<H>, root: &AccRoot<H>) -> bool {
+        if &self.root != root {
+            return false;
+        }
+        self.path
+            .verify(
+                &parameters.leaf_crh_params,
+                &parameters.two_to_one_crh_params,
+                root,
+                H::leaf(&self.info).as_slice(),
+            )
+            .unwrap_or(false)
+    }
+}
@@ -0,0 +1,127 @@
+use super::{
+    account::{AccountId, AccountPublicKey},
+    escrow::{Escrow, EscrowId},
+    history::Version,
+    ledger::{AccRoot, Amount, AssetId},
+    simulation::BalanceChange,
+    transaction::TxHash,
+    tree::{AccountTreeConfig, Pedersen},
+};
+use std::collections::{BTreeMap, HashMap};
+
+/// Something that happened on the ledger.
+#[derive(Hash, Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Event {
+    /// A new account was registered.
+    AccountCreated {
+        id: AccountId,
+        public_key: AccountPublicKey,
+    },
+    /// The balance of an account was set directly with `State::update_balance`.
+    BalanceUpdated {
+        id: AccountId,
+        asset_id: AssetId,
+        amount: Amount,
+    },
+    /// Funds were transferred from one account to another.
+    Transferred {
+        sender: AccountId,
+        recipient: AccountId,
+        asset_id: AssetId,
+        amount: Amount,
+    },
+    /// Funds were locked in an escrow.
+    EscrowLocked { escrow_id: EscrowId, escrow: Escrow },
+    /// The funds of an escrow were released to its recipient.
+    EscrowClaimed { escrow_id: EscrowId, escrow: Escrow },
+    /// The funds of an expired escrow were returned to its sender.
+    EscrowRefunded { escrow_id: EscrowId, escrow: Escrow },
+}
+
+impl Event {
+    /// The accounts involved in the event, without duplicates.
+    pub fn accounts(&self) -> Vec<AccountId> {
+        let (first, second) = match self {
+            Event::AccountCreated { id, .. } | Event::BalanceUpdated { id, .. } => (*id, *id),
+            Event::Transferred {
+                sender, recipient, ..
+            } => (*sender, *recipient),
+            Event::EscrowLocked { escrow, .. }
+            | Event::EscrowClaimed { escrow, .. }
+            | Event::EscrowRefunded { escrow, .. } => (escrow.sender, escrow.recipient),
+        };
+        if first == second {
+            vec![first]
+        } else {
+            vec![first, second]
+        }
+    }
+}
+
+/// An event, together with the version of the state it produced.
+#[derive(Hash, Eq, PartialEq, Copy, Clone, Debug)]
+pub struct EventRecord {
+    pub version: Version,
+    pub event: Event,
+}
+
+/// The record of an applied transaction.
+#[derive(Clone, Debug)]
+pub struct Receipt<H: AccountTreeConfig = Pedersen> {
+    /// The hash of the applied transaction.
+    pub tx_hash: TxHash,
+    /// The version of the state produced by the transaction.
+    pub version: Version,
+    /// The root of the account Merkle tree before the transaction.
+    pub old_root: AccRoot<H>,
+    /// The root of the account Merkle tree after the transaction.
+    pub new_root: AccRoot<H>,
+    /// The balances changed by the transaction, by account and asset.
+    pub balance_changes: BTreeMap<AccountId, BTreeMap<AssetId, BalanceChange>>,
+    /// The events emitted by the transaction.
+    pub events: Vec<Event>,
+}
+
+/// An append-only log of events, indexed by the accounts involved in them.
+#[derive(Clone, Debug, Default)]
+pub struct EventLog {
+    records: Vec<EventRecord>,
+    /// For each account, the positions in `records` of the events involving it.
+    by_account: HashMap<AccountId, Vec<usize>>,
+}
+
+impl EventLog {
+    /// Append `event`, which produced `version`.
+    pub fn record(&mut self, version: Version, event: Event) {
+        let position = self.records.len();
+        for id in event.accounts() {
+            self.by_account.entry(id).or_default().push(position);
+        }
+        self.records.push(EventRecord { version, event });
+    }
+
+    /// All the events, oldest first.
+    pub fn records(&self) -> &[EventRecord] {
+        &self.records
+    }
+
+    /// The events involving account `id`, oldest first.
+    pub fn for_account(&self, id: AccountId) -> impl Iterator<Item = &EventRecord> + '_ {
+        self.by_account
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .map(|&position| &self.records[position])
+    }
+
+    /// The events involving account `id` that produced a version after `version`,
+    /// oldest first. This is the feed of account activity since `version`.
+    pub fn for_account_since(
+        &self,
+        id: AccountId,
+        version: Version,
+    ) -> impl Iterator<Item = &EventRecord> + '_ {
+        self.for_account(id)
+            .skip_while(move |record| record.version <= version)
+    }
+}
@@ -0,0 +1,102 @@
+use super::{account::AccountPublicKey, ledger::SignatureParameters};
+use ark_crypto_primitives::signature::schnorr::Signature;
+use ark_ec::{AffineRepr, CurveGroup};
+use ark_ed_on_bls12_381::{EdwardsAffine, EdwardsProjective as JubJub, Fr};
+use ark_ff::{Field, PrimeField};
+use ark_serialize::CanonicalSerialize;
+use ark_std::{cfg_into_iter, cfg_iter};
+use blake2::{Blake2s256 as Blake2s, Digest};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// A message, the public key it should be signed under, and the claimed signature.
+pub type SignedMessage<'a> = (&'a [u8], &'a AccountPublicKey, &'a Signature<JubJub>);
+
+/// Verify many Schnorr signatures at once. Returns `Ok(())` if every signature is
+/// valid, and otherwise the indices of the invalid entries of `batch`, in increasing
+/// order.
+///
+/// A signature stores the verifier challenge `e = H(salt || R || msg)` rather than the
+/// prover commitment `R`, so every commitment `R = s·G + e·pk` has to be recomputed
+/// and hashed on its own; the entries cannot be folded into a single random linear
+/// combination. Instead, the batch shares the projective-to-affine normalization of
+/// all commitments (a single field inversion), and the scalar multiplications and
+/// hashing run on the rayon pool when the `parallel` feature is enabled.
+pub fn verify_batch(pp: &SignatureParameters, batch: &[SignedMessage]) -> Result<(), Vec<usize>> {
+    // Recompute the prover commitments s·G + e·pk.
+    let commitments: Vec<JubJub> = cfg_iter!(batch)
+        .map(|(_, public_key, signature)| {
+            pp.generator
+                .mul_bigint(signature.prover_response.into_bigint())
+                + public_key.mul_bigint(signature.verifier_challenge.into_bigint())
+        })
+        .collect();
+    let commitments = JubJub::normalize_batch(&commitments);
+    // Recompute the verifier challenges, and compare against the claimed ones.
+    let failed: Vec<usize> = cfg_into_iter!(0..batch.len())
+        .filter(|&i| {
+            let (message, _, signature) = batch[i];
+            challenge(pp, &commitments[i], message) != Some(signature.verifier_challenge)
+        })
+        .collect();
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(failed)
+    }
+}
+
+/// Compute the Schnorr verifier challenge `H(salt || commitment || message)`, exactly
+/// as `Schnorr::verify` does. Returns `None` if the digest is not a valid scalar.
+fn challenge(pp: &SignatureParameters, commitment: &EdwardsAffine, message: &[u8]) -> Option<Fr> {
+    let mut hash_input = Vec::new();
+    pp.salt.serialize_compressed(&mut hash_input).ok()?;
+    commitment.serialize_compressed(&mut hash_input).ok()?;
+    message.serialize_compressed(&mut hash_input).ok()?;
+    Fr::from_random_bytes(&Blake2s::digest(&hash_input))
+}
+
+#[cfg(test)]
+mod test {
+    use super::verify_batch;
+    use crate::payment::ledger::SignatureParameters;
+    use ark_crypto_primitives::signature::{schnorr::Schnorr, SignatureScheme};
+    use ark_ed_on_bls12_381::EdwardsProjective as JubJub;
+    use blake2::Blake2s256 as Blake2s;
+
+    type Sig = Schnorr<JubJub, Blake2s>;
+
+    #[test]
+    fn batch_verification_matches_single_verification() {
+        let mut rng = ark_std::test_rng();
+        let pp: SignatureParameters = Sig::setup(&mut rng).unwrap();
+        let keys: Vec<_> = (0..4)
+            .map(|_| Sig::keygen(&pp, &mut rng).unwrap())
+            .collect();
+        let messages: Vec<Vec<u8>> = (0..8u8).map(|i| vec![i; i as usize + 1]).collect();
+        let mut signatures: Vec<_> = messages
+            .iter()
+            .enumerate()
+            .map(|(i, message)| Sig::sign(&pp, &keys[i % 4].1, message, &mut rng).unwrap())
+            .collect();
+        let mut public_keys: Vec<_> = (0..8).map(|i| keys[i % 4].0).collect();
+
+        let batch: Vec<_> = (0..8)
+            .map(|i| (messages[i].as_slice(), &public_keys[i], &signatures[i]))
+            .collect();
+        assert_eq!(verify_batch(&pp, &batch), Ok(()));
+        assert_eq!(verify_batch(&pp, &[]), Ok(()));
+
+        // Corrupt a signature, and sign entry 6 under the wrong key.
+        signatures[2] = signatures[3].clone();
+        public_keys[6] = keys[0].0;
+        let batch: Vec<_> = (0..8)
+            .map(|i| (messages[i].as_slice(), &public_keys[i], &signatures[i]))
+            .collect();
+        assert_eq!(verify_batch(&pp, &batch), Err(vec![2, 6]));
+        for (i, (message, public_key, signature)) in batch.iter().enumerate() {
+            let expected = Sig::verify(&pp, public_key, message, signature).unwrap();
+            assert_eq!(expected, i != 2 && i != 6);
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use super::{
+    account::{AccountId, AccountInformation},
+    ledger::{AccRoot, Amount, AssetId, NUM_ASSETS},
+    transaction::TransactionError,
+    tree::{AccountTreeConfig, Pedersen},
+};
+use std::collections::BTreeMap;
+
+/// The outcome of a single transaction in a simulation.
+pub type TransactionOutcome = Result<(), TransactionError>;
+
+/// The balance of an account in one asset, before and after a change.
+#[derive(Hash, Eq, PartialEq, Copy, Clone, Debug)]
+pub struct BalanceChange {
+    pub before: Amount,
+    pub after: Amount,
+}
+
+impl BalanceChange {
+    /// The balances that differ between two versions of the same account, by asset.
+    pub fn between(
+        before: &AccountInformation,
+        after: &AccountInformation,
+    ) -> BTreeMap<AssetId, BalanceChange> {
+        (0..NUM_ASSETS)
+            .filter(|&asset| before.balances[asset] != after.balances[asset])
+            .map(|asset| {
+                let change = BalanceChange {
+                    before: before.balances[asset],
+                    after: after.balances[asset],
+                };
+                (AssetId(asset as u8), change)
+            })
+            .collect()
+    }
+}
+
+/// The result of dry-running a sequence of transactions with `State::simulate`.
+#[derive(Clone, Debug)]
+pub struct SimulationReport<H: AccountTreeConfig = Pedersen> {
+    /// The outcome of each transaction, in the order they were given.
+    pub outcomes: Vec<TransactionOutcome>,
+    /// The balances that differ after the simulation, by account and asset.
+    pub balance_changes: BTreeMap<AccountId, BTreeMap<AssetId, BalanceChange>>,
+    /// The root of the account Merkle tree after applying the successful transactions.
+    pub root: AccRoot<H>,
+}
+
+impl<H: AccountTreeConfig> SimulationReport<H> {
+    /// Did every transaction succeed?
+    pub fn all_succeeded(&self) -> bool {
+        self.outcomes.iter().all(Result::is_ok)
+    }
+
+    /// Return the balance change of account `id` in asset `asset_id`, if it changed.
+    pub fn balance_change(&self, id: AccountId, asset_id: AssetId) -> Option<BalanceChange> {
+        self.balance_changes.get(&id)?.get(&asset_id).copied()
+    }
+}
@@ -1,7 +1,11 @@
 use super::{
-    account::{AccountId, AccountPublicKey, AccountSecretKey},
-    ledger::{self, Amount, SignatureParameters},
+    account::{AccountId, AccountInformation, AccountPublicKey, AccountSecretKey},
+    ledger::{self, Amount, AssetId},
+    message::transfer_message,
+    signature,
+    tree::AccountTreeConfig,
 };
+use crate::basic_merkle_tree::storage::NodeStore;
 use ark_crypto_primitives::signature::{
     schnorr::{Schnorr, Signature},
     SignatureScheme,
@@ -9,7 +13,31 @@ use ark_crypto_primitives::signature::{
 use ark_ed_on_bls12_381::EdwardsProjective as JubJub;
 use ark_serialize::CanonicalSerialize;
 use ark_std::rand::Rng;
-use blake2::Blake2s256 as Blake2s;
+use blake2::{Blake2s256 as Blake2s, Digest};
+
+/// The reasons a transaction can be rejected.
+#[derive(Hash, Eq, PartialEq, Copy, Clone, Debug)]
+pub enum TransactionError {
+    /// The sender's account does not exist.
+    UnknownSender,
+    /// The sender's account information is not committed in the account Merkle tree.
+    InvalidAccountProof,
+    /// The asset being transferred does not exist.
+    InvalidAsset,
+    /// The sender's balance in the asset is smaller than the amount.
+    InsufficientBalance,
+    /// The recipient's account does not exist.
+    UnknownRecipient,
+    /// The recipient's balance in the asset would overflow.
+    BalanceOverflow,
+    /// The signature is not valid under the sender's public key.
+    InvalidSignature,
+}
+
+/// Identifies a transaction. It is the Blake2s hash of the signed message and of the
+/// signature.
+#[derive(Hash, Eq, PartialEq, Copy, Clone, Debug)]
+pub struct TxHash(pub [u8; 32]);
 
 /// Transaction transferring some amount from one account to another.
 #[derive(Clone, Debug)]
@@ -18,25 +46,51 @@ pub struct Transaction {
     pub sender: AccountId,
     /// The account information of the recipient.
     pub recipient: AccountId,
+    /// The asset being transferred.
+    pub asset_id: AssetId,
     /// The amount being transferred from the sender to the receiver.
     pub amount: Amount,
-    /// The spend authorization is a signature over the sender, the recipient,
-    /// and the amount.
+    /// The spend authorization is a signature over the ledger identifier, the sender,
+    /// the recipient, the asset, and the amount.
     pub signature: Signature<JubJub>,
 }
 
 impl Transaction {
+    /// The message authorized by the sender's signature on the ledger `parameters.ledger_id`.
+    pub fn message<H: AccountTreeConfig>(&self, parameters: &ledger::Parameters<H>) -> Vec<u8> {
+        transfer_message(
+            &parameters.ledger_id,
+            self.sender,
+            self.recipient,
+            self.asset_id,
+            self.amount,
+        )
+    }
+
+    /// The hash of the transaction on the ledger `parameters.ledger_id`.
+    pub fn hash<H: AccountTreeConfig>(&self, parameters: &ledger::Parameters<H>) -> TxHash {
+        let mut hash_input = self.message(parameters);
+        self.signature
+            .prover_response
+            .serialize_compressed(&mut hash_input)
+            .unwrap();
+        self.signature
+            .verifier_challenge
+            .serialize_compressed(&mut hash_input)
+            .unwrap();
+        TxHash(Blake2s::digest(hash_input).into())
+    }
+
     /// Verify just the signature in the transaction.
-    fn verify_signature(&self, pp: &SignatureParameters, pub_key: &AccountPublicKey) -> bool {
-        // The authorized message consists of
-        // (SenderAccId || SenderPubKey || RecipientAccId || RecipientPubKey || Amount)
-        let mut message = self.sender.to_bytes_le();
-        message.extend(self.recipient.to_bytes_le());
-        message.extend(self.amount.to_bytes_le());
+    fn verify_signature<H: AccountTreeConfig>(
+        &self,
+        parameters: &ledger::Parameters<H>,
+        pub_key: &AccountPublicKey,
+    ) -> bool {
         <Schnorr<JubJub, Blake2s> as SignatureScheme>::verify(
-            &pp,
-            &pub_key,
-            &message,
+            &parameters.sig_params,
+            pub_key,
+            &self.message(parameters),
             &self.signature,
         )
         .unwrap()
@@ -45,60 +99,159 @@ impl Transaction {
     /// Check that the transaction is valid for the given ledger state. This checks
     /// the following conditions:
     /// 1. Verify that the signature is valid with respect to the public key
-    /// corresponding to `self.sender`.
-    /// 2. Verify that the sender's account has sufficient balance to finance
-    /// the transaction.
-    /// 3. Verify that the recipient's account exists.
-    pub fn validate(&self, parameters: &ledger::Parameters, state: &ledger::State) -> bool {
-        // Lookup public key corresponding to sender ID
-        if let Some(sender_acc_info) = state.id_to_account_info.get(&self.sender) {
-            let mut result = true;
-            // Check that the account_info exists in the Merkle tree.
-            result &= {
-                let path = state
-                    .account_merkle_tree
-                    .generate_proof(self.sender.0 as usize)
-                    .expect("path should exist");
-                let mut uncompressed_bytes = Vec::new();
-                sender_acc_info
-                    .serialize_uncompressed(&mut uncompressed_bytes)
-                    .unwrap();
-                path.verify(
-                    &parameters.leaf_crh_params,
-                    &parameters.two_to_one_crh_params,
-                    &state.account_merkle_tree.root(),
-                    uncompressed_bytes.as_slice(),
-                )
-                .unwrap()
-            };
-            // Verify the signature against the sender pubkey.
-            result &= self.verify_signature(&parameters.sig_params, &sender_acc_info.public_key);
-            // assert!(result, "signature verification failed");
-            // Verify the amount is available in the sender account.
-            result &= self.amount <= sender_acc_info.balance;
-            // Verify that recipient account exists.
-            result &= state.id_to_account_info.get(&self.recipient).is_some();
-            result
+    ///    corresponding to `self.sender`.
+    /// 2. Verify that the sender's account has sufficient balance in `self.asset_id`
+    ///    to finance the transaction.
+    /// 3. Verify that the recipient's account exists, and can receive the amount.
+    pub fn validate<H: AccountTreeConfig, S: NodeStore<H::Config>>(
+        &self,
+        parameters: &ledger::Parameters<H>,
+        state: &ledger::State<H, S>,
+    ) -> bool {
+        self.check(parameters, state).is_ok()
+    }
+
+    /// Like `validate`, but returns the reason the transaction is invalid.
+    pub fn check<H: AccountTreeConfig, S: NodeStore<H::Config>>(
+        &self,
+        parameters: &ledger::Parameters<H>,
+        state: &ledger::State<H, S>,
+    ) -> Result<(), TransactionError> {
+        self.check_without_signature(parameters, state)?;
+        // Verify the signature against the sender pubkey, once the sender is known to exist.
+        let sender_acc_info = &state.id_to_account_info[&self.sender];
+        if self.verify_signature(parameters, &sender_acc_info.public_key) {
+            Ok(())
+        } else {
+            Err(TransactionError::InvalidSignature)
+        }
+    }
+
+    /// Check the transaction against the given information of its sender and recipient,
+    /// and return their balances in `self.asset_id` after the transfer, in that order.
+    /// Unlike `check`, this does not require the sender's information to be committed
+    /// in a ledger state.
+    pub fn check_transfer<H: AccountTreeConfig>(
+        &self,
+        parameters: &ledger::Parameters<H>,
+        sender: Option<&AccountInformation>,
+        recipient: Option<&AccountInformation>,
+    ) -> Result<(Amount, Amount), TransactionError> {
+        let sender = sender.ok_or(TransactionError::UnknownSender)?;
+        let new_balances = self.new_balances(sender, recipient)?;
+        if self.verify_signature(parameters, &sender.public_key) {
+            Ok(new_balances)
         } else {
-            false
+            Err(TransactionError::InvalidSignature)
+        }
+    }
+
+    /// Compute the balances of the sender and of the recipient in `self.asset_id`
+    /// after the transfer, in that order, ignoring the signature. A transfer from an
+    /// account to itself leaves its balance unchanged.
+    pub fn new_balances(
+        &self,
+        sender: &AccountInformation,
+        recipient: Option<&AccountInformation>,
+    ) -> Result<(Amount, Amount), TransactionError> {
+        // Verify the asset exists, and the amount is available in the sender account.
+        let sender_balance = sender
+            .balance(self.asset_id)
+            .ok_or(TransactionError::InvalidAsset)?;
+        let new_sender_balance = sender_balance
+            .checked_sub(self.amount)
+            .ok_or(TransactionError::InsufficientBalance)?;
+        if self.sender == self.recipient {
+            return Ok((sender_balance, sender_balance));
+        }
+        // Verify that recipient account exists, and that its balance does not overflow.
+        let recipient_balance = recipient
+            .ok_or(TransactionError::UnknownRecipient)?
+            .balance(self.asset_id)
+            .ok_or(TransactionError::InvalidAsset)?;
+        let new_recipient_balance = recipient_balance
+            .checked_add(self.amount)
+            .ok_or(TransactionError::BalanceOverflow)?;
+        Ok((new_sender_balance, new_recipient_balance))
+    }
+
+    /// Check every condition of `check` except the signature.
+    fn check_without_signature<H: AccountTreeConfig, S: NodeStore<H::Config>>(
+        &self,
+        parameters: &ledger::Parameters<H>,
+        state: &ledger::State<H, S>,
+    ) -> Result<(), TransactionError> {
+        let sender_acc_info = state
+            .id_to_account_info
+            .get(&self.sender)
+            .ok_or(TransactionError::UnknownSender)?;
+        // Check that the account_info exists in the Merkle tree.
+        if !state
+            .prove_account(self.sender)
+            .is_some_and(|proof| proof.verify(parameters, &state.root()))
+        {
+            return Err(TransactionError::InvalidAccountProof);
+        }
+        self.new_balances(
+            sender_acc_info,
+            state.id_to_account_info.get(&self.recipient),
+        )
+        .map(|_| ())
+    }
+
+    /// Validate many transactions against the same ledger state. The `i`-th entry of
+    /// the result is `transactions[i].validate(parameters, state)`.
+    /// The signatures are checked together with `signature::verify_batch`.
+    pub fn validate_batch<H: AccountTreeConfig, S: NodeStore<H::Config>>(
+        transactions: &[Self],
+        parameters: &ledger::Parameters<H>,
+        state: &ledger::State<H, S>,
+    ) -> Vec<bool> {
+        let mut result: Vec<bool> = transactions
+            .iter()
+            .map(|tx| tx.check_without_signature(parameters, state).is_ok())
+            .collect();
+        // Only the signatures of otherwise valid transactions need to be checked.
+        let candidates: Vec<usize> = (0..transactions.len()).filter(|&i| result[i]).collect();
+        let messages: Vec<Vec<u8>> = candidates
+            .iter()
+            .map(|&i| transactions[i].message(parameters))
+            .collect();
+        let batch: Vec<signature::SignedMessage> = candidates
+            .iter()
+            .zip(&messages)
+            .map(|(&i, message)| {
+                let tx = &transactions[i];
+                let sender_acc_info = &state.id_to_account_info[&tx.sender];
+                (
+                    message.as_slice(),
+                    &sender_acc_info.public_key,
+                    &tx.signature,
+                )
+            })
+            .collect();
+        if let Err(failed) = signature::verify_batch(&parameters.sig_params, &batch) {
+            for j in failed {
+                result[candidates[j]] = false;
+            }
         }
+        result
     }
 
     // Create a (possibly invalid) transaction.
-    pub fn create<R: Rng>(
-        parameters: &ledger::Parameters,
+    pub fn create<R: Rng, H: AccountTreeConfig>(
+        parameters: &ledger::Parameters<H>,
         sender: AccountId,
         recipient: AccountId,
+        asset_id: AssetId,
         amount: Amount,
         sender_sk: &AccountSecretKey,
         rng: &mut R,
     ) -> Self {
-        let mut message = sender.to_bytes_le();
-        message.extend(recipient.to_bytes_le());
-        message.extend(amount.to_bytes_le());
+        let message = transfer_message(&parameters.ledger_id, sender, recipient, asset_id, amount);
         let signature = <Schnorr<JubJub, Blake2s> as SignatureScheme>::sign(
             &parameters.sig_params,
-            &sender_sk,
+            sender_sk,
             &message,
             rng,
         )
@@ -106,6 +259,7 @@ impl Transaction {
         Self {
             sender,
             recipient,
+            asset_id,
             amount,
             signature,
         }
@@ -0,0 +1,78 @@
+use super::account::AccountInformation;
+use crate::basic_merkle_tree::{
+    common::{CompressH, JubJubMerkleTreeParams, LeafH},
+    poseidon::{poseidon_parameters, PoseidonMerkleTreeParams},
+    storage::SyncConfig,
+};
+use ark_crypto_primitives::{
+    crh::{CRHScheme, TwoToOneCRHScheme},
+    merkle_tree::Config,
+};
+use ark_ed_on_bls12_381::Fq;
+use ark_std::rand::Rng;
+use std::fmt::Debug;
+
+/// The hash functions of the account Merkle tree, and the encoding of account
+/// information into its leaves.
+pub trait AccountTreeConfig: Copy + Clone + Debug {
+    /// The elements of an encoded leaf.
+    type LeafElement: Sync;
+    /// The configuration of the account Merkle tree.
+    type Config: Config<Leaf = [Self::LeafElement]> + SyncConfig;
+
+    /// Sample the parameters of the leaf and two-to-one hashes, in that order.
+    fn setup<R: Rng>(rng: &mut R) -> (LeafHashParams<Self>, TwoToOneHashParams<Self>);
+
+    /// Encode `info` into the leaf of its account.
+    fn leaf(info: &AccountInformation) -> Vec<Self::LeafElement>;
+}
+
+/// The parameters of the leaf hash of the account Merkle tree.
+pub type LeafHashParams<H> =
+    <<<H as AccountTreeConfig>::Config as Config>::LeafHash as CRHScheme>::Parameters;
+
+/// The parameters of the two-to-one hash of the account Merkle tree.
+pub type TwoToOneHashParams<H> =
+    <<<H as AccountTreeConfig>::Config as Config>::TwoToOneHash as TwoToOneCRHScheme>::Parameters;
+
+/// An account tree hashed with Pedersen over JubJub, whose leaves are the bytes of the
+/// account information. Its digests are curve points.
+#[derive(Hash, Eq, PartialEq, Copy, Clone, Debug, Default)]
+pub struct Pedersen;
+
+impl AccountTreeConfig for Pedersen {
+    type LeafElement = u8;
+    type Config = JubJubMerkleTreeParams;
+
+    fn setup<R: Rng>(rng: &mut R) -> (LeafHashParams<Self>, TwoToOneHashParams<Self>) {
+        let leaf_crh_params = <LeafH as CRHScheme>::setup(rng).unwrap();
+        let two_to_one_crh_params = <CompressH as TwoToOneCRHScheme>::setup(rng).unwrap();
+        (leaf_crh_params, two_to_one_crh_params)
+    }
+
+    fn leaf(info: &AccountInformation) -> Vec<u8> {
+        info.to_bytes_le()
+    }
+}
+
+/// An account tree hashed with Poseidon over the base field of JubJub, whose leaves
+/// are the field elements of the account information. Its digests are field
+/// elements, and verifying a path in a circuit costs far fewer constraints than with
+/// `Pedersen`.
+#[derive(Hash, Eq, PartialEq, Copy, Clone, Debug, Default)]
+pub struct Poseidon;
+
+impl AccountTreeConfig for Poseidon {
+    type LeafElement = Fq;
+    type Config = PoseidonMerkleTreeParams;
+
+    /// The Poseidon parameters are fixed, so `rng` is not used.
+    fn setup<R: Rng>(_rng: &mut R) -> (LeafHashParams<Self>, TwoToOneHashParams<Self>) {
+        let params = poseidon_parameters();
+        (params.clone(), params)
+    }
+
+    fn leaf(info: &AccountInformation) -> Vec<Fq> {
+        info.to_field_elements()
+    }
+}
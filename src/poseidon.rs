@@ -0,0 +1,89 @@
+//! Derives Poseidon configurations for any prime field with
+//! `find_poseidon_ark_and_mds`, which samples the round constants and the MDS matrix
+//! from the Grain LFSR of the reference implementation of Poseidon
+//! (`generate_parameters_grain.sage`).
+use ark_crypto_primitives::sponge::poseidon::{find_poseidon_ark_and_mds, PoseidonConfig};
+use ark_ff::PrimeField;
+
+/// The capacity of the generated configurations, which is that of every Poseidon hash
+/// of the crate.
+const CAPACITY: usize = 1;
+
+/// Generate the Poseidon configuration over `F` with rate `rate`, capacity 1, the S-box
+/// `x^alpha`, `full_rounds` full rounds and `partial_rounds` partial rounds.
+///
+/// The numbers of rounds are not checked against the security bounds of the Poseidon
+/// paper, and must be picked from its recommendations for the field size and `alpha`.
+/// Panics if `x^alpha` is not a permutation of `F`, or if `full_rounds` is odd.
+pub fn poseidon_config<F: PrimeField>(
+    rate: usize,
+    alpha: u64,
+    full_rounds: usize,
+    partial_rounds: usize,
+) -> PoseidonConfig<F> {
+    assert!(
+        alpha > 1 && gcd(alpha, modulus_minus_one_mod::<F>(alpha)) == 1,
+        "x^alpha should be a permutation of the field"
+    );
+    assert!(
+        full_rounds.is_multiple_of(2),
+        "the full rounds should be split evenly"
+    );
+    let (ark, mds) = find_poseidon_ark_and_mds::<F>(
+        F::MODULUS_BIT_SIZE as u64,
+        rate,
+        full_rounds as u64,
+        partial_rounds as u64,
+        0,
+    );
+    PoseidonConfig::new(full_rounds, partial_rounds, alpha, mds, ark, rate, CAPACITY)
+}
+
+/// Compute `(p - 1) mod m`, where `p` is the modulus of `F`.
+fn modulus_minus_one_mod<F: PrimeField>(m: u64) -> u64 {
+    let p_mod_m = F::MODULUS.as_ref().iter().rev().fold(0u64, |acc, limb| {
+        ((((acc as u128) << 64) | *limb as u128) % m as u128) as u64
+    });
+    (p_mod_m + m - 1) % m
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::poseidon_config;
+    use ark_ff::MontFp;
+
+    #[test]
+    fn bls12_381_known_answers() {
+        // The first round constants of the reference implementation over BLS12-381
+        // `Fr`, for a state of 3 elements, 8 full rounds and 31 partial rounds.
+        let config = poseidon_config::<ark_bls12_381::Fr>(2, 17, 8, 31);
+        assert_eq!(
+            config.ark[0][..2],
+            [
+                MontFp!(
+                    "27117311055620256798560880810000042840428971800021819916023577129547249660720"
+                ),
+                MontFp!(
+                    "51641662388546346858987925410984003801092143452466182801674685248597955169158"
+                ),
+            ]
+        );
+        assert_eq!((config.rate, config.capacity, config.alpha), (2, 1, 17));
+        assert_eq!(config.ark.len(), 8 + 31);
+        assert_eq!(config.mds.len(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "permutation")]
+    fn alpha_must_be_a_permutation() {
+        // 3 divides the order of the multiplicative group of BLS12-381 `Fr`.
+        poseidon_config::<ark_bls12_381::Fr>(2, 3, 8, 31);
+    }
+}
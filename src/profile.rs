@@ -0,0 +1,343 @@
+//! Reports the size of a constraint system per namespace.
+//!
+//! Namespaces are the `r1cs` tracing spans opened by `ns!` and by functions
+//! instrumented with `#[tracing::instrument(target = "r1cs")]`. Each namespace is
+//! charged with the constraints, witnesses and public inputs allocated while it is
+//! entered, including those of the namespaces nested in it.
+use ark_ff::PrimeField;
+use ark_relations::r1cs::{
+    ConstraintSynthesizer, ConstraintSystem, ConstraintSystemRef, SynthesisError,
+};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    fmt,
+    ops::Sub,
+    sync::{Arc, Mutex},
+};
+use tracing::{span, Subscriber};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer, Registry};
+
+/// The size of (a part of) a constraint system.
+#[derive(Hash, Eq, PartialEq, Copy, Clone, Debug, Default)]
+pub struct ConstraintCounts {
+    pub constraints: usize,
+    pub witnesses: usize,
+    /// The number of public inputs, excluding the constant `1`.
+    pub public_inputs: usize,
+}
+
+impl ConstraintCounts {
+    fn of<F: PrimeField>(cs: &ConstraintSystemRef<F>) -> Self {
+        Self {
+            constraints: cs.num_constraints(),
+            witnesses: cs.num_witness_variables(),
+            public_inputs: cs.num_instance_variables().saturating_sub(1),
+        }
+    }
+
+    fn add_assign(&mut self, other: Self) {
+        self.constraints += other.constraints;
+        self.witnesses += other.witnesses;
+        self.public_inputs += other.public_inputs;
+    }
+}
+
+impl Sub for ConstraintCounts {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self {
+            constraints: self.constraints - other.constraints,
+            witnesses: self.witnesses - other.witnesses,
+            public_inputs: self.public_inputs - other.public_inputs,
+        }
+    }
+}
+
+/// The size of a constraint system, in total and per namespace.
+#[derive(Clone, Debug, Default)]
+pub struct ConstraintReport {
+    pub total: ConstraintCounts,
+    /// The counts of every namespace, keyed by the `/`-separated names of the
+    /// namespaces enclosing it, outermost first.
+    pub namespaces: BTreeMap<String, ConstraintCounts>,
+}
+
+impl ConstraintReport {
+    /// Return the counts of the namespace at `path`, if it allocated anything.
+    pub fn namespace(&self, path: &str) -> Option<ConstraintCounts> {
+        self.namespaces.get(path).copied()
+    }
+}
+
+impl fmt::Display for ConstraintReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .namespaces
+            .keys()
+            .map(String::len)
+            .chain(Some("total".len()))
+            .max()
+            .unwrap_or_default();
+        writeln!(
+            f,
+            "{:width$} {:>12} {:>12} {:>12}",
+            "namespace", "constraints", "witnesses", "inputs"
+        )?;
+        let rows = self
+            .namespaces
+            .iter()
+            .map(|(path, counts)| (path.as_str(), counts))
+            .chain(Some(("total", &self.total)));
+        for (path, counts) in rows {
+            writeln!(
+                f,
+                "{:width$} {:>12} {:>12} {:>12}",
+                path, counts.constraints, counts.witnesses, counts.public_inputs
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Synthesize `circuit` in a fresh constraint system, and report its size.
+pub fn profile<F: PrimeField, C: ConstraintSynthesizer<F>>(
+    circuit: C,
+) -> Result<ConstraintReport, SynthesisError> {
+    profile_with(|cs| circuit.generate_constraints(cs)).map(|((), report)| report)
+}
+
+/// Run `synthesize` on a fresh constraint system, and report the size of what it
+/// allocates. This profiles gadgets that are not a whole circuit.
+pub fn profile_with<F: PrimeField, T>(
+    synthesize: impl FnOnce(ConstraintSystemRef<F>) -> Result<T, SynthesisError>,
+) -> Result<(T, ConstraintReport), SynthesisError> {
+    let cs = ConstraintSystem::<F>::new_ref();
+    let namespaces = Arc::new(Mutex::new(BTreeMap::new()));
+    let layer = ProfileLayer {
+        namespaces: namespaces.clone(),
+        marks: Mutex::new(HashMap::new()),
+    };
+    let counter_cs = cs.clone();
+    COUNTER.with(|counter| {
+        *counter.borrow_mut() = Some(Box::new(move || ConstraintCounts::of(&counter_cs)))
+    });
+    let subscriber = tracing_subscriber::layer::SubscriberExt::with(Registry::default(), layer);
+    let result = tracing::subscriber::with_default(subscriber, || synthesize(cs.clone()));
+    COUNTER.with(|counter| *counter.borrow_mut() = None);
+
+    let namespaces = std::mem::take(&mut *namespaces.lock().unwrap());
+    let report = ConstraintReport {
+        total: ConstraintCounts::of(&cs),
+        namespaces,
+    };
+    result.map(|value| (value, report))
+}
+
+thread_local! {
+    /// Reads the counts of the constraint system being profiled on this thread.
+    /// Constraint systems are not `Send`, so the tracing layer cannot own one.
+    static COUNTER: RefCell<Option<Box<dyn Fn() -> ConstraintCounts>>> = RefCell::new(None);
+}
+
+fn current_counts() -> Option<ConstraintCounts> {
+    COUNTER.with(|counter| counter.borrow().as_ref().map(|count| count()))
+}
+
+/// Charges every `r1cs` span with the growth of the constraint system between
+/// entering and exiting it.
+struct ProfileLayer {
+    namespaces: Arc<Mutex<BTreeMap<String, ConstraintCounts>>>,
+    /// The counts when each open span was last entered or exited. The span of an
+    /// `ns!` namespace is exited whenever a clone of its `Namespace` is dropped, which
+    /// may happen before the namespace is done allocating. A span is therefore
+    /// charged at every exit, until it closes.
+    marks: Mutex<HashMap<span::Id, ConstraintCounts>>,
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for ProfileLayer {
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        let is_r1cs = ctx
+            .span(id)
+            .is_some_and(|span| span.metadata().target() == "r1cs");
+        if let (true, Some(counts)) = (is_r1cs, current_counts()) {
+            self.marks.lock().unwrap().insert(id.clone(), counts);
+        }
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        let (Some(counts), Some(span)) = (current_counts(), ctx.span(id)) else {
+            return;
+        };
+        let Some(mark) = self
+            .marks
+            .lock()
+            .unwrap()
+            .get_mut(id)
+            .map(|mark| std::mem::replace(mark, counts))
+        else {
+            return;
+        };
+        let mut names: Vec<&str> = span
+            .scope()
+            .filter(|span| span.metadata().target() == "r1cs")
+            .map(|span| span.name())
+            .collect();
+        names.reverse();
+        let mut namespaces = self.namespaces.lock().unwrap();
+        namespaces
+            .entry(names.join("/"))
+            .or_default()
+            .add_assign(counts - mark);
+    }
+
+    fn on_close(&self, id: span::Id, _ctx: Context<'_, S>) {
+        self.marks.lock().unwrap().remove(&id);
+    }
+}
+
+/// Check that the size `actual` of a circuit is within `budget`, so that a test fails
+/// when the circuit grows past the recorded numbers.
+#[cfg(test)]
+pub(crate) fn assert_budget(actual: ConstraintCounts, budget: ConstraintCounts) {
+    assert!(
+        actual.constraints <= budget.constraints
+            && actual.witnesses <= budget.witnesses
+            && actual.public_inputs <= budget.public_inputs,
+        "{actual:?} exceeds the budget {budget:?}"
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::{assert_budget, profile, profile_with, ConstraintCounts, ConstraintReport};
+    use crate::{
+        basic_merkle_tree::{common::*, constraints::MerkleTreeVerification},
+        payment::{
+            ledger::{Amount, AssetId, Parameters, State},
+            transaction::Transaction,
+            tree::{Pedersen, Poseidon},
+        },
+        rollup::{ledger::AmountVar, rollup::UnaryRollup, tree::AccountTreeConfigVar, ConstraintF},
+    };
+    use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+    use ark_r1cs_std::prelude::AllocVar;
+    use ark_relations::ns;
+
+    // Budgets: each test fails when its circuit grows past the recorded numbers. Lower
+    // the numbers when a change makes a circuit smaller.
+
+    #[test]
+    fn merkle_tree_verification_budget() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafH as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <CompressH as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+        let leaves: Vec<Vec<u8>> = (0..4u8).map(|i| vec![i; 30]).collect();
+        let tree = JubJubMerkleTree::new(
+            &leaf_crh_params,
+            &two_to_one_crh_params,
+            leaves.iter().map(|v| v.as_slice()),
+        )
+        .unwrap();
+        let circuit = MerkleTreeVerification {
+            leaf_crh_params,
+            two_to_one_crh_params,
+            root: tree.root(),
+            leaf: leaves[1].clone(),
+            authentication_path: Some(tree.generate_proof(1).unwrap()),
+        };
+        let report = profile(circuit).unwrap();
+        println!("{report}");
+        assert_budget(
+            report.total,
+            ConstraintCounts {
+                constraints: 14566,
+                witnesses: 13945,
+                public_inputs: 3,
+            },
+        );
+    }
+
+    #[test]
+    fn unary_rollup_budget() {
+        let report = unary_rollup_report::<Pedersen>();
+        println!("{report}");
+        // A namespace is charged with everything allocated in it, even after a clone of
+        // its `Namespace` is dropped.
+        assert_eq!(
+            report.namespace("generate_constraints/sender_acc_info"),
+            report.namespace("generate_constraints/sender_acc_info/new_witness"),
+        );
+        assert_budget(
+            report.total,
+            ConstraintCounts {
+                constraints: 97384,
+                witnesses: 92426,
+                public_inputs: 4,
+            },
+        );
+    }
+
+    #[test]
+    fn poseidon_unary_rollup_budget() {
+        let report = unary_rollup_report::<Poseidon>();
+        println!("{report}");
+        assert_budget(
+            report.total,
+            ConstraintCounts {
+                constraints: 11075,
+                witnesses: 11058,
+                public_inputs: 2,
+            },
+        );
+    }
+
+    /// Profile the rollup of a transfer between two accounts of a ledger whose account
+    /// tree is hashed as configured by `H`.
+    fn unary_rollup_report<H: AccountTreeConfigVar>() -> ConstraintReport {
+        let mut rng = ark_std::test_rng();
+        let pp = Parameters::<H>::sample_with_tree(&mut rng);
+        let mut state = State::new(16, &pp);
+        let (alice_id, _alice_pk, alice_sk) =
+            state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        let (bob_id, _bob_pk, _bob_sk) = state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        state
+            .update_balance(alice_id, AssetId(0), Amount(10))
+            .unwrap();
+        let tx = Transaction::create(
+            &pp,
+            alice_id,
+            bob_id,
+            AssetId(0),
+            Amount(4),
+            &alice_sk,
+            &mut rng,
+        );
+        let circuit = UnaryRollup::new(&pp, &state, &tx, state.root()).unwrap();
+        profile(circuit).unwrap()
+    }
+
+    #[test]
+    fn amount_var_budget() {
+        let ((), report) = profile_with::<ConstraintF, _>(|cs| {
+            let a = AmountVar::new_witness(ns!(cs, "a"), || Ok(Amount(5)))?;
+            let b = AmountVar::new_witness(ns!(cs, "b"), || Ok(Amount(3)))?;
+            a.checked_add(&b)?;
+            a.checked_sub(&b)?;
+            Ok(())
+        })
+        .unwrap();
+        println!("{report}");
+        for op in ["checked_add", "checked_sub"] {
+            assert_budget(
+                report.namespace(op).unwrap(),
+                ConstraintCounts {
+                    constraints: 65,
+                    witnesses: 64,
+                    public_inputs: 0,
+                },
+            );
+        }
+    }
+}
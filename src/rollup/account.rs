@@ -1,19 +1,23 @@
 use std::borrow::Borrow;
 
 use crate::payment::account::{AccountId, AccountInformation};
-use ark_crypto_primitives::signature::schnorr::{constraints::PublicKeyVar, PublicKey};
-use ark_ed_on_bls12_381::{constraints::EdwardsVar, EdwardsProjective as JubJub};
+use ark_ed_on_bls12_381::constraints::EdwardsVar;
 use ark_r1cs_std::{
-    prelude::{AllocVar, AllocationMode},
+    fields::fp::FpVar,
+    prelude::{AllocVar, AllocationMode, Boolean, EqGadget},
     uint8::UInt8,
     ToBytesGadget,
 };
 use ark_relations::r1cs::{Namespace, SynthesisError};
 
-use super::{ledger::AmountVar, ConstraintF};
+use super::{
+    ledger::{AmountVar, AssetIdVar},
+    ConstraintF,
+};
 
-/// Account public key used to verify transaction signatures.
-pub type AccountPublicKeyVar = PublicKeyVar<JubJub, EdwardsVar>;
+/// Account public key used to verify transaction signatures. It is the curve point
+/// of the key, rather than a Schnorr `PublicKeyVar`, which hides its coordinates.
+pub type AccountPublicKeyVar = EdwardsVar;
 
 /// Account identifier. This prototype supports only 256 accounts at a time.
 #[derive(Clone, Debug)]
@@ -38,13 +42,15 @@ impl AllocVar<AccountId, ConstraintF> for AccountIdVar {
     }
 }
 
-/// Information about the account, such as the balance and the associated public key.
+/// Information about the account, such as the balances and the associated public key.
 #[derive(Clone)]
 pub struct AccountInformationVar {
     /// The account public key.
     pub public_key: AccountPublicKeyVar,
-    /// The balance associated with this this account.
-    pub balance: AmountVar,
+    /// The balances associated with this this account, indexed by asset identifier.
+    pub balances: Vec<AmountVar>,
+    /// The amounts locked in pending escrows, indexed by asset identifier.
+    pub locked: Vec<AmountVar>,
 }
 
 impl AccountInformationVar {
@@ -55,9 +61,55 @@ impl AccountInformationVar {
             .to_bytes()
             .unwrap()
             .into_iter()
-            .chain(self.balance.to_bytes_le())
+            .chain(self.balances.iter().flat_map(AmountVar::to_bytes_le))
+            .chain(self.locked.iter().flat_map(AmountVar::to_bytes_le))
+            .collect()
+    }
+
+    /// Convert the account information to field elements, as
+    /// `AccountInformation::to_field_elements` does. This adds no constraints.
+    #[tracing::instrument(target = "r1cs", skip(self))]
+    pub fn to_field_elements(&self) -> Result<Vec<FpVar<ConstraintF>>, SynthesisError> {
+        let amounts = self.balances.iter().chain(&self.locked);
+        [Ok(self.public_key.x.clone()), Ok(self.public_key.y.clone())]
+            .into_iter()
+            .chain(amounts.map(AmountVar::to_fp_var))
             .collect()
     }
+
+    /// Return the balance of the account in asset `asset_id`. This enforces that
+    /// `asset_id` is a valid asset identifier.
+    #[tracing::instrument(target = "r1cs", skip(self, asset_id))]
+    pub fn balance(&self, asset_id: &AssetIdVar) -> Result<AmountVar, SynthesisError> {
+        let one_hot = asset_id.one_hot()?;
+        Boolean::kary_or(&one_hot)?.enforce_equal(&Boolean::TRUE)?;
+        let mut balance = self.balances[0].0.clone();
+        for (is_asset, asset_balance) in one_hot.iter().zip(&self.balances).skip(1) {
+            balance = is_asset.select(&asset_balance.0, &balance)?;
+        }
+        Ok(AmountVar(balance))
+    }
+
+    /// Return the account information with the balance in asset `asset_id` replaced
+    /// by `new_balance`. Other balances are unchanged.
+    #[tracing::instrument(target = "r1cs", skip(self, asset_id, new_balance))]
+    pub fn with_balance(
+        &self,
+        asset_id: &AssetIdVar,
+        new_balance: &AmountVar,
+    ) -> Result<Self, SynthesisError> {
+        let balances = asset_id
+            .one_hot()?
+            .iter()
+            .zip(&self.balances)
+            .map(|(is_asset, balance)| is_asset.select(&new_balance.0, &balance.0).map(AmountVar))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            public_key: self.public_key.clone(),
+            balances,
+            locked: self.locked.clone(),
+        })
+    }
 }
 
 impl AllocVar<AccountInformation, ConstraintF> for AccountInformationVar {
@@ -71,16 +123,93 @@ impl AllocVar<AccountInformation, ConstraintF> for AccountInformationVar {
             let info: &AccountInformation = info.borrow();
             let cs = cs.into();
             let public_key =
-                <AccountPublicKeyVar as AllocVar<PublicKey<JubJub>, ConstraintF>>::new_variable(
-                    cs.clone(),
-                    || Ok(&info.public_key),
-                    mode,
-                )?;
-            let balance = AmountVar::new_variable(cs.clone(), || Ok(&info.balance), mode)?;
+                AccountPublicKeyVar::new_variable(cs.clone(), || Ok(info.public_key), mode)?;
+            let balances = info
+                .balances
+                .iter()
+                .map(|balance| AmountVar::new_variable(cs.clone(), || Ok(balance), mode))
+                .collect::<Result<_, _>>()?;
+            let locked = info
+                .locked
+                .iter()
+                .map(|locked| AmountVar::new_variable(cs.clone(), || Ok(locked), mode))
+                .collect::<Result<_, _>>()?;
             Ok(Self {
                 public_key,
-                balance,
+                balances,
+                locked,
             })
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::AccountInformationVar;
+    use crate::{
+        payment::{
+            account::AccountInformation,
+            ledger::{Amount, AssetId, Parameters, State},
+        },
+        rollup::{
+            ledger::{AmountVar, AssetIdVar},
+            ConstraintF,
+        },
+    };
+    use ark_r1cs_std::{prelude::AllocVar, R1CSVar};
+    use ark_relations::r1cs::ConstraintSystem;
+
+    fn sample_account_info() -> AccountInformation {
+        let mut rng = ark_std::test_rng();
+        let pp = Parameters::sample(&mut rng);
+        let mut state = State::new(32, &pp);
+        let (id, _pk, _sk) = state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        for (asset, amount) in [(0, 7), (1, 0), (2, u64::MAX), (3, 42)] {
+            state
+                .update_balance(id, AssetId(asset), Amount(amount))
+                .unwrap();
+        }
+        state.id_to_account_info[&id]
+    }
+
+    #[test]
+    fn account_information_gadget_matches_native() {
+        let info = sample_account_info();
+        let cs = ConstraintSystem::<ConstraintF>::new_ref();
+        let info_var = AccountInformationVar::new_witness(cs.clone(), || Ok(&info)).unwrap();
+        assert_eq!(info_var.to_bytes_le().value().unwrap(), info.to_bytes_le());
+        assert_eq!(
+            info_var.to_field_elements().unwrap().value().unwrap(),
+            info.to_field_elements()
+        );
+
+        for asset in 0..4u8 {
+            let asset_id = AssetIdVar::new_witness(cs.clone(), || Ok(AssetId(asset))).unwrap();
+            let balance = info_var.balance(&asset_id).unwrap();
+            assert_eq!(
+                Some(Amount(balance.0.value().unwrap())),
+                info.balance(AssetId(asset))
+            );
+
+            let new_balance = AmountVar::new_witness(cs.clone(), || Ok(Amount(1))).unwrap();
+            let updated = info_var.with_balance(&asset_id, &new_balance).unwrap();
+            let mut expected = info;
+            expected.balances[asset as usize] = Amount(1);
+            assert_eq!(
+                updated.to_bytes_le().value().unwrap(),
+                expected.to_bytes_le()
+            );
+        }
+        assert!(cs.is_satisfied().unwrap());
+    }
+
+    #[test]
+    fn invalid_asset_is_unsatisfiable() {
+        let info = sample_account_info();
+        let cs = ConstraintSystem::<ConstraintF>::new_ref();
+        let info_var = AccountInformationVar::new_witness(cs.clone(), || Ok(&info)).unwrap();
+        let asset_id = AssetIdVar::new_witness(cs.clone(), || Ok(AssetId(4))).unwrap();
+        info_var.balance(&asset_id).unwrap();
+        assert!(!cs.is_satisfied().unwrap());
+    }
+}
@@ -1,19 +1,20 @@
-use crate::{
-    basic_merkle_tree::constraints::{
-        AllocVarParameters, LeafHashParamsVar, RootVar, SimplePathVar, TwoToOneHashParamsVar,
-    },
-    payment::ledger::{Amount, Parameters as PaymentParameters},
+use crate::payment::{
+    ledger::{Amount, AssetId, Parameters as PaymentParameters, NUM_ASSETS},
+    tree::{AccountTreeConfig, Pedersen},
+};
+use ark_crypto_primitives::{
+    merkle_tree::constraints::{ConfigGadget, PathVar},
+    signature::schnorr::constraints::ParametersVar as SchnorrParamsVar,
 };
-use ark_crypto_primitives::signature::schnorr::constraints::ParametersVar as SchnorrParamsVar;
 use ark_ed_on_bls12_381::{constraints::EdwardsVar, EdwardsProjective as JubJub};
 use ark_r1cs_std::{bits::uint64::UInt64, fields::fp::FpVar, prelude::*};
 use ark_relations::r1cs::{Namespace, SynthesisError};
-use std::{
-    borrow::Borrow,
-    ops::{Add, Sub},
-};
+use std::borrow::Borrow;
 
-use super::ConstraintF;
+use super::{
+    tree::{AccountTreeConfigVar, LeafHashParamsVar, TwoToOneHashParamsVar},
+    ConstraintF,
+};
 
 /// Represents transaction amounts and account balances.
 #[derive(Clone, Debug)]
@@ -25,46 +26,63 @@ impl AmountVar {
         self.0.to_bytes().unwrap()
     }
 
+    /// Add two amounts, enforcing that the sum does not overflow.
+    ///
+    /// The sum is witnessed as a `UInt64`, whose bit decomposition range-checks it,
+    /// and a single constraint enforces that it equals the sum of the operands as
+    /// field elements. The sum of two 64-bit values is below 2^65, far below the field
+    /// modulus, so the equality holds exactly when the sum fits in 64 bits: a 65th bit
+    /// of the decomposition would be forced to zero, and is therefore omitted.
+    ///
+    /// This costs 65 constraints, down from 706 for the previous implementation, which
+    /// decomposed the field sum into 32 bytes and also added the operands with
+    /// `UInt64::addmany`.
     #[tracing::instrument(target = "r1cs", skip(self, other))]
     pub fn checked_add(&self, other: &Self) -> Result<Self, SynthesisError> {
-        // To do a checked add, we add two uint64's directly.
-        // We also check for overflow, by casting them to field elements,
-        // adding the field element representation
-        // converting the field elements to bits
-        // and then checking if the 65th bit is 0.
-        // TODO: Demonstrate via circuit profiling if this needs optimization.
-        let self_bits: Vec<Boolean<ConstraintF>> = self.0.to_bits_le();
-        let self_fe: FpVar<ConstraintF> = Boolean::le_bits_to_fp_var(&self_bits)?;
-        let other_bits: Vec<Boolean<ConstraintF>> = other.0.to_bits_le();
-        let other_fe: FpVar<ConstraintF> = Boolean::le_bits_to_fp_var(&other_bits)?;
-        let res_fe: <FpVar<ConstraintF> as Add<FpVar<ConstraintF>>>::Output = self_fe + other_fe;
-        let res_bz: Vec<UInt8<ConstraintF>> = res_fe.to_bytes()?;
-        // Ensure 65th bit is 0
-        // implies 8th word (0-indexed) is 0
-        res_bz[8].enforce_equal(&UInt8::<ConstraintF>::constant(0))?;
-        // Add sum
-        let result: UInt64<ConstraintF> = UInt64::addmany(&[self.0.clone(), other.0.clone()])?;
-        Ok(AmountVar(result))
+        let result = self.witness_result(other, u64::checked_add)?;
+        (result.to_fp_var()?).enforce_equal(&(self.to_fp_var()? + other.to_fp_var()?))?;
+        Ok(result)
     }
 
+    /// Subtract `other` from `self`, enforcing that the difference does not underflow.
+    ///
+    /// The difference is witnessed as a `UInt64`, whose bit decomposition
+    /// range-checks it, and a single constraint enforces `other + difference == self`
+    /// over field elements. The left-hand side is below 2^65, far below the field
+    /// modulus, so the equality holds exactly when `other <= self`.
+    ///
+    /// This costs 65 constraints, down from 1271 for the previous implementation,
+    /// which decomposed the field difference into 32 bytes, and then again into bits.
     #[tracing::instrument(target = "r1cs", skip(self, other))]
     pub fn checked_sub(&self, other: &Self) -> Result<Self, SynthesisError> {
-        // To do a checked sub, we convert the uints to a field element.
-        // We do the sub on the field element.
-        // We then cast the field element to bits, and ensure the top bits are 0.
-        // We then convert these bits to a field element
-        // TODO: Demonstrate via circuit profiling if this needs optimization.
-        let self_bits: Vec<Boolean<ConstraintF>> = self.0.to_bits_le();
-        let self_fe: FpVar<ConstraintF> = Boolean::le_bits_to_fp_var(&self_bits)?;
-        let other_bits: Vec<Boolean<ConstraintF>> = other.0.to_bits_le();
-        let other_fe: FpVar<ConstraintF> = Boolean::le_bits_to_fp_var(&other_bits)?;
-        let res_fe: <FpVar<ConstraintF> as Sub<FpVar<ConstraintF>>>::Output = self_fe - other_fe;
-        let res_bz: Vec<UInt8<ConstraintF>> = res_fe.to_bytes()?;
-        // Ensure top bit is 0
-        res_bz[res_bz.len() - 1].enforce_equal(&UInt8::<ConstraintF>::constant(0))?;
-        // Convert to UInt64
-        let res: UInt64<ConstraintF> = UInt64::from_bits_le(&res_fe.to_bits_le()?[..64]);
-        Ok(AmountVar(res))
+        let result = self.witness_result(other, u64::checked_sub)?;
+        (other.to_fp_var()? + result.to_fp_var()?).enforce_equal(&self.to_fp_var()?)?;
+        Ok(result)
+    }
+
+    /// The amount as a field element. This adds no constraints.
+    pub fn to_fp_var(&self) -> Result<FpVar<ConstraintF>, SynthesisError> {
+        Boolean::le_bits_to_fp_var(&self.0.to_bits_le())
+    }
+
+    /// Allocate `op(self, other)` as a witness, or as a constant if both operands are
+    /// constants. When `op` fails, an arbitrary witness is allocated, which cannot
+    /// satisfy the constraints of the caller; constant operands yield an error.
+    fn witness_result(
+        &self,
+        other: &Self,
+        op: fn(u64, u64) -> Option<u64>,
+    ) -> Result<Self, SynthesisError> {
+        let cs = self.0.cs().or(other.0.cs());
+        if cs.is_none() {
+            let result =
+                op(self.0.value()?, other.0.value()?).ok_or(SynthesisError::Unsatisfiable)?;
+            return Ok(AmountVar(UInt64::constant(result)));
+        }
+        let result = UInt64::new_witness(cs, || {
+            Ok(op(self.0.value()?, other.0.value()?).unwrap_or_default())
+        })?;
+        Ok(AmountVar(result))
     }
 }
 
@@ -79,40 +97,157 @@ impl AllocVar<Amount, ConstraintF> for AmountVar {
     }
 }
 
-pub type AccRootVar = RootVar;
-pub type AccPathVar = SimplePathVar;
+/// Identifies an asset settled on the ledger.
+#[derive(Clone, Debug)]
+pub struct AssetIdVar(pub UInt8<ConstraintF>);
+
+impl AssetIdVar {
+    /// Convert the asset identifier to bytes.
+    #[tracing::instrument(target = "r1cs", skip(self))]
+    pub fn to_bytes_le(&self) -> Vec<UInt8<ConstraintF>> {
+        vec![self.0.clone()]
+    }
 
-pub struct ParametersVar {
+    /// Return, for each asset `i` in `0..NUM_ASSETS`, whether `self` equals `i`.
+    /// At most one of the returned booleans is true, and none of them is true if the
+    /// asset identifier is invalid.
+    #[tracing::instrument(target = "r1cs", skip(self))]
+    pub fn one_hot(&self) -> Result<Vec<Boolean<ConstraintF>>, SynthesisError> {
+        (0..NUM_ASSETS)
+            .map(|i| self.0.is_eq(&UInt8::constant(i as u8)))
+            .collect()
+    }
+}
+
+impl AllocVar<AssetId, ConstraintF> for AssetIdVar {
+    #[tracing::instrument(target = "r1cs", skip(cs, f, mode))]
+    fn new_variable<T: Borrow<AssetId>>(
+        cs: impl Into<Namespace<ConstraintF>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        UInt8::new_variable(cs, || f().map(|u| u.borrow().0), mode).map(Self)
+    }
+}
+
+pub type AccRootVar<H = Pedersen> = <<H as AccountTreeConfigVar>::ConfigVar as ConfigGadget<
+    <H as AccountTreeConfig>::Config,
+    ConstraintF,
+>>::InnerDigest;
+pub type AccPathVar<H = Pedersen> =
+    PathVar<<H as AccountTreeConfig>::Config, ConstraintF, <H as AccountTreeConfigVar>::ConfigVar>;
+
+pub struct ParametersVar<H: AccountTreeConfigVar = Pedersen> {
     pub sig_params: SchnorrParamsVar<JubJub, EdwardsVar>,
-    pub leaf_crh_params: LeafHashParamsVar,
-    pub two_to_one_crh_params: TwoToOneHashParamsVar,
+    pub leaf_crh_params: LeafHashParamsVar<H>,
+    pub two_to_one_crh_params: TwoToOneHashParamsVar<H>,
+    /// The ledger identifier bound into signed messages.
+    pub ledger_id: Vec<UInt8<ConstraintF>>,
 }
 
-impl AllocVar<PaymentParameters, ConstraintF> for ParametersVar {
+impl<H: AccountTreeConfigVar> AllocVar<PaymentParameters<H>, ConstraintF> for ParametersVar<H> {
     #[tracing::instrument(target = "r1cs", skip(cs, f, _mode))]
-    fn new_variable<T: Borrow<PaymentParameters>>(
+    fn new_variable<T: Borrow<PaymentParameters<H>>>(
         cs: impl Into<Namespace<ConstraintF>>,
         f: impl FnOnce() -> Result<T, SynthesisError>,
         _mode: AllocationMode,
     ) -> Result<Self, SynthesisError> {
         let cs = cs.into();
         f().and_then(|params| {
-            let params: &PaymentParameters = params.borrow();
+            let params: &PaymentParameters<H> = params.borrow();
             let sig_params = SchnorrParamsVar::new_constant(cs.clone(), &params.sig_params)?;
-            let leaf_crh_params = <LeafHashParamsVar as AllocVar<
-                AllocVarParameters,
-                ConstraintF,
-            >>::new_constant(cs.clone(), &params.leaf_crh_params)?;
-            let two_to_one_crh_params =
-                <TwoToOneHashParamsVar as AllocVar<AllocVarParameters, ConstraintF>>::new_constant(
-                    cs.clone(),
-                    &params.two_to_one_crh_params,
-                )?;
+            let leaf_crh_params =
+                LeafHashParamsVar::<H>::new_constant(cs.clone(), &params.leaf_crh_params)?;
+            let two_to_one_crh_params = TwoToOneHashParamsVar::<H>::new_constant(
+                cs.clone(),
+                &params.two_to_one_crh_params,
+            )?;
+            let ledger_id = UInt8::constant_vec(&params.ledger_id.0);
             Ok(Self {
                 sig_params,
                 leaf_crh_params,
                 two_to_one_crh_params,
+                ledger_id,
             })
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::AmountVar;
+    use crate::{payment::ledger::Amount, rollup::ConstraintF};
+    use ark_r1cs_std::{prelude::AllocVar, R1CSVar};
+    use ark_relations::r1cs::{ConstraintSystem, SynthesisError};
+
+    /// Boundary values of `u64`, and their neighbours.
+    const EDGE_CASES: [u64; 11] = [
+        0,
+        1,
+        2,
+        (1 << 32) - 1,
+        1 << 32,
+        (1 << 63) - 1,
+        1 << 63,
+        (1 << 63) + 1,
+        u64::MAX - 2,
+        u64::MAX - 1,
+        u64::MAX,
+    ];
+
+    type Gadget = fn(&AmountVar, &AmountVar) -> Result<AmountVar, SynthesisError>;
+    type Native = fn(Amount, Amount) -> Option<Amount>;
+
+    fn operations() -> [(Gadget, Native); 2] {
+        [
+            (AmountVar::checked_add, Amount::checked_add),
+            (AmountVar::checked_sub, Amount::checked_sub),
+        ]
+    }
+
+    #[test]
+    fn checked_arithmetic_matches_native() {
+        for (gadget, native) in operations() {
+            for a in EDGE_CASES {
+                for b in EDGE_CASES {
+                    let expected = native(Amount(a), Amount(b));
+                    let cs = ConstraintSystem::<ConstraintF>::new_ref();
+                    let a_var = AmountVar::new_witness(cs.clone(), || Ok(Amount(a))).unwrap();
+                    let b_var = AmountVar::new_witness(cs.clone(), || Ok(Amount(b))).unwrap();
+                    let result = gadget(&a_var, &b_var).unwrap();
+                    assert_eq!(cs.is_satisfied().unwrap(), expected.is_some(), "{a}, {b}");
+                    if let Some(expected) = expected {
+                        assert_eq!(Amount(result.0.value().unwrap()), expected);
+                    }
+
+                    // Constant operands are computed natively, and mixed operands are
+                    // constrained like witnesses.
+                    let a_const = AmountVar::new_constant(cs.clone(), Amount(a)).unwrap();
+                    let b_const = AmountVar::new_constant(cs.clone(), Amount(b)).unwrap();
+                    let result = gadget(&a_const, &b_const);
+                    assert_eq!(result.is_ok(), expected.is_some());
+                    if let Some(expected) = expected {
+                        assert_eq!(Amount(result.unwrap().0.value().unwrap()), expected);
+                    }
+                    let cs = ConstraintSystem::<ConstraintF>::new_ref();
+                    let a_var = AmountVar::new_witness(cs.clone(), || Ok(Amount(a))).unwrap();
+                    gadget(&a_var, &b_const).unwrap();
+                    assert_eq!(cs.is_satisfied().unwrap(), expected.is_some(), "{a}, {b}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn checked_arithmetic_constraint_count() {
+        for (gadget, _) in operations() {
+            let cs = ConstraintSystem::<ConstraintF>::new_ref();
+            let a = AmountVar::new_witness(cs.clone(), || Ok(Amount(5))).unwrap();
+            let b = AmountVar::new_witness(cs.clone(), || Ok(Amount(3))).unwrap();
+            let before = cs.num_constraints();
+            gadget(&a, &b).unwrap();
+            // 64 booleanity constraints for the result, and 1 equality.
+            assert_eq!(cs.num_constraints() - before, 65);
+        }
+    }
+}
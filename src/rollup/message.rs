@@ -0,0 +1,55 @@
+use super::{
+    account::AccountIdVar,
+    ledger::{AmountVar, AssetIdVar},
+    ConstraintF,
+};
+use crate::payment::message::{TransactionKind, MESSAGE_DOMAIN};
+use ark_r1cs_std::uint8::UInt8;
+
+/// The R1CS equivalent of `payment::message::MessageEncoder`. The domain, the lengths
+/// and the transaction kind tag are constants, so encoding adds no constraints.
+pub struct MessageEncoderVar {
+    bytes: Vec<UInt8<ConstraintF>>,
+}
+
+impl MessageEncoderVar {
+    /// Start a message of kind `kind` for the ledger identified by `ledger_id`.
+    #[tracing::instrument(target = "r1cs", skip(ledger_id))]
+    pub fn new(ledger_id: &[UInt8<ConstraintF>], kind: TransactionKind) -> Self {
+        let mut encoder = Self { bytes: Vec::new() };
+        encoder.field(&UInt8::constant_vec(MESSAGE_DOMAIN));
+        encoder.field(ledger_id);
+        encoder.field(&[UInt8::constant(kind.tag())]);
+        encoder
+    }
+
+    /// Append a length-prefixed field.
+    pub fn field(&mut self, field: &[UInt8<ConstraintF>]) -> &mut Self {
+        self.bytes
+            .extend(UInt8::constant_vec(&(field.len() as u32).to_le_bytes()));
+        self.bytes.extend_from_slice(field);
+        self
+    }
+
+    /// Return the encoded message.
+    pub fn finish(&mut self) -> Vec<UInt8<ConstraintF>> {
+        std::mem::take(&mut self.bytes)
+    }
+}
+
+/// The R1CS equivalent of `payment::message::transfer_message`.
+#[tracing::instrument(target = "r1cs", skip(ledger_id, sender, recipient, asset_id, amount))]
+pub fn transfer_message_var(
+    ledger_id: &[UInt8<ConstraintF>],
+    sender: &AccountIdVar,
+    recipient: &AccountIdVar,
+    asset_id: &AssetIdVar,
+    amount: &AmountVar,
+) -> Vec<UInt8<ConstraintF>> {
+    MessageEncoderVar::new(ledger_id, TransactionKind::Transfer)
+        .field(&sender.to_bytes_le())
+        .field(&recipient.to_bytes_le())
+        .field(&asset_id.to_bytes_le())
+        .field(&amount.to_bytes_le())
+        .finish()
+}
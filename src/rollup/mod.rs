@@ -1,6 +1,9 @@
 pub mod account;
 pub mod ledger;
+pub mod message;
+pub mod mutators;
 pub mod transaction;
+pub mod tree;
 pub mod rollup;
 
 pub type ConstraintF = ark_ed_on_bls12_381::Fq;
@@ -0,0 +1,174 @@
+use super::rollup::UnaryRollup;
+use crate::{
+    basic_merkle_tree::mutators::PathMutation,
+    payment::{
+        account::AccountId,
+        ledger::{AccRoot, Amount, Parameters, State},
+        transaction::Transaction,
+    },
+};
+
+/// A way for a dishonest prover to corrupt the witness of a `UnaryRollup`. The rollup
+/// circuit should be unsatisfiable with any of them.
+#[derive(Hash, Eq, PartialEq, Copy, Clone, Debug)]
+pub enum RollupMutation {
+    /// Corrupt the path of the sender's leaf.
+    SenderPath(PathMutation),
+    /// Corrupt the path of the recipient's leaf.
+    RecipientPath(PathMutation),
+    /// Transfer one more unit than the sender's balance, and claim the root obtained
+    /// when the sender's balance wraps around.
+    Overdraw,
+    /// Transfer just enough for the recipient's balance to overflow, and claim the root
+    /// obtained when it wraps around. This isolates the overflow check only if the
+    /// sender can afford the amount.
+    OverflowRecipient,
+    /// Prove the sender's information with the leaf and path of another account.
+    SubstituteSender(AccountId),
+    /// Prove the recipient's information with the leaf and path of another account.
+    SubstituteRecipient(AccountId),
+}
+
+impl RollupMutation {
+    /// Apply the mutation to `circuit`, which proves a transaction applied to `state`.
+    pub fn apply(&self, ledger_params: &Parameters, state: &State, circuit: &mut UnaryRollup) {
+        let tx = &circuit.transaction;
+        let asset = tx.asset_id.0 as usize;
+        match *self {
+            RollupMutation::SenderPath(mutation) => mutation.apply(&mut circuit.sender_path),
+            RollupMutation::RecipientPath(mutation) => mutation.apply(&mut circuit.recipient_path),
+            RollupMutation::Overdraw => {
+                let balance = state.id_to_account_info[&tx.sender].balances[asset];
+                let amount = Amount(balance.0.wrapping_add(1));
+                *circuit = wrapping_rollup(ledger_params, state, tx, amount);
+            }
+            RollupMutation::OverflowRecipient => {
+                let balance = state.id_to_account_info[&tx.recipient].balances[asset];
+                let amount = Amount((u64::MAX - balance.0).wrapping_add(1));
+                *circuit = wrapping_rollup(ledger_params, state, tx, amount);
+            }
+            RollupMutation::SubstituteSender(other) => {
+                circuit.sender_acc_info = state.id_to_account_info[&other];
+                circuit.sender_path = state
+                    .account_merkle_tree
+                    .generate_proof(other.0 as usize)
+                    .unwrap();
+            }
+            RollupMutation::SubstituteRecipient(other) => {
+                circuit.recipient_acc_info = state.id_to_account_info[&other];
+                circuit.recipient_path = state
+                    .account_merkle_tree
+                    .generate_proof(other.0 as usize)
+                    .unwrap();
+            }
+        }
+    }
+}
+
+/// The circuit proving `tx` with `amount` applied to `state`, and claiming the root
+/// obtained when balances wrap around instead of being rejected.
+fn wrapping_rollup(
+    ledger_params: &Parameters,
+    state: &State,
+    tx: &Transaction,
+    amount: Amount,
+) -> UnaryRollup {
+    let tx = Transaction {
+        amount,
+        ..tx.clone()
+    };
+    let circuit = UnaryRollup::new(ledger_params, state, &tx, state.root()).unwrap();
+    UnaryRollup {
+        final_root: wrapping_root(state, &circuit),
+        ..circuit
+    }
+}
+
+/// The root obtained by crediting the recipient in the witness of `circuit`, with
+/// wrapping arithmetic.
+fn wrapping_root(state: &State, circuit: &UnaryRollup) -> AccRoot {
+    let tx = &circuit.transaction;
+    let asset = tx.asset_id.0 as usize;
+    let mut tree = state.account_merkle_tree.clone();
+    let mut sender = circuit.sender_acc_info;
+    sender.balances[asset].0 = sender.balances[asset].0.wrapping_sub(tx.amount.0);
+    tree.update(tx.sender.0 as usize, sender.to_bytes_le().as_slice())
+        .unwrap();
+    let mut recipient = circuit.recipient_acc_info;
+    recipient.balances[asset].0 = recipient.balances[asset].0.wrapping_add(tx.amount.0);
+    tree.update(tx.recipient.0 as usize, recipient.to_bytes_le().as_slice())
+        .unwrap();
+    tree.root()
+}
+
+#[cfg(test)]
+mod test {
+    use super::RollupMutation;
+    use crate::{
+        basic_merkle_tree::mutators::PathMutation,
+        payment::{
+            ledger::{Amount, AssetId, Parameters, State},
+            transaction::Transaction,
+        },
+        rollup::{rollup::UnaryRollup, ConstraintF},
+    };
+    use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystem};
+
+    fn is_satisfied(circuit: UnaryRollup) -> bool {
+        let cs = ConstraintSystem::<ConstraintF>::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+        cs.is_satisfied().unwrap()
+    }
+
+    #[test]
+    fn mutated_rollup_witnesses_are_unsatisfiable() {
+        let mut rng = ark_std::test_rng();
+        let pp = Parameters::sample(&mut rng);
+        let mut state = State::new(16, &pp);
+        let (alice_id, _alice_pk, alice_sk) =
+            state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        let (bob_id, _bob_pk, _bob_sk) = state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        let (carol_id, _carol_pk, _carol_sk) =
+            state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        // Alice can afford any amount that overflows Bob's balance.
+        state
+            .update_balance(alice_id, AssetId(0), Amount(u64::MAX - 1))
+            .unwrap();
+        state.update_balance(bob_id, AssetId(0), Amount(2)).unwrap();
+        let tx = Transaction::create(
+            &pp,
+            alice_id,
+            bob_id,
+            AssetId(0),
+            Amount(1),
+            &alice_sk,
+            &mut rng,
+        );
+
+        let mut applied = state.clone();
+        applied.apply_transaction(&pp, &tx).unwrap();
+        let honest = UnaryRollup::new(&pp, &state, &tx, applied.root()).unwrap();
+        assert!(is_satisfied(honest.clone()));
+
+        let height = state.account_merkle_tree.height();
+        let mutations = PathMutation::all(height)
+            .into_iter()
+            .flat_map(|mutation| {
+                [
+                    RollupMutation::SenderPath(mutation),
+                    RollupMutation::RecipientPath(mutation),
+                ]
+            })
+            .chain([
+                RollupMutation::Overdraw,
+                RollupMutation::OverflowRecipient,
+                RollupMutation::SubstituteSender(carol_id),
+                RollupMutation::SubstituteRecipient(carol_id),
+            ]);
+        for mutation in mutations {
+            let mut circuit = honest.clone();
+            mutation.apply(&pp, &state, &mut circuit);
+            assert!(!is_satisfied(circuit), "{mutation:?} is satisfiable");
+        }
+    }
+}
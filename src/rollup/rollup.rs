@@ -0,0 +1,391 @@
+use super::{
+    account::{AccountIdVar, AccountInformationVar},
+    ledger::{AccPathVar, AccRootVar, ParametersVar},
+    transaction::TransactionVar,
+    tree::AccountTreeConfigVar,
+    ConstraintF,
+};
+use crate::{
+    basic_merkle_tree::storage::NodeStore,
+    payment::{
+        account::AccountInformation,
+        ledger::{AccPath, AccRoot, Parameters, State},
+        transaction::Transaction,
+        tree::Pedersen,
+    },
+};
+use ark_r1cs_std::prelude::*;
+use ark_relations::{
+    ns,
+    r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError},
+};
+
+/// A circuit proving that applying a single transfer to the account tree with root
+/// `initial_root` produces the account tree with root `final_root`. It enforces the
+/// same conditions as `State::apply_transaction`, except for the signature: the
+/// account tree commits to the account information, the asset is valid, the sender's
+/// balance covers the amount, and the recipient's balance does not overflow.
+///
+/// The signature is not checked, because ark-crypto-primitives has no Schnorr
+/// verification gadget. A transaction with an invalid signature therefore satisfies
+/// the circuit whenever it would otherwise be applied.
+///
+/// The account tree is hashed as configured by `H`.
+#[derive(Clone)]
+pub struct UnaryRollup<H: AccountTreeConfigVar = Pedersen> {
+    /// The ledger parameters, which are constants of the circuit.
+    pub ledger_params: Parameters<H>,
+    /// The root of the account tree before the transaction (public input).
+    pub initial_root: AccRoot<H>,
+    /// The root of the account tree after the transaction (public input).
+    pub final_root: AccRoot<H>,
+    /// The applied transaction.
+    pub transaction: Transaction,
+    /// The information of the sender before the transaction.
+    pub sender_acc_info: AccountInformation,
+    /// The path of the sender's leaf, against `initial_root`.
+    pub sender_path: AccPath<H>,
+    /// The information of the recipient after the sender is debited.
+    pub recipient_acc_info: AccountInformation,
+    /// The path of the recipient's leaf, in the account tree after the sender is
+    /// debited.
+    pub recipient_path: AccPath<H>,
+}
+
+impl<H: AccountTreeConfigVar> UnaryRollup<H> {
+    /// Build the circuit claiming that applying `transaction` to `state` produces the
+    /// root `final_root`. The witness is computed as the native application would,
+    /// with wrapping arithmetic if the balances underflow or overflow, so that the
+    /// circuit is satisfied exactly when the claim holds.
+    /// Returns `None` if the sender or the recipient does not exist, in which case no
+    /// witness exists.
+    pub fn new<S: NodeStore<H::Config>>(
+        ledger_params: &Parameters<H>,
+        state: &State<H, S>,
+        transaction: &Transaction,
+        final_root: AccRoot<H>,
+    ) -> Option<Self> {
+        let sender_acc_info = *state.id_to_account_info.get(&transaction.sender)?;
+        state.id_to_account_info.get(&transaction.recipient)?;
+        let asset = transaction.asset_id.0 as usize;
+        let mut tree = state.account_merkle_tree.overlay();
+        let sender_path = tree.generate_proof(transaction.sender.0 as usize).ok()?;
+
+        // Debit the sender.
+        let mut debited_sender = sender_acc_info;
+        if let Some(balance) = debited_sender.balances.get_mut(asset) {
+            balance.0 = balance.0.wrapping_sub(transaction.amount.0);
+        }
+        tree.update(
+            transaction.sender.0 as usize,
+            H::leaf(&debited_sender).as_slice(),
+        )
+        .ok()?;
+        let recipient_acc_info = if transaction.sender == transaction.recipient {
+            debited_sender
+        } else {
+            state.id_to_account_info[&transaction.recipient]
+        };
+        let recipient_path = tree.generate_proof(transaction.recipient.0 as usize).ok()?;
+        Some(Self {
+            ledger_params: ledger_params.clone(),
+            initial_root: state.root(),
+            final_root,
+            transaction: transaction.clone(),
+            sender_acc_info,
+            sender_path,
+            recipient_acc_info,
+            recipient_path,
+        })
+    }
+
+    /// Allocate the witness and enforce the transition from the allocated
+    /// `initial_root`. Returns the root of the account tree after the transaction.
+    fn apply(
+        &self,
+        cs: ConstraintSystemRef<ConstraintF>,
+        initial_root: &AccRootVar<H>,
+    ) -> Result<AccRootVar<H>, SynthesisError> {
+        let ledger_params =
+            ParametersVar::new_constant(ns!(cs, "ledger_params"), &self.ledger_params)?;
+        let tx = TransactionVar::new_witness(ns!(cs, "transaction"), || Ok(&self.transaction))?;
+        let sender_acc_info =
+            AccountInformationVar::new_witness(ns!(cs, "sender_acc_info"), || {
+                Ok(&self.sender_acc_info)
+            })?;
+        let sender_path =
+            AccPathVar::<H>::new_witness(ns!(cs, "sender_path"), || Ok(&self.sender_path))?;
+        let recipient_acc_info =
+            AccountInformationVar::new_witness(ns!(cs, "recipient_acc_info"), || {
+                Ok(&self.recipient_acc_info)
+            })?;
+        let recipient_path =
+            AccPathVar::<H>::new_witness(ns!(cs, "recipient_path"), || Ok(&self.recipient_path))?;
+
+        // Debit the sender, whose information is committed under `initial_root`.
+        enforce_leaf_position::<H>(&sender_path, &tx.sender)?;
+        let sender_balance = sender_acc_info.balance(&tx.asset_id)?;
+        let debited_sender =
+            sender_acc_info.with_balance(&tx.asset_id, &sender_balance.checked_sub(&tx.amount)?)?;
+        let intermediate_root = sender_path.update_leaf(
+            &ledger_params.leaf_crh_params,
+            &ledger_params.two_to_one_crh_params,
+            initial_root,
+            &H::leaf_var(&sender_acc_info)?,
+            &H::leaf_var(&debited_sender)?,
+        )?;
+
+        // Credit the recipient, whose information is committed under the intermediate
+        // root. For a transfer to the sender itself, this is the debited information.
+        enforce_leaf_position::<H>(&recipient_path, &tx.recipient)?;
+        let recipient_balance = recipient_acc_info.balance(&tx.asset_id)?;
+        let credited_recipient = recipient_acc_info
+            .with_balance(&tx.asset_id, &recipient_balance.checked_add(&tx.amount)?)?;
+        recipient_path.update_leaf(
+            &ledger_params.leaf_crh_params,
+            &ledger_params.two_to_one_crh_params,
+            &intermediate_root,
+            &H::leaf_var(&recipient_acc_info)?,
+            &H::leaf_var(&credited_recipient)?,
+        )
+    }
+}
+
+impl<H: AccountTreeConfigVar> ConstraintSynthesizer<ConstraintF> for UnaryRollup<H> {
+    #[tracing::instrument(target = "r1cs", skip(self, cs))]
+    fn generate_constraints(
+        self,
+        cs: ConstraintSystemRef<ConstraintF>,
+    ) -> Result<(), SynthesisError> {
+        // allocate public inputs
+        let initial_root =
+            AccRootVar::<H>::new_input(ns!(cs, "initial_root"), || Ok(&self.initial_root))?;
+        let final_root =
+            AccRootVar::<H>::new_input(ns!(cs, "final_root"), || Ok(&self.final_root))?;
+        let new_root = self.apply(cs, &initial_root)?;
+        new_root.enforce_equal(&final_root)
+    }
+}
+
+/// Enforce that `path` is the path of the leaf of account `id`. Identifiers that do
+/// not fit in the tree have no path.
+fn enforce_leaf_position<H: AccountTreeConfigVar>(
+    path: &AccPathVar<H>,
+    id: &AccountIdVar,
+) -> Result<(), SynthesisError> {
+    let position = path.get_leaf_position();
+    let id_bits = id.0.to_bits_le()?;
+    // Missing bits of the shorter of the two are zero.
+    for i in 0..position.len().max(id_bits.len()) {
+        let position_bit = position.get(i).cloned().unwrap_or(Boolean::FALSE);
+        let id_bit = id_bits.get(i).cloned().unwrap_or(Boolean::FALSE);
+        id_bit.enforce_equal(&position_bit)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::UnaryRollup;
+    use crate::{
+        payment::{
+            account::{AccountId, AccountSecretKey},
+            ledger::{Amount, AssetId, Parameters, State, NUM_ASSETS},
+            transaction::Transaction,
+            tree::{AccountTreeConfig, Poseidon},
+        },
+        rollup::{ledger::AccRootVar, tree::AccountTreeConfigVar, ConstraintF},
+    };
+    use ark_r1cs_std::{
+        prelude::{AllocVar, EqGadget},
+        R1CSVar,
+    };
+    use ark_relations::r1cs::ConstraintSystem;
+    use ark_std::rand::{rngs::StdRng, SeedableRng};
+    use proptest::prelude::*;
+    use std::sync::OnceLock;
+
+    /// The number of accounts registered in the ledger under test.
+    const NUM_ACCOUNTS: u8 = 3;
+
+    /// Parameters, and a funded ledger with its keys. The last account holds nearly
+    /// the maximum balance of the last asset, so that transfers to it can overflow.
+    fn funded_ledger<H: AccountTreeConfig>() -> (Parameters<H>, State<H>, Vec<AccountSecretKey>) {
+        let mut rng = ark_std::test_rng();
+        let pp = Parameters::sample_with_tree(&mut rng);
+        let mut state = State::new(16, &pp);
+        let mut keys = Vec::new();
+        for _ in 0..NUM_ACCOUNTS {
+            let (id, _pk, sk) = state.sample_keys_and_register(&pp, &mut rng).unwrap();
+            for asset in 0..NUM_ASSETS as u8 {
+                state
+                    .update_balance(id, AssetId(asset), Amount(10))
+                    .unwrap();
+            }
+            keys.push(sk);
+        }
+        let whale = AccountId(NUM_ACCOUNTS);
+        let asset = AssetId(NUM_ASSETS as u8 - 1);
+        state
+            .update_balance(whale, asset, Amount(u64::MAX - 5))
+            .unwrap();
+        (pp, state, keys)
+    }
+
+    /// The funded ledger with a Pedersen account tree, shared by every test case.
+    fn setup() -> &'static (Parameters, State, Vec<AccountSecretKey>) {
+        static SETUP: OnceLock<(Parameters, State, Vec<AccountSecretKey>)> = OnceLock::new();
+        SETUP.get_or_init(funded_ledger)
+    }
+
+    /// The funded ledger with a Poseidon account tree, shared by every test case.
+    fn poseidon_setup() -> &'static (Parameters<Poseidon>, State<Poseidon>, Vec<AccountSecretKey>) {
+        static SETUP: OnceLock<(Parameters<Poseidon>, State<Poseidon>, Vec<AccountSecretKey>)> =
+            OnceLock::new();
+        SETUP.get_or_init(funded_ledger)
+    }
+
+    /// Check that the rollup circuit for `tx` is satisfied exactly when `tx` can be
+    /// applied to `state`, and that it computes the resulting root. Applies `tx` to
+    /// `state`.
+    fn check_transition<H: AccountTreeConfigVar>(
+        pp: &Parameters<H>,
+        state: &mut State<H>,
+        tx: &Transaction,
+    ) {
+        let initial_root = state.root();
+        let circuit = UnaryRollup::new(pp, state, tx, initial_root.clone()).unwrap();
+        let applied = state.apply_transaction(pp, tx).is_some();
+        let circuit = UnaryRollup {
+            final_root: state.root(),
+            ..circuit
+        };
+
+        // Synthesize as `generate_constraints` does, keeping the computed root.
+        let cs = ConstraintSystem::<ConstraintF>::new_ref();
+        let initial_root_var = AccRootVar::<H>::new_input(cs.clone(), || Ok(initial_root)).unwrap();
+        let final_root_var = AccRootVar::<H>::new_input(cs.clone(), || Ok(state.root())).unwrap();
+        let new_root = circuit.apply(cs.clone(), &initial_root_var).unwrap();
+        new_root.enforce_equal(&final_root_var).unwrap();
+        if applied {
+            assert_eq!(new_root.value().unwrap(), final_root_var.value().unwrap());
+        }
+        assert_eq!(cs.is_satisfied().unwrap(), applied);
+    }
+
+    fn transfer() -> impl Strategy<Value = (u8, u8, u8, u64)> {
+        let id = 1..=NUM_ACCOUNTS;
+        let asset = 0..=NUM_ASSETS as u8;
+        let amount = prop_oneof![0..12u64, Just(u64::MAX), any::<u64>()];
+        (id.clone(), id, asset, amount)
+    }
+
+    /// Check every transition of `transfers`, applied in order to a copy of `state`.
+    /// Every transaction is signed by its sender, since the circuit does not check
+    /// signatures.
+    fn check_transfers<H: AccountTreeConfigVar>(
+        pp: &Parameters<H>,
+        state: &State<H>,
+        keys: &[AccountSecretKey],
+        transfers: Vec<(u8, u8, u8, u64)>,
+    ) {
+        let mut state = state.clone();
+        let mut rng = StdRng::seed_from_u64(0);
+        for (sender, recipient, asset, amount) in transfers {
+            let tx = Transaction::create(
+                pp,
+                AccountId(sender),
+                AccountId(recipient),
+                AssetId(asset),
+                Amount(amount),
+                &keys[sender as usize - 1],
+                &mut rng,
+            );
+            check_transition(pp, &mut state, &tx);
+        }
+    }
+
+    proptest! {
+        // The Pedersen circuit is about 9 times larger than the Poseidon one, which
+        // runs most of the cases.
+        #![proptest_config(ProptestConfig::with_cases(6))]
+
+        #[test]
+        fn circuit_matches_native_application(
+            transfers in prop::collection::vec(transfer(), 1..4)
+        ) {
+            let (pp, state, keys) = setup();
+            check_transfers(pp, state, keys, transfers);
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn poseidon_circuit_matches_native_application(
+            transfers in prop::collection::vec(transfer(), 1..4)
+        ) {
+            let (pp, state, keys) = poseidon_setup();
+            check_transfers(pp, state, keys, transfers);
+        }
+    }
+
+    #[test]
+    fn circuit_rejects_what_native_application_rejects() {
+        let (pp, state, keys) = setup();
+        check_rejections(pp, state.clone(), keys);
+    }
+
+    #[test]
+    fn poseidon_circuit_rejects_what_native_application_rejects() {
+        let (pp, state, keys) = funded_ledger::<Poseidon>();
+        check_rejections(&pp, state, &keys);
+    }
+
+    /// Check transitions that are valid, then invalid for each reason the circuit
+    /// enforces, and that no circuit exists for unknown accounts.
+    fn check_rejections<H: AccountTreeConfigVar>(
+        pp: &Parameters<H>,
+        mut state: State<H>,
+        keys: &[AccountSecretKey],
+    ) {
+        let mut rng = StdRng::seed_from_u64(0);
+        let whale = NUM_ACCOUNTS;
+        let last_asset = NUM_ASSETS as u8 - 1;
+        for (sender, recipient, asset, amount) in [
+            (1, 2, 0, 4),
+            (1, 1, 0, 3),
+            // Insufficient balance, also for a transfer to the sender itself.
+            (1, 2, 0, 7),
+            (2, 2, 1, 11),
+            // Invalid asset.
+            (1, 2, NUM_ASSETS as u8, 0),
+            // Overflow of the recipient's balance.
+            (1, whale, last_asset, 6),
+            (1, whale, last_asset, 5),
+        ] {
+            let tx = Transaction::create(
+                pp,
+                AccountId(sender),
+                AccountId(recipient),
+                AssetId(asset),
+                Amount(amount),
+                &keys[sender as usize - 1],
+                &mut rng,
+            );
+            check_transition(pp, &mut state, &tx);
+        }
+        // No witness exists for unknown accounts.
+        let tx = Transaction::create(
+            pp,
+            AccountId(1),
+            AccountId(NUM_ACCOUNTS + 1),
+            AssetId(0),
+            Amount(1),
+            &keys[0],
+            &mut rng,
+        );
+        assert!(UnaryRollup::new(pp, &state, &tx, state.root()).is_none());
+    }
+}
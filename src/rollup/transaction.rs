@@ -1,4 +1,16 @@
-use super::{account::AccountIdVar, ledger::AmountVar};
+use super::{
+    account::AccountIdVar,
+    ledger::{AmountVar, AssetIdVar, ParametersVar},
+    message::transfer_message_var,
+    ConstraintF,
+};
+use crate::payment::transaction::Transaction;
+use ark_r1cs_std::{
+    prelude::{AllocVar, AllocationMode},
+    uint8::UInt8,
+};
+use ark_relations::r1cs::{Namespace, SynthesisError};
+use std::borrow::Borrow;
 
 /// Transaction transferring some amount from one account to another.
 pub struct TransactionVar {
@@ -6,14 +18,95 @@ pub struct TransactionVar {
     pub sender: AccountIdVar,
     /// The account information of the recipient.
     pub recipient: AccountIdVar,
+    /// The asset being transferred.
+    pub asset_id: AssetIdVar,
     /// The amount being transferred from the sender to the receiver.
     pub amount: AmountVar,
     // Missing implementation
     // pub signature: <SigVerifyGadget<>>::SignatureVar;
 }
 
+impl TransactionVar {
+    /// The message authorized by the sender's signature. This matches
+    /// `Transaction::message` byte for byte.
+    #[tracing::instrument(target = "r1cs", skip(self, parameters))]
+    pub fn message(&self, parameters: &ParametersVar) -> Vec<UInt8<ConstraintF>> {
+        transfer_message_var(
+            &parameters.ledger_id,
+            &self.sender,
+            &self.recipient,
+            &self.asset_id,
+            &self.amount,
+        )
+    }
+}
+
+impl AllocVar<Transaction, ConstraintF> for TransactionVar {
+    #[tracing::instrument(target = "r1cs", skip(cs, f, mode))]
+    fn new_variable<T: Borrow<Transaction>>(
+        cs: impl Into<Namespace<ConstraintF>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let cs = cs.into();
+        f().and_then(|tx: T| {
+            let tx: &Transaction = tx.borrow();
+            let sender = AccountIdVar::new_variable(cs.clone(), || Ok(&tx.sender), mode)?;
+            let recipient = AccountIdVar::new_variable(cs.clone(), || Ok(&tx.recipient), mode)?;
+            let asset_id = AssetIdVar::new_variable(cs.clone(), || Ok(&tx.asset_id), mode)?;
+            let amount = AmountVar::new_variable(cs.clone(), || Ok(&tx.amount), mode)?;
+            Ok(Self {
+                sender,
+                recipient,
+                asset_id,
+                amount,
+            })
+        })
+    }
+}
+
 // IMPORTANT NOTE: I am stuck here, where struct SignatureVar is missing in the library
 // ark-crypto-primitive. I feel that this library is not good choice for
 // ongoing development, it may be impact by missing implementation, and
 // lack of ongoing support.
 // Still, I am happy that I learn a lot throughout the coding days.
+
+#[cfg(test)]
+mod test {
+    use super::TransactionVar;
+    use crate::{
+        payment::{
+            ledger::{Amount, AssetId, LedgerId, Parameters, State},
+            transaction::Transaction,
+        },
+        rollup::{ledger::ParametersVar, ConstraintF},
+    };
+    use ark_r1cs_std::{prelude::AllocVar, R1CSVar};
+    use ark_relations::r1cs::ConstraintSystem;
+
+    #[test]
+    fn message_gadget_matches_native_message() {
+        let mut rng = ark_std::test_rng();
+        let pp = Parameters::sample(&mut rng).with_ledger_id(LedgerId(b"testnet".to_vec()));
+        let mut state = State::new(32, &pp);
+        let (alice_id, _alice_pk, alice_sk) =
+            state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        let (bob_id, _bob_pk, _bob_sk) = state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        let tx = Transaction::create(
+            &pp,
+            alice_id,
+            bob_id,
+            AssetId(2),
+            Amount(5),
+            &alice_sk,
+            &mut rng,
+        );
+
+        let cs = ConstraintSystem::<ConstraintF>::new_ref();
+        let pp_var = ParametersVar::new_constant(cs.clone(), &pp).unwrap();
+        let tx_var = TransactionVar::new_witness(cs.clone(), || Ok(&tx)).unwrap();
+        let message = tx_var.message(&pp_var).value().unwrap();
+        assert_eq!(message, tx.message(&pp));
+        assert!(cs.is_satisfied().unwrap());
+    }
+}
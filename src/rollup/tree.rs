@@ -0,0 +1,59 @@
+use super::{account::AccountInformationVar, ConstraintF};
+use crate::{
+    basic_merkle_tree::{common::JubJubMerkleTreeParamsVar, poseidon::PoseidonMerkleTreeParamsVar},
+    payment::tree::{AccountTreeConfig, Pedersen, Poseidon},
+};
+use ark_crypto_primitives::{
+    crh::{CRHSchemeGadget, TwoToOneCRHSchemeGadget},
+    merkle_tree::{constraints::ConfigGadget, Config},
+};
+use ark_r1cs_std::{fields::fp::FpVar, uint8::UInt8};
+use ark_relations::r1cs::SynthesisError;
+
+/// The R1CS equivalent of an `AccountTreeConfig`.
+pub trait AccountTreeConfigVar: AccountTreeConfig {
+    /// The R1CS equivalent of `Self::LeafElement`.
+    type LeafElementVar;
+    /// The R1CS equivalent of `Self::Config`.
+    type ConfigVar: ConfigGadget<Self::Config, ConstraintF, Leaf = [Self::LeafElementVar]>;
+
+    /// Encode `info` into the leaf of its account, as `Self::leaf` does natively.
+    fn leaf_var(info: &AccountInformationVar) -> Result<Vec<Self::LeafElementVar>, SynthesisError>;
+}
+
+/// The R1CS equivalent of the parameters of the leaf hash of the account Merkle tree.
+pub type LeafHashParamsVar<H> = <<<H as AccountTreeConfigVar>::ConfigVar as ConfigGadget<
+    <H as AccountTreeConfig>::Config,
+    ConstraintF,
+>>::LeafHash as CRHSchemeGadget<
+    <<H as AccountTreeConfig>::Config as Config>::LeafHash,
+    ConstraintF,
+>>::ParametersVar;
+
+/// The R1CS equivalent of the parameters of the two-to-one hash of the account Merkle
+/// tree.
+pub type TwoToOneHashParamsVar<H> = <<<H as AccountTreeConfigVar>::ConfigVar as ConfigGadget<
+    <H as AccountTreeConfig>::Config,
+    ConstraintF,
+>>::TwoToOneHash as TwoToOneCRHSchemeGadget<
+    <<H as AccountTreeConfig>::Config as Config>::TwoToOneHash,
+    ConstraintF,
+>>::ParametersVar;
+
+impl AccountTreeConfigVar for Pedersen {
+    type LeafElementVar = UInt8<ConstraintF>;
+    type ConfigVar = JubJubMerkleTreeParamsVar;
+
+    fn leaf_var(info: &AccountInformationVar) -> Result<Vec<UInt8<ConstraintF>>, SynthesisError> {
+        Ok(info.to_bytes_le())
+    }
+}
+
+impl AccountTreeConfigVar for Poseidon {
+    type LeafElementVar = FpVar<ConstraintF>;
+    type ConfigVar = PoseidonMerkleTreeParamsVar;
+
+    fn leaf_var(info: &AccountInformationVar) -> Result<Vec<FpVar<ConstraintF>>, SynthesisError> {
+        info.to_field_elements()
+    }
+}
@@ -0,0 +1,173 @@
+use ark_crypto_primitives::{
+    crh::{CRHSchemeGadget, TwoToOneCRHSchemeGadget},
+    merkle_tree::{
+        constraints::{ConfigGadget, PathVar},
+        Config,
+    },
+};
+use ark_ff::Field;
+use ark_r1cs_std::prelude::*;
+use ark_relations::r1cs::SynthesisError;
+
+/// The R1CS equivalent of the parameters of the leaf hash of `PG`.
+pub type LeafHashParamsVar<P, F, PG> = <<PG as ConfigGadget<P, F>>::LeafHash as CRHSchemeGadget<
+    <P as Config>::LeafHash,
+    F,
+>>::ParametersVar;
+
+/// The R1CS equivalent of the parameters of the two-to-one hash of `PG`.
+pub type TwoToOneHashParamsVar<P, F, PG> =
+    <<PG as ConfigGadget<P, F>>::TwoToOneHash as TwoToOneCRHSchemeGadget<
+        <P as Config>::TwoToOneHash,
+        F,
+    >>::ParametersVar;
+
+/// Return whether `path` proves that the leaf at the key with little-endian bits
+/// `key` is `leaf` in the sparse Merkle tree with root `root`. As natively, a path
+/// for another key is rejected, so `key` may have more bits than the tree's depth
+/// only if the extra bits are zero.
+#[tracing::instrument(
+    target = "r1cs",
+    skip(leaf_hash_params, two_to_one_hash_params, root, key, leaf, path)
+)]
+pub fn verify_membership<P: Config, F: Field, PG: ConfigGadget<P, F>>(
+    leaf_hash_params: &LeafHashParamsVar<P, F, PG>,
+    two_to_one_hash_params: &TwoToOneHashParamsVar<P, F, PG>,
+    root: &PG::InnerDigest,
+    key: &[Boolean<F>],
+    leaf: &PG::Leaf,
+    path: &PathVar<P, F, PG>,
+) -> Result<Boolean<F>, SynthesisError> {
+    let position = path.get_leaf_position();
+    // Missing bits of the shorter of the two are zero.
+    let bits_match = (0..position.len().max(key.len()))
+        .map(|i| {
+            let position_bit = position.get(i).cloned().unwrap_or(Boolean::FALSE);
+            let key_bit = key.get(i).cloned().unwrap_or(Boolean::FALSE);
+            key_bit.is_eq(&position_bit)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let is_member = path.verify_membership(leaf_hash_params, two_to_one_hash_params, root, leaf)?;
+    Boolean::kary_and(&bits_match)?.and(&is_member)
+}
+
+/// Return whether `path` proves that the key with little-endian bits `key` is absent
+/// from the sparse Merkle tree with root `root`, i.e. that its leaf is `empty_leaf`.
+#[tracing::instrument(
+    target = "r1cs",
+    skip(leaf_hash_params, two_to_one_hash_params, root, key, empty_leaf, path)
+)]
+pub fn verify_non_membership<P: Config, F: Field, PG: ConfigGadget<P, F>>(
+    leaf_hash_params: &LeafHashParamsVar<P, F, PG>,
+    two_to_one_hash_params: &TwoToOneHashParamsVar<P, F, PG>,
+    root: &PG::InnerDigest,
+    key: &[Boolean<F>],
+    empty_leaf: &PG::Leaf,
+    path: &PathVar<P, F, PG>,
+) -> Result<Boolean<F>, SynthesisError> {
+    verify_membership(
+        leaf_hash_params,
+        two_to_one_hash_params,
+        root,
+        key,
+        empty_leaf,
+        path,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{verify_membership, verify_non_membership};
+    use crate::{
+        basic_merkle_tree::{
+            common::ConstraintF,
+            poseidon::{
+                poseidon_parameters, PoseidonMerkleTreeParams, PoseidonMerkleTreeParamsVar,
+            },
+        },
+        sparse_merkle_tree::SparseMerkleTree,
+    };
+    use ark_crypto_primitives::{
+        crh::poseidon::constraints::CRHParametersVar,
+        merkle_tree::{constraints::PathVar, Path},
+    };
+    use ark_r1cs_std::{fields::fp::FpVar, prelude::*, uint64::UInt64};
+    use ark_relations::{ns, r1cs::ConstraintSystem};
+
+    type SparsePathVar =
+        PathVar<PoseidonMerkleTreeParams, ConstraintF, PoseidonMerkleTreeParamsVar>;
+
+    /// Whether the circuit checking `path` against `key` and `leaf` is satisfied,
+    /// for a membership proof if `member`, and a non-membership proof otherwise.
+    fn is_satisfied(
+        tree: &SparseMerkleTree<PoseidonMerkleTreeParams>,
+        key: u64,
+        leaf: ConstraintF,
+        path: &Path<PoseidonMerkleTreeParams>,
+        member: bool,
+    ) -> bool {
+        let params = poseidon_parameters();
+        let cs = ConstraintSystem::<ConstraintF>::new_ref();
+        let params_var = CRHParametersVar::new_constant(ns!(cs, "params"), &params).unwrap();
+        let root = FpVar::new_input(ns!(cs, "root"), || Ok(tree.root())).unwrap();
+        let key = UInt64::new_witness(ns!(cs, "key"), || Ok(key))
+            .unwrap()
+            .to_bits_le();
+        let leaf = vec![FpVar::new_witness(ns!(cs, "leaf"), || Ok(leaf)).unwrap()];
+        let path = SparsePathVar::new_witness(ns!(cs, "path"), || Ok(path)).unwrap();
+        let is_valid = if member {
+            verify_membership(
+                &params_var,
+                &params_var,
+                &root,
+                &key,
+                leaf.as_slice(),
+                &path,
+            )
+        } else {
+            verify_non_membership(
+                &params_var,
+                &params_var,
+                &root,
+                &key,
+                leaf.as_slice(),
+                &path,
+            )
+        };
+        is_valid.unwrap().enforce_equal(&Boolean::TRUE).unwrap();
+        cs.is_satisfied().unwrap()
+    }
+
+    #[test]
+    fn sparse_merkle_tree_gadgets() {
+        let params = poseidon_parameters();
+        let empty_leaf = ConstraintF::from(0u8);
+        let mut tree =
+            SparseMerkleTree::<PoseidonMerkleTreeParams>::new(&params, &params, 40, &[empty_leaf])
+                .unwrap();
+        let (key, leaf) = ((1 << 39) + 5, ConstraintF::from(7u8));
+        tree.update(key, &[leaf]).unwrap();
+        tree.update(4, &[ConstraintF::from(8u8)]).unwrap();
+
+        let path = tree.generate_proof(key).unwrap();
+        assert!(is_satisfied(&tree, key, leaf, &path, true));
+        assert!(!is_satisfied(&tree, key, empty_leaf, &path, false));
+        assert!(!is_satisfied(
+            &tree,
+            key,
+            ConstraintF::from(8u8),
+            &path,
+            true
+        ));
+        // Another key, and the same key with a bit above the depth set.
+        assert!(!is_satisfied(&tree, key ^ 1, leaf, &path, true));
+        assert!(!is_satisfied(&tree, key + (1 << 40), leaf, &path, true));
+
+        let absent = 5;
+        let path = tree.generate_proof(absent).unwrap();
+        assert!(is_satisfied(&tree, absent, empty_leaf, &path, false));
+        assert!(!is_satisfied(&tree, absent, leaf, &path, true));
+        // The path of an absent key proves nothing about its neighbour, which is present.
+        assert!(!is_satisfied(&tree, absent ^ 1, empty_leaf, &path, false));
+    }
+}
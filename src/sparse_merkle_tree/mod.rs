@@ -0,0 +1,366 @@
+use ark_crypto_primitives::{
+    crh::{CRHScheme, TwoToOneCRHScheme},
+    merkle_tree::{Config, DigestConverter, LeafParam, Path, TwoToOneParam},
+    Error,
+};
+use std::collections::HashMap;
+
+pub mod constraints;
+
+/// A Merkle tree with `2^depth` leaves indexed by `u64` keys, all of which initially
+/// hold the same empty leaf. Only the leaves that were set, and the internal nodes
+/// above them, are stored. Every other node is the default node of its level: the
+/// root of a subtree of empty leaves, which is computed once per level.
+///
+/// Its proofs are ark `Path`s from the leaf at a key to the root. The proof of a key
+/// that holds the empty leaf shows that the key is absent, so the empty leaf must not
+/// encode any value that is stored in the tree.
+pub struct SparseMerkleTree<P: Config> {
+    depth: usize,
+    leaf_hash_params: LeafParam<P>,
+    two_to_one_hash_params: TwoToOneParam<P>,
+    empty_leaf_digest: P::LeafDigest,
+    /// `default_nodes[level - 1]` is the root of a subtree of height `level` whose
+    /// leaves are all empty, where the leaves are at level 0.
+    default_nodes: Vec<P::InnerDigest>,
+    /// The digests of the leaves that are not empty, by key.
+    leaves: HashMap<u64, P::LeafDigest>,
+    /// The internal nodes that differ from the default node of their level, by level
+    /// and index within the level.
+    nodes: HashMap<(usize, u64), P::InnerDigest>,
+}
+
+impl<P: Config> SparseMerkleTree<P> {
+    /// The largest supported depth, at which every `u64` is a key.
+    pub const MAX_DEPTH: usize = 64;
+
+    /// Create a tree of `2^depth` leaves that all hold `empty_leaf`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `depth` is 0 or greater than `Self::MAX_DEPTH`.
+    pub fn new(
+        leaf_hash_params: &LeafParam<P>,
+        two_to_one_hash_params: &TwoToOneParam<P>,
+        depth: usize,
+        empty_leaf: &P::Leaf,
+    ) -> Result<Self, Error> {
+        assert!(
+            (1..=Self::MAX_DEPTH).contains(&depth),
+            "the depth of a sparse Merkle tree must be between 1 and {}",
+            Self::MAX_DEPTH
+        );
+        let empty_leaf_digest = P::LeafHash::evaluate(leaf_hash_params, empty_leaf)?;
+        let mut default_nodes = Vec::with_capacity(depth);
+        let mut node = P::TwoToOneHash::evaluate(
+            two_to_one_hash_params,
+            P::LeafInnerDigestConverter::convert(empty_leaf_digest.clone())?,
+            P::LeafInnerDigestConverter::convert(empty_leaf_digest.clone())?,
+        )?;
+        for _ in 1..depth {
+            let parent = P::TwoToOneHash::compress(two_to_one_hash_params, &node, &node)?;
+            default_nodes.push(std::mem::replace(&mut node, parent));
+        }
+        default_nodes.push(node);
+        Ok(Self {
+            depth,
+            leaf_hash_params: leaf_hash_params.clone(),
+            two_to_one_hash_params: two_to_one_hash_params.clone(),
+            empty_leaf_digest,
+            default_nodes,
+            leaves: HashMap::new(),
+            nodes: HashMap::new(),
+        })
+    }
+
+    /// Return the number of key bits, i.e. the number of levels above the leaves.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn root(&self) -> P::InnerDigest {
+        self.node(self.depth, 0)
+    }
+
+    /// Return whether the leaf at `key` is not the empty leaf.
+    pub fn contains(&self, key: u64) -> bool {
+        self.leaves.contains_key(&key)
+    }
+
+    /// Return the number of leaves that are not the empty leaf.
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    /// Replace the leaf at `key` by `new_leaf`, and rehash the path above it.
+    pub fn update(&mut self, key: u64, new_leaf: &P::Leaf) -> Result<(), Error> {
+        let digest = P::LeafHash::evaluate(&self.leaf_hash_params, new_leaf)?;
+        self.set_leaf_digest(key, digest)
+    }
+
+    /// Replace the leaf at `key` by the empty leaf, and rehash the path above it.
+    pub fn remove(&mut self, key: u64) -> Result<(), Error> {
+        self.set_leaf_digest(key, self.empty_leaf_digest.clone())
+    }
+
+    /// Return the path of the leaf at `key`. It proves membership of the leaf if one
+    /// was set, and non-membership of `key` otherwise.
+    pub fn generate_proof(&self, key: u64) -> Result<Path<P>, Error> {
+        self.check_key(key)?;
+        // The siblings of the internal nodes on the path, from the top down.
+        let auth_path = (1..self.depth)
+            .rev()
+            .map(|level| self.node(level, (key >> level) ^ 1))
+            .collect();
+        Ok(Path {
+            leaf_sibling_hash: self.leaf_digest(key ^ 1),
+            auth_path,
+            leaf_index: usize::try_from(key)?,
+        })
+    }
+
+    fn check_key(&self, key: u64) -> Result<(), Error> {
+        if is_in_range(key, self.depth) {
+            Ok(())
+        } else {
+            Err(format!("key {key} does not fit in {} bits", self.depth).into())
+        }
+    }
+
+    fn leaf_digest(&self, key: u64) -> P::LeafDigest {
+        self.leaves
+            .get(&key)
+            .cloned()
+            .unwrap_or_else(|| self.empty_leaf_digest.clone())
+    }
+
+    fn node(&self, level: usize, index: u64) -> P::InnerDigest {
+        self.nodes
+            .get(&(level, index))
+            .cloned()
+            .unwrap_or_else(|| self.default_nodes[level - 1].clone())
+    }
+
+    fn set_leaf_digest(&mut self, key: u64, digest: P::LeafDigest) -> Result<(), Error> {
+        self.check_key(key)?;
+        if digest == self.empty_leaf_digest {
+            self.leaves.remove(&key);
+        } else {
+            self.leaves.insert(key, digest.clone());
+        }
+
+        let sibling = self.leaf_digest(key ^ 1);
+        let (left, right) = if key & 1 == 0 {
+            (digest, sibling)
+        } else {
+            (sibling, digest)
+        };
+        let mut node = P::TwoToOneHash::evaluate(
+            &self.two_to_one_hash_params,
+            P::LeafInnerDigestConverter::convert(left)?,
+            P::LeafInnerDigestConverter::convert(right)?,
+        )?;
+        let mut index = key >> 1;
+        self.set_node(1, index, node.clone());
+        for level in 2..=self.depth {
+            let sibling = self.node(level - 1, index ^ 1);
+            let (left, right) = if index & 1 == 0 {
+                (node, sibling)
+            } else {
+                (sibling, node)
+            };
+            node = P::TwoToOneHash::compress(&self.two_to_one_hash_params, left, right)?;
+            index >>= 1;
+            self.set_node(level, index, node.clone());
+        }
+        Ok(())
+    }
+
+    /// Store `digest` at `(level, index)`, unless it is the default node of the
+    /// level, so that removing every leaf below a node also frees the node.
+    fn set_node(&mut self, level: usize, index: u64, digest: P::InnerDigest) {
+        if digest == self.default_nodes[level - 1] {
+            self.nodes.remove(&(level, index));
+        } else {
+            self.nodes.insert((level, index), digest);
+        }
+    }
+}
+
+/// Return whether `key` fits in `depth` bits.
+fn is_in_range(key: u64, depth: usize) -> bool {
+    depth >= 64 || key >> depth == 0
+}
+
+/// Check that `path` proves that the leaf at `key` is `leaf` in the sparse Merkle
+/// tree with root `root`. Unlike `Path::verify` alone, this rejects a path for
+/// another key, including one that only differs in bits above the tree's depth.
+pub fn verify_membership<P: Config>(
+    leaf_hash_params: &LeafParam<P>,
+    two_to_one_hash_params: &TwoToOneParam<P>,
+    root: &P::InnerDigest,
+    key: u64,
+    leaf: &P::Leaf,
+    path: &Path<P>,
+) -> Result<bool, Error> {
+    let depth = path.auth_path.len() + 1;
+    if !is_in_range(key, depth) || path.leaf_index as u64 != key {
+        return Ok(false);
+    }
+    path.verify(leaf_hash_params, two_to_one_hash_params, root, leaf)
+}
+
+/// Check that `path` proves that `key` is absent from the sparse Merkle tree with
+/// root `root`, i.e. that its leaf is `empty_leaf`.
+pub fn verify_non_membership<P: Config>(
+    leaf_hash_params: &LeafParam<P>,
+    two_to_one_hash_params: &TwoToOneParam<P>,
+    root: &P::InnerDigest,
+    key: u64,
+    empty_leaf: &P::Leaf,
+    path: &Path<P>,
+) -> Result<bool, Error> {
+    verify_membership(
+        leaf_hash_params,
+        two_to_one_hash_params,
+        root,
+        key,
+        empty_leaf,
+        path,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{verify_membership, verify_non_membership, SparseMerkleTree};
+    use crate::basic_merkle_tree::{
+        common::*,
+        poseidon::{poseidon_parameters, PoseidonMerkleTreeParams},
+    };
+    use ark_crypto_primitives::crh::{CRHScheme, TwoToOneCRHScheme};
+
+    #[test]
+    fn sparse_tree_matches_dense_tree() {
+        let mut rng = ark_std::test_rng();
+        let leaf_crh_params = <LeafH as CRHScheme>::setup(&mut rng).unwrap();
+        let two_to_one_crh_params = <CompressH as TwoToOneCRHScheme>::setup(&mut rng).unwrap();
+        let empty_leaf = vec![0u8; 30];
+        let mut leaves = vec![empty_leaf.clone(); 8];
+        let mut tree = SparseMerkleTree::<JubJubMerkleTreeParams>::new(
+            &leaf_crh_params,
+            &two_to_one_crh_params,
+            3,
+            empty_leaf.as_slice(),
+        )
+        .unwrap();
+        for (key, byte) in [(5u64, 1u8), (0, 2), (4, 3)] {
+            leaves[key as usize] = vec![byte; 30];
+            tree.update(key, leaves[key as usize].as_slice()).unwrap();
+        }
+        let dense = JubJubMerkleTree::new(
+            &leaf_crh_params,
+            &two_to_one_crh_params,
+            leaves.iter().map(|v| v.as_slice()),
+        )
+        .unwrap();
+        assert_eq!(tree.root(), dense.root());
+        for key in 0..8 {
+            let path = tree.generate_proof(key).unwrap();
+            let dense_path = dense.generate_proof(key as usize).unwrap();
+            assert_eq!(path.leaf_sibling_hash, dense_path.leaf_sibling_hash);
+            assert_eq!(path.auth_path, dense_path.auth_path);
+            assert_eq!(path.leaf_index, dense_path.leaf_index);
+        }
+        assert!(tree.generate_proof(8).is_err());
+        assert!(tree.update(8, empty_leaf.as_slice()).is_err());
+    }
+
+    #[test]
+    fn membership_and_non_membership_at_full_depth() {
+        let params = poseidon_parameters();
+        let empty_leaf: &[ConstraintF] = &[ConstraintF::from(0u8)];
+        let leaf = |i: usize| vec![ConstraintF::from(i as u64 + 1)];
+        let mut tree = SparseMerkleTree::<PoseidonMerkleTreeParams>::new(
+            &params,
+            &params,
+            SparseMerkleTree::<PoseidonMerkleTreeParams>::MAX_DEPTH,
+            empty_leaf,
+        )
+        .unwrap();
+        let empty_root = tree.root();
+        let keys = [0, 1, 1 << 40, u64::MAX - 1, u64::MAX];
+        for (i, &key) in keys.iter().enumerate() {
+            tree.update(key, &leaf(i)).unwrap();
+        }
+        assert_eq!(tree.len(), keys.len());
+        // Only the paths of the set leaves are stored.
+        assert!(tree.nodes.len() <= keys.len() * tree.depth());
+
+        let root = tree.root();
+        for (i, &key) in keys.iter().enumerate() {
+            let path = tree.generate_proof(key).unwrap();
+            assert!(
+                verify_membership(&params, &params, &root, key, leaf(i).as_slice(), &path).unwrap()
+            );
+            assert!(
+                !verify_non_membership(&params, &params, &root, key, empty_leaf, &path).unwrap()
+            );
+            // The path of a key proves nothing about its neighbour.
+            assert!(!verify_membership(
+                &params,
+                &params,
+                &root,
+                key ^ 1,
+                leaf(i).as_slice(),
+                &path
+            )
+            .unwrap());
+        }
+
+        for key in [2, 1 << 39, u64::MAX - 2] {
+            assert!(!tree.contains(key));
+            let path = tree.generate_proof(key).unwrap();
+            assert!(
+                verify_non_membership(&params, &params, &root, key, empty_leaf, &path).unwrap()
+            );
+            assert!(
+                !verify_membership(&params, &params, &root, key, leaf(0).as_slice(), &path)
+                    .unwrap()
+            );
+        }
+
+        for &key in &keys {
+            tree.remove(key).unwrap();
+        }
+        assert!(tree.is_empty());
+        assert!(tree.nodes.is_empty());
+        assert_eq!(tree.root(), empty_root);
+    }
+
+    #[test]
+    fn keys_above_the_depth_are_rejected() {
+        let params = poseidon_parameters();
+        let leaf: &[ConstraintF] = &[ConstraintF::from(1u8)];
+        let mut tree = SparseMerkleTree::<PoseidonMerkleTreeParams>::new(
+            &params,
+            &params,
+            32,
+            &[ConstraintF::from(0u8)],
+        )
+        .unwrap();
+        tree.update(3, leaf).unwrap();
+        let root = tree.root();
+        // `Path::verify` ignores the bits of the index above the depth.
+        let mut aliased = tree.generate_proof(3).unwrap();
+        aliased.leaf_index += 1 << 32;
+        assert!(aliased.verify(&params, &params, &root, leaf).unwrap());
+        assert!(
+            !verify_membership(&params, &params, &root, 3 + (1 << 32), leaf, &aliased).unwrap()
+        );
+        assert!(tree.generate_proof(1 << 32).is_err());
+    }
+}
@@ -0,0 +1,352 @@
+use super::{Liability, SumNode, SumPath};
+use crate::{
+    basic_merkle_tree::common::ConstraintF,
+    groth16::PublicInputs,
+    rollup::{account::AccountPublicKeyVar, ledger::AmountVar},
+};
+use ark_crypto_primitives::{
+    crh::{
+        poseidon::constraints::{CRHGadget, CRHParametersVar},
+        CRHSchemeGadget,
+    },
+    sponge::poseidon::PoseidonConfig,
+};
+use ark_r1cs_std::{bits::uint64::UInt64, fields::fp::FpVar, prelude::*};
+use ark_relations::{
+    ns,
+    r1cs::{ConstraintSynthesizer, ConstraintSystemRef, Namespace, SynthesisError},
+};
+use std::borrow::Borrow;
+
+/// The R1CS equivalent of `SumHash`.
+pub type SumHashG = CRHGadget<ConstraintF>;
+
+/// The R1CS equivalent of `SumNode`.
+#[derive(Clone)]
+pub struct SumNodeVar {
+    pub hash: FpVar<ConstraintF>,
+    pub sum: AmountVar,
+}
+
+impl SumNodeVar {
+    /// Compute the parent of `left` and `right`, enforcing that the sum of their
+    /// amounts does not overflow.
+    #[tracing::instrument(target = "r1cs", skip(params, left, right))]
+    pub fn parent(
+        params: &CRHParametersVar<ConstraintF>,
+        left: &Self,
+        right: &Self,
+    ) -> Result<Self, SynthesisError> {
+        let sum = left.sum.checked_add(&right.sum)?;
+        let hash = SumHashG::evaluate(
+            params,
+            &[
+                left.hash.clone(),
+                left.sum.to_fp_var()?,
+                right.hash.clone(),
+                right.sum.to_fp_var()?,
+            ],
+        )?;
+        Ok(Self { hash, sum })
+    }
+
+    /// Return `true_value` if `cond` is true, and `false_value` otherwise.
+    fn select(
+        cond: &Boolean<ConstraintF>,
+        true_value: &Self,
+        false_value: &Self,
+    ) -> Result<Self, SynthesisError> {
+        Ok(Self {
+            hash: cond.select(&true_value.hash, &false_value.hash)?,
+            sum: AmountVar(cond.select(&true_value.sum.0, &false_value.sum.0)?),
+        })
+    }
+}
+
+impl AllocVar<SumNode, ConstraintF> for SumNodeVar {
+    #[tracing::instrument(target = "r1cs", skip(cs, f, mode))]
+    fn new_variable<T: Borrow<SumNode>>(
+        cs: impl Into<Namespace<ConstraintF>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let cs = cs.into().cs();
+        let node = f().map(|node| *node.borrow());
+        let hash = FpVar::new_variable(cs.clone(), || node.map(|node| node.hash), mode)?;
+        let sum = AmountVar::new_variable(cs, || node.map(|node| node.sum), mode)?;
+        Ok(Self { hash, sum })
+    }
+}
+
+/// The R1CS equivalent of `Liability`.
+#[derive(Clone)]
+pub struct LiabilityVar {
+    pub public_key: AccountPublicKeyVar,
+    pub amount: AmountVar,
+}
+
+impl LiabilityVar {
+    /// Return the leaf node, whose hash commits to the public key and the amount.
+    #[tracing::instrument(target = "r1cs", skip(self, params))]
+    pub fn node(
+        &self,
+        params: &CRHParametersVar<ConstraintF>,
+    ) -> Result<SumNodeVar, SynthesisError> {
+        let hash = SumHashG::evaluate(
+            params,
+            &[
+                self.public_key.x.clone(),
+                self.public_key.y.clone(),
+                self.amount.to_fp_var()?,
+            ],
+        )?;
+        Ok(SumNodeVar {
+            hash,
+            sum: self.amount.clone(),
+        })
+    }
+}
+
+/// The R1CS equivalent of `SumPath`.
+pub struct SumPathVar {
+    /// The little-endian bits of the index of the leaf.
+    pub position: Vec<Boolean<ConstraintF>>,
+    pub siblings: Vec<SumNodeVar>,
+}
+
+impl SumPathVar {
+    /// Compute the root from `leaf` and the siblings, enforcing that no sum overflows.
+    #[tracing::instrument(target = "r1cs", skip(self, params, leaf))]
+    pub fn compute_root(
+        &self,
+        params: &CRHParametersVar<ConstraintF>,
+        leaf: &LiabilityVar,
+    ) -> Result<SumNodeVar, SynthesisError> {
+        self.position.iter().zip(&self.siblings).try_fold(
+            leaf.node(params)?,
+            |node, (is_right, sibling)| {
+                let left = SumNodeVar::select(is_right, sibling, &node)?;
+                let right = SumNodeVar::select(is_right, &node, sibling)?;
+                SumNodeVar::parent(params, &left, &right)
+            },
+        )
+    }
+}
+
+impl AllocVar<SumPath, ConstraintF> for SumPathVar {
+    #[tracing::instrument(target = "r1cs", skip(cs, f, mode))]
+    fn new_variable<T: Borrow<SumPath>>(
+        cs: impl Into<Namespace<ConstraintF>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let cs = cs.into().cs();
+        let path = f()?;
+        let path = path.borrow();
+        let position = (0..path.siblings.len())
+            .map(|level| {
+                Boolean::new_variable(cs.clone(), || Ok((path.leaf_index >> level) & 1 == 1), mode)
+            })
+            .collect::<Result<_, _>>()?;
+        let siblings = Vec::new_variable(cs, || Ok(path.siblings.clone()), mode)?;
+        Ok(Self { position, siblings })
+    }
+}
+
+/// Proves that the liability of an exchange to an account is included in a Merkle
+/// sum tree, whose root sum is the total liability that the exchange claims in one
+/// asset, without revealing the other liabilities. Every sum on the path is enforced
+/// not to overflow, so that no other leaf can offset the liability.
+///
+/// The public inputs are the root and the liability, whose amount is allocated as a
+/// single field element rather than as the bits of a `UInt64`.
+#[derive(Clone)]
+pub struct ProofOfReserves {
+    pub params: PoseidonConfig<ConstraintF>,
+
+    // public input
+    pub root: SumNode,
+    pub liability: Liability,
+
+    // private witness
+    pub path: Option<SumPath>,
+}
+
+impl ProofOfReserves {
+    /// Return the public inputs of the circuit, in the order in which they are
+    /// allocated: the hash and the sum of the root, then the coordinates of the public
+    /// key and the amount of the liability.
+    pub fn public_inputs(&self) -> PublicInputs {
+        let mut inputs = PublicInputs::new();
+        inputs
+            .push(&self.root.hash)
+            .push(&ConstraintF::from(self.root.sum.0))
+            .push(&self.liability.public_key)
+            .push(&ConstraintF::from(self.liability.amount.0));
+        inputs
+    }
+}
+
+/// Allocate `amount` as a public field element, and return it as a range-checked
+/// `AmountVar`.
+fn new_amount_input(
+    cs: ConstraintSystemRef<ConstraintF>,
+    amount: u64,
+) -> Result<AmountVar, SynthesisError> {
+    let input = FpVar::new_input(cs.clone(), || Ok(ConstraintF::from(amount)))?;
+    let amount = AmountVar(UInt64::new_witness(cs, || Ok(amount))?);
+    amount.to_fp_var()?.enforce_equal(&input)?;
+    Ok(amount)
+}
+
+impl ConstraintSynthesizer<ConstraintF> for ProofOfReserves {
+    #[tracing::instrument(target = "r1cs", skip(self, cs))]
+    fn generate_constraints(
+        self,
+        cs: ConstraintSystemRef<ConstraintF>,
+    ) -> Result<(), SynthesisError> {
+        // allocate public input
+        let root_hash = FpVar::new_input(ns!(cs, "root_hash"), || Ok(self.root.hash))?;
+        let root_sum = new_amount_input(ns!(cs, "root_sum").cs(), self.root.sum.0)?;
+        let liability = LiabilityVar {
+            public_key: AccountPublicKeyVar::new_input(ns!(cs, "public_key"), || {
+                Ok(self.liability.public_key)
+            })?,
+            amount: new_amount_input(ns!(cs, "amount").cs(), self.liability.amount.0)?,
+        };
+
+        // allocate private witness
+        let path = SumPathVar::new_witness(ns!(cs, "path"), || {
+            self.path.as_ref().ok_or(SynthesisError::AssignmentMissing)
+        })?;
+
+        let params = CRHParametersVar::new_constant(ns!(cs, "params"), &self.params)?;
+        let root = path.compute_root(&params, &liability)?;
+        root.hash.enforce_equal(&root_hash)?;
+        root.sum.0.enforce_equal(&root_sum.0)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProofOfReserves;
+    use crate::{
+        basic_merkle_tree::{common::ConstraintF, poseidon::poseidon_parameters},
+        groth16::{prove, setup, verify},
+        payment::{
+            account::AccountInformation,
+            ledger::{Amount, AssetId, Parameters, State, NUM_ASSETS},
+        },
+        sum_merkle_tree::{Liability, SumHash, SumMerkleTree, SumNode, SumPath},
+    };
+    use ark_crypto_primitives::crh::CRHScheme;
+    use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystem};
+    use ark_std::rand::{rngs::StdRng, SeedableRng};
+
+    fn is_satisfied(circuit: ProofOfReserves) -> bool {
+        let cs = ConstraintSystem::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+        cs.is_satisfied().unwrap()
+    }
+
+    #[test]
+    fn proof_of_reserves() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let pp = Parameters::sample(&mut rng);
+        let mut state = State::new(8, &pp);
+        let mut accounts = Vec::new();
+        for balance in [10, 20, 30] {
+            let (id, _, _) = state.sample_keys_and_register(&pp, &mut rng).unwrap();
+            state
+                .update_balance(id, AssetId(0), Amount(balance))
+                .unwrap();
+            accounts.push(state.id_to_account_info[&id]);
+        }
+        let params = poseidon_parameters();
+        let tree = SumMerkleTree::from_accounts(&params, &accounts, AssetId(0)).unwrap();
+        let circuit = ProofOfReserves {
+            params: params.clone(),
+            root: tree.root(),
+            liability: Liability::of(&accounts[1], AssetId(0)).unwrap(),
+            path: Some(tree.generate_proof(1).unwrap()),
+        };
+        // The root hash and sum, the public key, and the amount.
+        assert_eq!(circuit.public_inputs().len(), 1 + 1 + 2 + 1);
+
+        let (pk, vk) = setup(circuit.clone(), &mut rng).unwrap();
+        let proof = prove(&pk, circuit.clone(), &mut rng).unwrap();
+        assert!(verify(&vk, &circuit.public_inputs(), &proof).unwrap());
+        // The proof does not verify for an understated total or another amount.
+        let understated = ProofOfReserves {
+            root: SumNode {
+                sum: Amount(59),
+                ..tree.root()
+            },
+            ..circuit.clone()
+        };
+        assert!(!verify(&vk, &understated.public_inputs(), &proof).unwrap());
+        assert!(!is_satisfied(understated));
+        let other_amount = ProofOfReserves {
+            liability: Liability {
+                amount: Amount(19),
+                ..circuit.liability
+            },
+            ..circuit.clone()
+        };
+        assert!(!verify(&vk, &other_amount.public_inputs(), &proof).unwrap());
+        assert!(!is_satisfied(other_amount));
+    }
+
+    #[test]
+    fn overflowing_sums_are_rejected() {
+        // An exchange owes u64::MAX to a sock puppet account, so that the total
+        // wraps around to a small amount.
+        let mut rng = ark_std::test_rng();
+        let pp = Parameters::sample(&mut rng);
+        let mut state = State::new(4, &pp);
+        let (alice_id, _, _) = state.sample_keys_and_register(&pp, &mut rng).unwrap();
+        state
+            .update_balance(alice_id, AssetId(0), Amount(10))
+            .unwrap();
+        let alice = state.id_to_account_info[&alice_id];
+        let puppet = AccountInformation {
+            balances: [Amount(u64::MAX); NUM_ASSETS],
+            ..alice
+        };
+        let params = poseidon_parameters();
+        assert!(SumMerkleTree::from_accounts(&params, [&alice, &puppet], AssetId(0)).is_err());
+
+        let leaf = Liability::of(&alice, AssetId(0)).unwrap();
+        let sibling = Liability::of(&puppet, AssetId(0))
+            .unwrap()
+            .node(&params)
+            .unwrap();
+        let path = SumPath {
+            leaf_index: 0,
+            siblings: vec![sibling],
+        };
+        // The root commits to the wrapped total, 10 + u64::MAX mod 2^64.
+        let leaf_node = leaf.node(&params).unwrap();
+        let hash = SumHash::evaluate(
+            &params,
+            [
+                leaf_node.hash,
+                ConstraintF::from(10u64),
+                sibling.hash,
+                ConstraintF::from(u64::MAX),
+            ],
+        )
+        .unwrap();
+        let root = SumNode {
+            hash,
+            sum: Amount(9),
+        };
+        assert!(!is_satisfied(ProofOfReserves {
+            params,
+            root,
+            liability: leaf,
+            path: Some(path),
+        }));
+    }
+}
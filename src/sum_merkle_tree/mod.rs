@@ -0,0 +1,283 @@
+use crate::{
+    basic_merkle_tree::common::ConstraintF,
+    payment::{
+        account::{AccountInformation, AccountPublicKey},
+        ledger::{Amount, AssetId},
+    },
+};
+use ark_crypto_primitives::{
+    crh::{poseidon, CRHScheme},
+    sponge::poseidon::PoseidonConfig,
+    Error,
+};
+use ark_ff::Zero;
+
+pub mod constraints;
+
+/// The hash of the leaves and of the internal nodes of a `SumMerkleTree`.
+pub type SumHash = poseidon::CRH<ConstraintF>;
+
+/// A node of a Merkle sum tree: a hash, and the sum of the amounts of the leaves
+/// below the node, to which the hash commits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SumNode {
+    pub hash: ConstraintF,
+    pub sum: Amount,
+}
+
+impl SumNode {
+    /// The node of the leaves that pad a tree to a power of two. Its hash has no known
+    /// preimage, and its amount is 0.
+    pub fn empty() -> Self {
+        Self {
+            hash: ConstraintF::zero(),
+            sum: Amount(0),
+        }
+    }
+
+    /// Compute the parent of `left` and `right`, which commits to both of them. Returns
+    /// an error if the sum of their amounts overflows.
+    pub fn parent(
+        params: &PoseidonConfig<ConstraintF>,
+        left: &Self,
+        right: &Self,
+    ) -> Result<Self, Error> {
+        let sum = left
+            .sum
+            .checked_add(right.sum)
+            .ok_or("the sum of the amounts overflows")?;
+        let hash = SumHash::evaluate(
+            params,
+            [
+                left.hash,
+                ConstraintF::from(left.sum.0),
+                right.hash,
+                ConstraintF::from(right.sum.0),
+            ],
+        )?;
+        Ok(Self { hash, sum })
+    }
+}
+
+/// The leaf of an account in a sum tree of the liabilities of an exchange in one
+/// asset: the public key of the account, and the amount that the exchange owes it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Liability {
+    pub public_key: AccountPublicKey,
+    pub amount: Amount,
+}
+
+impl Liability {
+    /// Return the liability to the account `account_info` in asset `asset_id`: its
+    /// balance plus the amount it locked in pending escrows. Returns `None` if the
+    /// asset identifier is invalid or the amount overflows.
+    pub fn of(account_info: &AccountInformation, asset_id: AssetId) -> Option<Self> {
+        let amount = account_info
+            .balance(asset_id)?
+            .checked_add(account_info.locked(asset_id)?)?;
+        Some(Self {
+            public_key: account_info.public_key,
+            amount,
+        })
+    }
+
+    /// Return the leaf node, whose hash commits to the public key and the amount.
+    pub fn node(&self, params: &PoseidonConfig<ConstraintF>) -> Result<SumNode, Error> {
+        let hash = SumHash::evaluate(
+            params,
+            [
+                self.public_key.x,
+                self.public_key.y,
+                ConstraintF::from(self.amount.0),
+            ],
+        )?;
+        Ok(SumNode {
+            hash,
+            sum: self.amount,
+        })
+    }
+}
+
+/// A Merkle tree whose nodes commit to the sum of the amounts of the leaves below
+/// them, so that its root commits to the total amount of the leaves. A proof of a
+/// leaf shows that its amount is part of that total, and since no sum may overflow,
+/// that no other leaf offsets it.
+pub struct SumMerkleTree {
+    /// `levels[level][index]` is the node at `index` within `level`, where the leaves
+    /// are at level 0 and the root alone is at the last level.
+    levels: Vec<Vec<SumNode>>,
+}
+
+impl SumMerkleTree {
+    /// Build the tree of `leaves`, padded with empty leaves to a power of two of at
+    /// least 2. Returns an error if a sum overflows.
+    pub fn new(params: &PoseidonConfig<ConstraintF>, leaves: &[Liability]) -> Result<Self, Error> {
+        let num_leaves = leaves.len().next_power_of_two().max(2);
+        let mut level = leaves
+            .iter()
+            .map(|leaf| leaf.node(params))
+            .collect::<Result<Vec<_>, _>>()?;
+        level.resize(num_leaves, SumNode::empty());
+        let mut levels = vec![level];
+        while levels[levels.len() - 1].len() > 1 {
+            let parents = levels[levels.len() - 1]
+                .chunks(2)
+                .map(|pair| SumNode::parent(params, &pair[0], &pair[1]))
+                .collect::<Result<_, _>>()?;
+            levels.push(parents);
+        }
+        Ok(Self { levels })
+    }
+
+    /// Build the tree of the liabilities in asset `asset_id` to `accounts`, in order.
+    /// Returns an error if the asset identifier is invalid or a sum overflows.
+    pub fn from_accounts<'a>(
+        params: &PoseidonConfig<ConstraintF>,
+        accounts: impl IntoIterator<Item = &'a AccountInformation>,
+        asset_id: AssetId,
+    ) -> Result<Self, Error> {
+        let leaves = accounts
+            .into_iter()
+            .map(|account_info| Liability::of(account_info, asset_id))
+            .collect::<Option<Vec<_>>>()
+            .ok_or("invalid asset or overflowing liability")?;
+        Self::new(params, &leaves)
+    }
+
+    /// Return the number of levels above the leaves.
+    pub fn depth(&self) -> usize {
+        self.levels.len() - 1
+    }
+
+    /// Return the root, whose sum is the total amount of the leaves.
+    pub fn root(&self) -> SumNode {
+        self.levels[self.depth()][0]
+    }
+
+    /// Return the path from the leaf at `index` to the root.
+    pub fn generate_proof(&self, index: usize) -> Result<SumPath, Error> {
+        if index >= self.levels[0].len() {
+            return Err(format!("leaf {index} is out of bounds").into());
+        }
+        let siblings = self.levels[..self.depth()]
+            .iter()
+            .enumerate()
+            .map(|(level, nodes)| nodes[(index >> level) ^ 1])
+            .collect();
+        Ok(SumPath {
+            leaf_index: index,
+            siblings,
+        })
+    }
+}
+
+/// The path from a leaf of a `SumMerkleTree` to its root.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SumPath {
+    pub leaf_index: usize,
+    /// The siblings of the nodes on the path, from the leaf up.
+    pub siblings: Vec<SumNode>,
+}
+
+impl SumPath {
+    /// Compute the root from `leaf` and the siblings. Returns an error if a sum
+    /// overflows.
+    pub fn compute_root(
+        &self,
+        params: &PoseidonConfig<ConstraintF>,
+        leaf: &Liability,
+    ) -> Result<SumNode, Error> {
+        self.siblings
+            .iter()
+            .enumerate()
+            .try_fold(leaf.node(params)?, |node, (level, sibling)| {
+                if (self.leaf_index >> level) & 1 == 0 {
+                    SumNode::parent(params, &node, sibling)
+                } else {
+                    SumNode::parent(params, sibling, &node)
+                }
+            })
+    }
+
+    /// Check that the path proves that `leaf` is in the tree with root `root`. A path
+    /// whose sums overflow is rejected.
+    pub fn verify(
+        &self,
+        params: &PoseidonConfig<ConstraintF>,
+        root: &SumNode,
+        leaf: &Liability,
+    ) -> Result<bool, Error> {
+        if self
+            .leaf_index
+            .checked_shr(self.siblings.len() as u32)
+            .unwrap_or(0)
+            != 0
+        {
+            return Ok(false);
+        }
+        Ok(self.compute_root(params, leaf).ok().as_ref() == Some(root))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Liability, SumMerkleTree, SumNode, SumPath};
+    use crate::{
+        basic_merkle_tree::poseidon::poseidon_parameters,
+        payment::ledger::{Amount, AssetId, Parameters, State},
+    };
+
+    #[test]
+    fn sum_tree_proofs() {
+        let mut rng = ark_std::test_rng();
+        let pp = Parameters::sample(&mut rng);
+        let mut state = State::new(8, &pp);
+        for balance in [3, 5, 7] {
+            let (id, _, _) = state.sample_keys_and_register(&pp, &mut rng).unwrap();
+            state
+                .update_balance(id, AssetId(0), Amount(balance))
+                .unwrap();
+        }
+        let params = poseidon_parameters();
+        let mut accounts: Vec<_> = state.id_to_account_info.iter().collect();
+        accounts.sort_by_key(|(id, _)| **id);
+        let accounts: Vec<_> = accounts.into_iter().map(|(_, info)| info).collect();
+        let tree =
+            SumMerkleTree::from_accounts(&params, accounts.iter().copied(), AssetId(0)).unwrap();
+        assert_eq!(tree.depth(), 2);
+        assert_eq!(tree.root().sum, Amount(15));
+
+        for (index, account_info) in accounts.iter().enumerate() {
+            let leaf = Liability::of(account_info, AssetId(0)).unwrap();
+            let proof = tree.generate_proof(index).unwrap();
+            assert!(proof.verify(&params, &tree.root(), &leaf).unwrap());
+            // A smaller liability is not included.
+            let smaller = Liability {
+                amount: Amount(leaf.amount.0 - 1),
+                ..leaf
+            };
+            assert!(!proof.verify(&params, &tree.root(), &smaller).unwrap());
+        }
+        // A root that understates the total is rejected.
+        let leaf = Liability::of(accounts[0], AssetId(0)).unwrap();
+        let understated = SumNode {
+            sum: Amount(14),
+            ..tree.root()
+        };
+        let proof = tree.generate_proof(0).unwrap();
+        assert!(!proof.verify(&params, &understated, &leaf).unwrap());
+        // A sibling that overflows cannot offset the other liabilities.
+        let overflowing = SumPath {
+            siblings: vec![
+                SumNode {
+                    sum: Amount(u64::MAX),
+                    ..proof.siblings[0]
+                },
+                proof.siblings[1],
+            ],
+            ..proof
+        };
+        assert!(overflowing.compute_root(&params, &leaf).is_err());
+        assert!(!overflowing.verify(&params, &tree.root(), &leaf).unwrap());
+    }
+}